@@ -0,0 +1,217 @@
+//! Data-parallel argument for k instances of the same circuit.
+//!
+//! Witnesses of all instances form one multilinear extension Z(j, y), where the instance index j gives
+//! `log(k)` extra variables. The first sumcheck runs over (x, j), so the proof has one commitment, one
+//! opening, and `log(m) + log(k) + log(n)` sumcheck rounds. Instances are padded to a power of two with
+//! all-zero instances, which satisfy every constraint.
+
+use linear_sumcheck::data_structures::ml_extension::{ArithmeticCombination, MLExtension};
+use linear_sumcheck::data_structures::MLExtensionArray;
+
+use crate::ahp::indexer::{IndexPK, IndexVK, VerifierIndex};
+use crate::ahp::prover::{ProverFifthMessage, ProverFirstMessage, ProverThirdMessage};
+use crate::ahp::setup::{PublicParameter, SetupIdentifier};
+use crate::ahp::MLProofForR1CS;
+use crate::commitment::verify::OpeningVerifierKey;
+use crate::commitment::MLPolyCommit;
+use crate::data_structures::eq::eq_table;
+use crate::data_structures::proof::BatchProof;
+use crate::data_structures::r1cs_reader::MatrixExtension;
+use crate::data_structures::transcript::{Transcript, TranscriptHash};
+use crate::error::{invalid_arg, SResult};
+use crate::{sumcheck, zerocheck};
+use ark_ec::PairingEngine;
+use ark_ff::Zero;
+
+/// label of the batch protocol in the transcript
+const BATCH_PROTOCOL_LABEL: &[u8] = b"r1cs-spartan-raw/batch-argument-for-r1cs";
+
+impl<E: PairingEngine> MLProofForR1CS<E> {
+    /// number of variables the public parameter should support to prove `k` instances of `pk` at once
+    pub fn num_variables_for_batch(pk: &IndexPK<E::Fr>, k: usize) -> usize {
+        pk.log_n - 1 + ark_std::log2(k) as usize
+    }
+
+    /// Start the transcript of a batch proof. It binds the setup, the index and all public inputs.
+    pub fn init_batch_transcript<K: VerifierIndex<E::Fr>, H: TranscriptHash>(
+        setup: &SetupIdentifier,
+        index: &K,
+        vs: &[Vec<E::Fr>],
+    ) -> SResult<Transcript<H>> {
+        let mut transcript = Transcript::new(BATCH_PROTOCOL_LABEL);
+        transcript.append_message(b"setup", setup)?;
+        index.append_to_transcript(&mut transcript)?;
+        transcript.append_message(b"number of public inputs", &vs.len())?;
+        for v in vs.iter() {
+            transcript.append_scalars(b"public input", v)?;
+        }
+        Ok(transcript)
+    }
+
+    /// Prove that `(vs[j], ws[j])` satisfies the circuit for every instance j.
+    pub fn prove_batch<H: TranscriptHash>(
+        pk: &IndexPK<E::Fr>,
+        vs: Vec<Vec<E::Fr>>,
+        ws: Vec<Vec<E::Fr>>,
+        pp: &PublicParameter<E>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<BatchProof<E>> {
+        if vs.is_empty() || vs.len() != ws.len() {
+            return Err(invalid_arg("should have the same positive number of public inputs and witnesses"));
+        }
+        let log_k = ark_std::log2(vs.len()) as usize;
+        let (log_m, log_n) = (pk.log_m, pk.log_n);
+        let half = 1 << (log_n - 1);
+
+        // z_j = v_j || w_j, and W(y, j) = w_j(y) where y are the low variables
+        let mut zs = Vec::with_capacity(1 << log_k);
+        let mut w_table = Vec::with_capacity(half << log_k);
+        for (v, w) in vs.into_iter().zip(ws.into_iter()) {
+            let (v, w) = Self::pad_instance(pk, v, w)?;
+            w_table.extend_from_slice(&w);
+            zs.push(v.into_iter().chain(w.into_iter()).collect::<Vec<_>>());
+        }
+        w_table.resize(half << log_k, E::Fr::zero());
+
+        let timer = start_timer!(|| "Commit to witnesses");
+        let w_poly = MLExtensionArray::from_vec(w_table)?;
+        let prover_first_message = ProverFirstMessage {
+            commitment: MLPolyCommit::commit(pp, w_poly.clone())?,
+        };
+        end_timer!(timer);
+        transcript.append_message(b"commitment to w", &prover_first_message)?;
+        let tor: Vec<E::Fr> = transcript.challenge_scalars(b"tor", log_m + log_k)?;
+
+        // first sumcheck over (x, j): eq(tor, (x, j)) * sum_l c_l prod_{i in S_l} M_i z(x, j)
+        let timer = start_timer!(|| "Prove first sumcheck");
+        let stack = |matrix: &MatrixExtension<E::Fr>| -> SResult<MLExtensionArray<E::Fr>> {
+            let mut table = Vec::with_capacity(1 << (log_m + log_k));
+            for z in zs.iter() {
+                table.extend(matrix.mul_vector(z)?);
+            }
+            table.resize(1 << (log_m + log_k), E::Fr::zero());
+            Ok(MLExtensionArray::from_vec(table)?)
+        };
+        let mz = pk.matrices.iter().map(stack).collect::<SResult<Vec<_>>>()?;
+        let poly = zerocheck::masked_combination(&tor, &mz, &pk.products()?)?;
+        let (first_sumcheck, r_xj) = sumcheck::prove(poly, transcript)?;
+        end_timer!(timer);
+
+        let prover_third_message = ProverThirdMessage {
+            evaluations: mz
+                .iter()
+                .map(|table| table.eval_at(&r_xj))
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+        let coefficients = Self::sample_verify_third_round(&prover_third_message, transcript)?.coefficients;
+        let (r_x, r_j) = r_xj.split_at(log_m);
+
+        // second sumcheck on M(r_x, y) * Z(r_j, y), where M = sum_i r_i * M_i
+        let timer = start_timer!(|| "Prove second sumcheck");
+        let eq_rj = eq_table(r_j);
+        let mut z_rj = vec![E::Fr::zero(); 1 << log_n];
+        for (z, eq) in zs.iter().zip(eq_rj.iter()) {
+            for (acc, zy) in z_rj.iter_mut().zip(z.iter()) {
+                *acc += *zy * eq;
+            }
+        }
+        let combination: Vec<_> = coefficients.into_iter().zip(pk.matrices.iter()).collect();
+        let m_rx = MatrixExtension::eval_combination_on_x(&combination, &eq_table(r_x))?;
+        let mut poly = ArithmeticCombination::new(log_n);
+        poly.add_product(vec![m_rx, MLExtensionArray::from_vec(z_rj)?].into_iter())?;
+        let (second_sumcheck, r_y) = sumcheck::prove(poly, transcript)?;
+        end_timer!(timer);
+
+        // open W at (r_y[..log_n - 1], r_j)
+        let timer = start_timer!(|| "Open witnesses");
+        let point: Vec<_> = r_y[..log_n - 1].iter().chain(r_j.iter()).cloned().collect();
+        let (w_ry, proof_for_w_ry, _) = MLPolyCommit::open(pp, w_poly, &point)?;
+        end_timer!(timer);
+
+        Ok(BatchProof {
+            prover_first_message,
+            first_sumcheck,
+            prover_third_message,
+            second_sumcheck,
+            prover_fifth_message: ProverFifthMessage {
+                w_ry,
+                proof_for_w_ry,
+            },
+        })
+    }
+
+    /// Verify a batch proof against the public inputs `vs` of all instances.
+    ///
+    /// `vp` can be the public `VerifierParameter` or a `DesignatedVerifierParameter`.
+    pub fn verify_batch<O: OpeningVerifierKey<E>, H: TranscriptHash>(
+        vk: &IndexVK<E::Fr>,
+        vs: Vec<Vec<E::Fr>>,
+        proof: BatchProof<E>,
+        vp: &O,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<bool> {
+        if vs.is_empty() {
+            return Err(invalid_arg("should have at least one public input"));
+        }
+        let log_k = ark_std::log2(vs.len()) as usize;
+        let (log_m, log_n) = (vk.log_m, vk.log_n);
+        let vs = vs
+            .into_iter()
+            .map(|v| Self::pad_public_input(vk, v))
+            .collect::<SResult<Vec<_>>>()?;
+
+        let commitment = &proof.prover_first_message.commitment;
+        if commitment.nv != log_n - 1 + log_k {
+            return Err(invalid_arg("commitment has wrong number of variables"));
+        }
+        transcript.append_message(b"commitment to w", &proof.prover_first_message)?;
+        let tor: Vec<E::Fr> = transcript.challenge_scalars(b"tor", log_m + log_k)?;
+
+        // first sumcheck
+        let (r_xj, expected) =
+            sumcheck::verify(proof.first_sumcheck, log_m + log_k, vk.degree() + 1, E::Fr::zero(), transcript)?;
+        let evaluations = &proof.prover_third_message.evaluations;
+        if evaluations.len() != vk.matrices.len() {
+            return Err(invalid_arg("should have one evaluation for each matrix"));
+        }
+        if zerocheck::masked_evaluation(&tor, &r_xj, evaluations, &vk.products()?)? != expected {
+            return Err(crate::Error::WrongWitness(Some(
+                "first sumcheck has wrong subclaim".into(),
+            )));
+        }
+        let coefficients = Self::sample_verify_third_round(&proof.prover_third_message, transcript)?.coefficients;
+        let (r_x, r_j) = r_xj.split_at(log_m);
+
+        // second sumcheck
+        let claimed_sum = coefficients
+            .iter()
+            .zip(evaluations.iter())
+            .fold(E::Fr::zero(), |acc, (r_i, e)| acc + &(*r_i * e));
+        let (r_y, expected) = sumcheck::verify(proof.second_sumcheck, log_n, 2, claimed_sum, transcript)?;
+
+        // the public part of Z(r_j, r_y) is the public input combined over instances
+        let eq_rj = eq_table(r_j);
+        let mut v_rj = vec![E::Fr::zero(); vs[0].len()];
+        for (v, eq) in vs.iter().zip(eq_rj.iter()) {
+            for (acc, vi) in v_rj.iter_mut().zip(v.iter()) {
+                *acc += *vi * eq;
+            }
+        }
+        let w_ry = proof.prover_fifth_message.w_ry;
+        let z_ry = Self::z_eval(v_rj, w_ry, &r_y)?;
+
+        let m_rx_ry = Self::matrix_evaluation(vk, r_x, &r_y, &coefficients)?;
+        if m_rx_ry * &z_ry != expected {
+            return Err(crate::Error::WrongWitness(Some(
+                "Cannot verify the matrices".into(),
+            )));
+        }
+
+        // verify W(r_y[..log_n - 1], r_j)
+        let point: Vec<_> = r_y[..log_n - 1].iter().chain(r_j.iter()).cloned().collect();
+        if !vp.verify_opening(commitment, &point, w_ry, proof.prover_fifth_message.proof_for_w_ry)? {
+            return Err(crate::Error::WrongWitness(Some("Cannot verify w_ry".into())));
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,187 @@
+//! Commit-and-prove: link a proof to commitments of segments of w made before proving.
+//!
+//! A segment of `2^k` elements starting at an offset that is a multiple of `2^k` is a subcube of w: fixing the
+//! high variables of w to the bits of `offset / 2^k` gives the extension of the segment. The prover shows that
+//! the committed segment and w agree at a random point of the low variables, which by Schwartz-Zippel implies
+//! that they are the same polynomial.
+
+use linear_sumcheck::data_structures::MLExtensionArray;
+
+use crate::ahp::indexer::{IndexPK, IndexVK};
+use crate::ahp::setup::{PublicParameter, VerifierParameter};
+use crate::ahp::MLProofForR1CS;
+use crate::commitment::commit::Commitment;
+use crate::commitment::open::Proof;
+use crate::commitment::verify::OpeningClaim;
+use crate::commitment::MLPolyCommit;
+use crate::data_structures::proof::LinkedProof;
+use crate::data_structures::transcript::{Transcript, TranscriptHash};
+use crate::error::{invalid_arg, SResult};
+use ark_ec::PairingEngine;
+use ark_ff::{One, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
+
+/// Commitment to `w[offset..offset + 2^commitment.nv]`, made with the same public parameter as the proof.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct CommittedSegment<E: PairingEngine> {
+    pub offset: usize,
+    pub commitment: Commitment<E>,
+}
+
+/// evaluation of a committed segment at a random point, opened against both the segment and w
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct SegmentOpening<E: PairingEngine> {
+    pub eval: E::Fr,
+    pub proof_for_segment: Proof<E>,
+    pub proof_for_w: Proof<E>,
+}
+
+impl<E: PairingEngine> CommittedSegment<E> {
+    pub fn new(offset: usize, commitment: Commitment<E>) -> Self {
+        Self { offset, commitment }
+    }
+
+    /// number of elements of w covered by the segment
+    pub fn size(&self) -> usize {
+        1 << self.commitment.nv
+    }
+
+    /// values of the high variables of w selecting the segment, where w has `log_half` variables
+    fn selector(&self, log_half: usize) -> SResult<Vec<E::Fr>> {
+        let nv = self.commitment.nv;
+        if nv == 0 || nv > log_half {
+            return Err(invalid_arg("segment has wrong number of variables"));
+        }
+        if self.offset % self.size() != 0 || self.offset + self.size() > (1 << log_half) {
+            return Err(invalid_arg("segment should be aligned to its length and lie within w"));
+        }
+        let index = self.offset >> nv;
+        Ok((0..log_half - nv)
+            .map(|i| if (index >> i) & 1 == 1 { E::Fr::one() } else { E::Fr::zero() })
+            .collect())
+    }
+}
+
+impl<E: PairingEngine> MLProofForR1CS<E> {
+    /// bind the committed segments to the transcript, before the commitment to w
+    pub fn append_segments_to_transcript<H: TranscriptHash>(
+        segments: &[CommittedSegment<E>],
+        transcript: &mut Transcript<H>,
+    ) -> SResult<()> {
+        transcript.append_message(b"number of segments", &segments.len())?;
+        for segment in segments.iter() {
+            transcript.append_message(b"committed segment", segment)?;
+        }
+        Ok(())
+    }
+
+    /// Open each segment and w at a random point of the segment, after the proof of the circuit.
+    /// * `w`: private input of the proof
+    pub fn prove_segments<H: TranscriptHash>(
+        pk: &IndexPK<E::Fr>,
+        mut w: Vec<E::Fr>,
+        segments: &[CommittedSegment<E>],
+        pp: &PublicParameter<E>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<Vec<SegmentOpening<E>>> {
+        let log_half = pk.log_n - 1;
+        if w.len() > 1 << log_half {
+            return Err(invalid_arg("|w| > number of witness variables"));
+        }
+        w.resize(1 << log_half, E::Fr::zero());
+        let w_poly = MLExtensionArray::from_vec(w.clone())?;
+
+        let timer = start_timer!(|| "Open committed segments");
+        let mut openings = Vec::with_capacity(segments.len());
+        for segment in segments.iter() {
+            let selector = segment.selector(log_half)?;
+            let point: Vec<E::Fr> = transcript.challenge_scalars(b"segment point", segment.commitment.nv)?;
+            let values = w[segment.offset..segment.offset + segment.size()].to_vec();
+            let (eval, proof_for_segment, _) = MLPolyCommit::open(pp, MLExtensionArray::from_vec(values)?, &point)?;
+            let w_point: Vec<_> = point.into_iter().chain(selector.into_iter()).collect();
+            let (_, proof_for_w, _) = MLPolyCommit::open(pp, w_poly.clone(), &w_point)?;
+            openings.push(SegmentOpening {
+                eval,
+                proof_for_segment,
+                proof_for_w,
+            });
+        }
+        end_timer!(timer);
+        Ok(openings)
+    }
+
+    /// Replay the random points of the segments, and return the opening claims of each segment and of w at
+    /// that point, to be checked together with the opening of w(r_y).
+    /// * `w_commitment`: commitment to w sent in the first round
+    pub fn check_segments<H: TranscriptHash>(
+        w_commitment: &Commitment<E>,
+        segments: &[CommittedSegment<E>],
+        openings: Vec<SegmentOpening<E>>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<Vec<OpeningClaim<E>>> {
+        if segments.len() != openings.len() {
+            return Err(invalid_arg("should have one opening for each segment"));
+        }
+        let mut claims = Vec::with_capacity(2 * segments.len());
+        for (segment, opening) in segments.iter().zip(openings.into_iter()) {
+            let selector = segment.selector(w_commitment.nv)?;
+            let point: Vec<E::Fr> = transcript.challenge_scalars(b"segment point", segment.commitment.nv)?;
+            let w_point: Vec<_> = point.iter().cloned().chain(selector.into_iter()).collect();
+            claims.push(OpeningClaim {
+                commitment: segment.commitment.clone(),
+                point,
+                eval: opening.eval,
+                proof: opening.proof_for_segment,
+            });
+            claims.push(OpeningClaim {
+                commitment: w_commitment.clone(),
+                point: w_point,
+                eval: opening.eval,
+                proof: opening.proof_for_w,
+            });
+        }
+        Ok(claims)
+    }
+
+    /// prove the circuit and the committed segments, continuing `transcript`
+    pub fn prove_linked<H: TranscriptHash>(
+        pk: IndexPK<E::Fr>,
+        v: Vec<E::Fr>,
+        w: Vec<E::Fr>,
+        segments: &[CommittedSegment<E>],
+        pp: &PublicParameter<E>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<LinkedProof<E>> {
+        Self::append_segments_to_transcript(segments, transcript)?;
+        let (proof, _) = Self::prove_rounds(pk.clone(), v, w.clone(), pp, transcript)?;
+        let segment_openings = Self::prove_segments(&pk, w, segments, pp, transcript)?;
+        Ok(LinkedProof {
+            proof,
+            segment_openings,
+        })
+    }
+
+    /// verify the proof and the committed segments, continuing `transcript`
+    pub fn verify_linked<H: TranscriptHash>(
+        vk: IndexVK<E::Fr>,
+        v: Vec<E::Fr>,
+        segments: &[CommittedSegment<E>],
+        proof: LinkedProof<E>,
+        vp: &VerifierParameter<E>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<bool> {
+        Self::append_segments_to_transcript(segments, transcript)?;
+        let claim = Self::verify_with(vk, v, proof.proof, transcript, |vs, pm, _| {
+            Self::check_fifth_round(vs, pm)
+        })?;
+        let mut claims = Self::check_segments(&claim.commitment, segments, proof.segment_openings, transcript)?;
+        claims.push(claim);
+
+        transcript.append_message(b"opening claims", &claims)?;
+        let challenge = transcript.challenge_scalar(b"opening challenge")?;
+        if !MLPolyCommit::verify_many(vp, &claims, challenge)? {
+            return Err(crate::Error::WrongWitness(Some("Cannot verify w_ry or committed segments".into())));
+        }
+        Ok(true)
+    }
+}
@@ -1,65 +1,507 @@
 use crate::ahp::MLProofForR1CS;
+use crate::ahp::setup::PublicParameter;
 use crate::data_structures::r1cs_reader::MatrixExtension;
-use crate::error::invalid_arg;
+use crate::data_structures::transcript::{Transcript, TranscriptHash};
+use crate::error::{invalid_arg, SResult};
+use crate::spark::indexer::{SparkPK, SparkVK};
+use crate::spark::MLSpark;
 use ark_ec::PairingEngine;
-use ark_ff::Field;
+use ark_ff::{Field, One};
 use ark_relations::r1cs::Matrix;
 use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
+use ark_std::fmt;
+use digest::Digest;
 
-/// Prover's Key
+/// SHA-256 digest of the sizes, the matrices, the multisets and the coefficients of an index. It is computed once
+/// by `index_ccs`, and only the digest is absorbed into the transcript, so it can also be logged and compared as a
+/// stable identifier of the circuit.
+#[derive(Clone, PartialEq, Eq, Debug, CanonicalSerialize, CanonicalDeserialize)]
+pub struct CircuitId(pub Vec<u8>);
+
+impl CircuitId {
+    /// Compute the digest. Use it to check the identifier of a key from an untrusted source.
+    pub fn compute<F: Field>(
+        log_m: usize,
+        log_n: usize,
+        num_instance_variables: usize,
+        matrices: &[MatrixExtension<F>],
+        multisets: &[Vec<usize>],
+        coefficients: &[F],
+    ) -> SResult<Self> {
+        let mut bytes = Vec::new();
+        b"r1cs-spartan-raw/circuit-id".to_vec().serialize(&mut bytes)?;
+        log_m.serialize(&mut bytes)?;
+        log_n.serialize(&mut bytes)?;
+        num_instance_variables.serialize(&mut bytes)?;
+        matrices.len().serialize(&mut bytes)?;
+        for matrix in matrices.iter() {
+            matrix.serialize(&mut bytes)?;
+        }
+        multisets.to_vec().serialize(&mut bytes)?;
+        coefficients.to_vec().serialize(&mut bytes)?;
+        Ok(CircuitId(sha2::Sha256::digest(&bytes).to_vec()))
+    }
+}
+
+impl fmt::Display for CircuitId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prover's Key of a customizable constraint system (CCS): `sum_j c_j ∘_{i in S_j} M_i z = 0`, for t matrices M_i,
+/// multisets S_j of matrix indices, and coefficients c_j. R1CS is the special case of the matrices A, B, C with
+/// multisets {0, 1}, {2} and coefficients 1, -1.
 #[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
 pub struct IndexPK<F: Field> {
-    pub matrix_a: MatrixExtension<F>,
-    pub matrix_b: MatrixExtension<F>,
-    pub matrix_c: MatrixExtension<F>,
+    /// M_0, ..., M_{t-1}
+    pub matrices: Vec<MatrixExtension<F>>,
+    /// S_j: indices of the matrices in the j-th Hadamard product, with repetition
+    pub multisets: Vec<Vec<usize>>,
+    /// c_j: coefficient of the j-th Hadamard product
+    pub coefficients: Vec<F>,
+    /// log(number of constraints)
+    pub log_m: usize,
     /// log(|v|+|w|)
     pub log_n: usize,
+    /// |v|, including the constant one at v\[0\]
+    pub num_instance_variables: usize,
+    pub circuit_id: CircuitId,
 }
 
 /// Verifier's Key
 #[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
 pub struct IndexVK<F: Field> {
-    pub matrix_a: MatrixExtension<F>,
-    pub matrix_b: MatrixExtension<F>,
-    pub matrix_c: MatrixExtension<F>,
+    /// M_0, ..., M_{t-1}
+    pub matrices: Vec<MatrixExtension<F>>,
+    /// S_j: indices of the matrices in the j-th Hadamard product, with repetition
+    pub multisets: Vec<Vec<usize>>,
+    /// c_j: coefficient of the j-th Hadamard product
+    pub coefficients: Vec<F>,
+    /// log(number of constraints)
+    pub log_m: usize,
+    /// log(|v|+|w|)
+    pub log_n: usize,
+    /// |v|, including the constant one at v\[0\]
+    pub num_instance_variables: usize,
+    pub circuit_id: CircuitId,
+}
+
+/// Prover's Key for preprocessing mode, where the verifier only holds commitments to the matrices
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct PreprocessedIndexPK<E: PairingEngine> {
+    pub index: IndexPK<E::Fr>,
+    pub spark: SparkPK<E>,
+}
+
+/// Verifier's Key for preprocessing mode. Its size is logarithmic in the number of non-zero entries.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct PreprocessedIndexVK<E: PairingEngine> {
+    /// S_j: indices of the matrices in the j-th Hadamard product, with repetition
+    pub multisets: Vec<Vec<usize>>,
+    /// c_j: coefficient of the j-th Hadamard product
+    pub coefficients: Vec<E::Fr>,
+    /// log(number of constraints)
+    pub log_m: usize,
     /// log(|v|+|w|)
     pub log_n: usize,
+    /// |v|, including the constant one at v\[0\]
+    pub num_instance_variables: usize,
+    pub spark: SparkVK<E>,
+}
+
+/// Information about the index used by the verifier in every round.
+/// Prover's keys also implement it, so that the prover derives the same transcript as the verifier.
+pub trait VerifierIndex<F: Field> {
+    /// log(number of constraints)
+    fn log_m(&self) -> usize;
+    /// log(|v|+|w|)
+    fn log_n(&self) -> usize;
+    /// |v|, including the constant one at v\[0\]
+    fn num_instance_variables(&self) -> usize;
+    /// t: number of matrices
+    fn num_matrices(&self) -> usize;
+    /// S_j: indices of the matrices in the j-th Hadamard product, with repetition
+    fn multisets(&self) -> &[Vec<usize>];
+    /// c_j: coefficient of the j-th Hadamard product
+    fn coefficients(&self) -> &[F];
+    /// absorb the sizes and a digest of the matrices (or their commitments) into `transcript`
+    fn append_to_transcript<H: TranscriptHash>(&self, transcript: &mut Transcript<H>) -> SResult<()>;
+
+    /// degree of the constraints: the size of the largest multiset
+    fn degree(&self) -> usize {
+        self.multisets().iter().map(|s| s.len()).max().unwrap_or(0)
+    }
+
+    /// the constraints as products of the tables M_i z, for the zerocheck
+    fn products(&self) -> SResult<Vec<(F, Vec<usize>)>> {
+        check_structure(self.num_matrices(), self.multisets(), self.coefficients())?;
+        Ok(self
+            .coefficients()
+            .iter()
+            .cloned()
+            .zip(self.multisets().iter().cloned())
+            .collect())
+    }
+}
+
+fn check_structure<F: Field>(num_matrices: usize, multisets: &[Vec<usize>], coefficients: &[F]) -> SResult<()> {
+    if num_matrices == 0 || multisets.is_empty() {
+        return Err(invalid_arg("constraint system should have at least one matrix and one multiset"));
+    }
+    if multisets.len() != coefficients.len() {
+        return Err(invalid_arg("should have one coefficient for each multiset"));
+    }
+    if multisets.iter().any(|s| s.is_empty() || s.iter().any(|i| *i >= num_matrices)) {
+        return Err(invalid_arg("multisets should be non-empty and refer to the given matrices"));
+    }
+    Ok(())
+}
+
+pub(crate) fn append_sizes<F: Field, I: VerifierIndex<F>, H: TranscriptHash>(
+    index: &I,
+    transcript: &mut Transcript<H>,
+) -> SResult<()> {
+    transcript.append_message(b"log_m", &index.log_m())?;
+    transcript.append_message(b"log_n", &index.log_n())?;
+    transcript.append_message(b"num_instance_variables", &index.num_instance_variables())
+}
+
+/// absorb the constraint structure of an index whose matrices are only known through commitments
+fn append_structure<F: Field, I: VerifierIndex<F>, H: TranscriptHash>(
+    index: &I,
+    transcript: &mut Transcript<H>,
+) -> SResult<()> {
+    transcript.append_message(b"multisets", &index.multisets().to_vec())?;
+    transcript.append_scalars(b"coefficients", index.coefficients())
+}
+
+impl<'a, F: Field, I: VerifierIndex<F>> VerifierIndex<F> for &'a I {
+    fn log_m(&self) -> usize {
+        I::log_m(*self)
+    }
+
+    fn log_n(&self) -> usize {
+        I::log_n(*self)
+    }
+
+    fn num_instance_variables(&self) -> usize {
+        I::num_instance_variables(*self)
+    }
+
+    fn num_matrices(&self) -> usize {
+        I::num_matrices(*self)
+    }
+
+    fn multisets(&self) -> &[Vec<usize>] {
+        I::multisets(*self)
+    }
+
+    fn coefficients(&self) -> &[F] {
+        I::coefficients(*self)
+    }
+
+    fn append_to_transcript<H: TranscriptHash>(&self, transcript: &mut Transcript<H>) -> SResult<()> {
+        I::append_to_transcript(*self, transcript)
+    }
+}
+
+impl<F: Field> VerifierIndex<F> for IndexPK<F> {
+    fn log_m(&self) -> usize {
+        self.log_m
+    }
+
+    fn log_n(&self) -> usize {
+        self.log_n
+    }
+
+    fn num_instance_variables(&self) -> usize {
+        self.num_instance_variables
+    }
+
+    fn num_matrices(&self) -> usize {
+        self.matrices.len()
+    }
+
+    fn multisets(&self) -> &[Vec<usize>] {
+        &self.multisets
+    }
+
+    fn coefficients(&self) -> &[F] {
+        &self.coefficients
+    }
+
+    fn append_to_transcript<H: TranscriptHash>(&self, transcript: &mut Transcript<H>) -> SResult<()> {
+        append_sizes(self, transcript)?;
+        transcript.append_message(b"circuit id", &self.circuit_id)
+    }
+}
+
+impl<F: Field> VerifierIndex<F> for IndexVK<F> {
+    fn log_m(&self) -> usize {
+        self.log_m
+    }
+
+    fn log_n(&self) -> usize {
+        self.log_n
+    }
+
+    fn num_instance_variables(&self) -> usize {
+        self.num_instance_variables
+    }
+
+    fn num_matrices(&self) -> usize {
+        self.matrices.len()
+    }
+
+    fn multisets(&self) -> &[Vec<usize>] {
+        &self.multisets
+    }
+
+    fn coefficients(&self) -> &[F] {
+        &self.coefficients
+    }
+
+    fn append_to_transcript<H: TranscriptHash>(&self, transcript: &mut Transcript<H>) -> SResult<()> {
+        append_sizes(self, transcript)?;
+        transcript.append_message(b"circuit id", &self.circuit_id)
+    }
+}
+
+impl<E: PairingEngine> VerifierIndex<E::Fr> for PreprocessedIndexVK<E> {
+    fn log_m(&self) -> usize {
+        self.log_m
+    }
+
+    fn log_n(&self) -> usize {
+        self.log_n
+    }
+
+    fn num_instance_variables(&self) -> usize {
+        self.num_instance_variables
+    }
+
+    fn num_matrices(&self) -> usize {
+        self.spark.matrices.len()
+    }
+
+    fn multisets(&self) -> &[Vec<usize>] {
+        &self.multisets
+    }
+
+    fn coefficients(&self) -> &[E::Fr] {
+        &self.coefficients
+    }
+
+    fn append_to_transcript<H: TranscriptHash>(&self, transcript: &mut Transcript<H>) -> SResult<()> {
+        append_sizes(self, transcript)?;
+        append_structure(self, transcript)?;
+        transcript.append_message(b"matrix commitments", &self.spark)
+    }
+}
+
+impl<E: PairingEngine> VerifierIndex<E::Fr> for PreprocessedIndexPK<E> {
+    fn log_m(&self) -> usize {
+        self.index.log_m
+    }
+
+    fn log_n(&self) -> usize {
+        self.index.log_n
+    }
+
+    fn num_instance_variables(&self) -> usize {
+        self.index.num_instance_variables
+    }
+
+    fn num_matrices(&self) -> usize {
+        self.index.matrices.len()
+    }
+
+    fn multisets(&self) -> &[Vec<usize>] {
+        &self.index.multisets
+    }
+
+    fn coefficients(&self) -> &[E::Fr] {
+        &self.index.coefficients
+    }
+
+    fn append_to_transcript<H: TranscriptHash>(&self, transcript: &mut Transcript<H>) -> SResult<()> {
+        append_sizes(self, transcript)?;
+        append_structure(self, transcript)?;
+        transcript.append_message(b"matrix commitments", &self.spark.vk)
+    }
 }
 
 impl<F: Field> IndexPK<F> {
     pub fn vk(&self) -> IndexVK<F> {
         IndexVK {
-            matrix_a: self.matrix_a.clone(),
-            matrix_b: self.matrix_b.clone(),
-            matrix_c: self.matrix_c.clone(),
+            matrices: self.matrices.clone(),
+            multisets: self.multisets.clone(),
+            coefficients: self.coefficients.clone(),
+            log_m: self.log_m,
+            log_n: self.log_n,
+            num_instance_variables: self.num_instance_variables,
+            circuit_id: self.circuit_id.clone(),
+        }
+    }
+}
+
+impl<E: PairingEngine> PreprocessedIndexPK<E> {
+    pub fn vk(&self) -> PreprocessedIndexVK<E> {
+        PreprocessedIndexVK {
+            multisets: self.index.multisets.clone(),
+            coefficients: self.index.coefficients.clone(),
+            log_m: self.index.log_m,
+            log_n: self.index.log_n,
+            num_instance_variables: self.index.num_instance_variables,
+            spark: self.spark.vk(),
+        }
+    }
+}
+
+impl<E: PairingEngine> Clone for PreprocessedIndexVK<E> {
+    fn clone(&self) -> Self {
+        Self {
+            multisets: self.multisets.clone(),
+            coefficients: self.coefficients.clone(),
+            log_m: self.log_m,
             log_n: self.log_n,
+            num_instance_variables: self.num_instance_variables,
+            spark: self.spark.clone(),
         }
     }
 }
 
+/// Place the columns of the witness at the second half of z and pad the matrices to powers of two. The sizes of v
+/// and w are given explicitly, since the last witness variables may appear in no constraint.
+///
+/// return: the matrices, log(number of constraints) and log(|v|+|w|)
+pub(crate) fn layout_matrices<F: Field>(
+    matrices: Vec<Matrix<F>>,
+    num_instance_variables: usize,
+    num_witness_variables: usize,
+) -> SResult<(Vec<MatrixExtension<F>>, usize, usize)> {
+    if num_instance_variables == 0 {
+        return Err(invalid_arg("public input should contain the constant one"));
+    }
+    let num_constraints = matrices.iter().map(|m| m.len()).max().unwrap_or(0);
+    let num_variables = num_instance_variables + num_witness_variables;
+    if matrices
+        .iter()
+        .any(|m| m.iter().any(|row| row.iter().any(|(_, y)| *y >= num_variables)))
+    {
+        return Err(invalid_arg("matrix refers to a variable beyond the public and private input"));
+    }
+
+    // z is v || w, where v and w are both padded with zeros to 2^(log_n - 1), so that the
+    // verifier can compute z(r_y) from w(r_y[..log_n - 1]) and the public input.
+    let log_half = ark_std::cmp::max(
+        ark_std::cmp::max(
+            ark_std::log2(num_instance_variables) as usize,
+            ark_std::log2(num_witness_variables) as usize,
+        ),
+        1,
+    );
+    let half = 1 << log_half;
+    let shift_witness = |matrix: Matrix<F>| -> Matrix<F> {
+        matrix
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|(a, y)| {
+                        if y < num_instance_variables {
+                            (a, y)
+                        } else {
+                            (a, y - num_instance_variables + half)
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    };
+
+    // the matrices are padded with zero rows so that the number of constraints is a power of 2.
+    let m = ark_std::cmp::max(num_constraints, 2).next_power_of_two();
+    let n = half << 1;
+    let matrices = matrices
+        .into_iter()
+        .map(|matrix| MatrixExtension::new(shift_witness(matrix), m, n))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((matrices, ark_std::log2(m) as usize, log_half + 1))
+}
+
 impl<E: PairingEngine> MLProofForR1CS<E> {
+    /// Index the R1CS `Az ∘ Bz - Cz = 0`.
+    /// * `num_instance_variables`: |v|, including the constant one at v\[0\]
+    /// * `num_witness_variables`: |w|
     pub fn index(
         matrix_a: Matrix<E::Fr>,
         matrix_b: Matrix<E::Fr>,
         matrix_c: Matrix<E::Fr>,
+        num_instance_variables: usize,
+        num_witness_variables: usize,
     ) -> Result<IndexPK<E::Fr>, crate::Error> {
-        // sanity check
-        let n = matrix_a.len();
-        // for simplicity, this protocol assume width of matrix (n) is a power of 2.
-        if !n.is_power_of_two() {
-            return Err(invalid_arg("Matrix width should be a power of 2."));
-        }
-        let log_n = ark_std::log2(n) as usize;
+        Self::index_ccs(
+            vec![matrix_a, matrix_b, matrix_c],
+            vec![vec![0, 1], vec![2]],
+            vec![E::Fr::one(), -E::Fr::one()],
+            num_instance_variables,
+            num_witness_variables,
+        )
+    }
 
-        let matrix_a = MatrixExtension::new(matrix_a, n)?;
-        let matrix_b = MatrixExtension::new(matrix_b, n)?;
-        let matrix_c = MatrixExtension::new(matrix_c, n)?;
+    /// Index a customizable constraint system `sum_j coefficients[j] ∘_{i in multisets[j]} matrices[i] z = 0`.
+    /// * `num_instance_variables`: |v|, including the constant one at v\[0\]
+    /// * `num_witness_variables`: |w|
+    pub fn index_ccs(
+        matrices: Vec<Matrix<E::Fr>>,
+        multisets: Vec<Vec<usize>>,
+        coefficients: Vec<E::Fr>,
+        num_instance_variables: usize,
+        num_witness_variables: usize,
+    ) -> SResult<IndexPK<E::Fr>> {
+        check_structure(matrices.len(), &multisets, &coefficients)?;
+        let (matrices, log_m, log_n) = layout_matrices(matrices, num_instance_variables, num_witness_variables)?;
+        let circuit_id = CircuitId::compute(
+            log_m,
+            log_n,
+            num_instance_variables,
+            &matrices,
+            &multisets,
+            &coefficients,
+        )?;
 
         Ok(IndexPK {
-            matrix_a,
-            matrix_b,
-            matrix_c,
+            matrices,
+            multisets,
+            coefficients,
+            log_m,
             log_n,
+            num_instance_variables,
+            circuit_id,
         })
     }
+
+    /// number of variables the public parameter should support to use `pk` in preprocessing mode
+    pub fn num_variables_for_preprocessing(pk: &IndexPK<E::Fr>) -> usize {
+        let max_nnz = pk.matrices.iter().map(|m| m.num_non_zero()).max().unwrap_or(0);
+        MLSpark::<E>::num_variables_for_setup(pk.log_m, pk.log_n, max_nnz)
+    }
+
+    /// Commit to the matrices so that the verifier does not need to hold them.
+    pub fn preprocess(
+        pk: IndexPK<E::Fr>,
+        pp: &PublicParameter<E>,
+    ) -> SResult<PreprocessedIndexPK<E>> {
+        let spark = MLSpark::index(
+            &pk.matrices.iter().collect::<Vec<_>>(),
+            pk.log_m,
+            pk.log_n,
+            pp,
+        )?;
+        Ok(PreprocessedIndexPK { index: pk, spark })
+    }
 }
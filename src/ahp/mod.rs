@@ -5,6 +5,16 @@ pub mod setup;
 pub mod indexer;
 pub mod prover;
 pub mod verifier;
+/// drivers of all rounds of the prover and the verifier
+mod rounds;
+/// proving many instances of the same circuit at once
+pub mod batch;
+/// proving instances of different circuits at once
+pub mod multi_circuit;
+/// linking proofs to commitments of segments of w
+pub mod commit_and_prove;
+/// witnesses generated in phases with challenges in between
+pub mod multi_phase;
 
 #[cfg(test)]
 mod tests;
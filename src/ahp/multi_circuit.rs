@@ -0,0 +1,283 @@
+//! Argument for instances of different circuits in one proof.
+//!
+//! Each circuit keeps its own commitment to w, but both sumchecks are shared: the tables of every circuit are
+//! padded with zeros to the largest size and combined with random coefficients, and all openings are checked
+//! with one multi-pairing. A table padded with zeros from `l` to `L` variables has the extension
+//! f(r[..l]) * prod_{k >= l} (1 - r_k), so the verifier recovers the claim of each circuit from its own
+//! evaluations.
+
+use linear_sumcheck::data_structures::ml_extension::{ArithmeticCombination, MLExtension};
+use linear_sumcheck::data_structures::MLExtensionArray;
+
+use crate::ahp::indexer::{IndexPK, IndexVK, VerifierIndex};
+use crate::ahp::prover::{ProverFifthMessage, ProverFirstMessage, ProverThirdMessage};
+use crate::ahp::setup::{PublicParameter, SetupIdentifier, VerifierParameter};
+use crate::ahp::MLProofForR1CS;
+use crate::commitment::verify::OpeningClaim;
+use crate::commitment::MLPolyCommit;
+use crate::data_structures::eq::{eq_eval, eq_table};
+use crate::data_structures::proof::MultiCircuitProof;
+use crate::data_structures::r1cs_reader::MatrixExtension;
+use crate::data_structures::transcript::{Transcript, TranscriptHash};
+use crate::error::{invalid_arg, SResult};
+use crate::sumcheck;
+use ark_ec::PairingEngine;
+use ark_ff::{Field, One, Zero};
+use ark_std::cmp::max;
+
+/// label of the multi-circuit protocol in the transcript
+const MULTI_CIRCUIT_PROTOCOL_LABEL: &[u8] = b"r1cs-spartan-raw/multi-circuit-argument-for-r1cs";
+
+/// extension of the zero padding at the padded variables: prod (1 - r_k)
+fn padding_factor<F: Field>(r: &[F]) -> F {
+    r.iter().fold(F::one(), |acc, r_k| acc * &(F::one() - r_k))
+}
+
+/// table of `f` padded with zeros to `nv` variables, scaled by `coefficient`
+fn padded_table<F: Field>(mut table: Vec<F>, coefficient: F, nv: usize) -> SResult<MLExtensionArray<F>> {
+    table.iter_mut().for_each(|t| *t *= &coefficient);
+    table.resize(1 << nv, F::zero());
+    Ok(MLExtensionArray::from_vec(table)?)
+}
+
+impl<E: PairingEngine> MLProofForR1CS<E> {
+    /// number of variables the public parameter should support to prove instances of all circuits in `indices`
+    pub fn num_variables_for_multi_circuit<K: VerifierIndex<E::Fr>>(indices: &[K]) -> usize {
+        indices.iter().map(|index| index.log_n() - 1).max().unwrap_or(0)
+    }
+
+    /// Start the transcript of a multi-circuit proof. It binds the setup, and the index and public input of
+    /// every instance.
+    pub fn init_multi_circuit_transcript<K: VerifierIndex<E::Fr>, H: TranscriptHash>(
+        setup: &SetupIdentifier,
+        instances: &[(K, &[E::Fr])],
+    ) -> SResult<Transcript<H>> {
+        let mut transcript = Transcript::new(MULTI_CIRCUIT_PROTOCOL_LABEL);
+        transcript.append_message(b"setup", setup)?;
+        transcript.append_message(b"number of instances", &instances.len())?;
+        for (index, v) in instances.iter() {
+            index.append_to_transcript(&mut transcript)?;
+            transcript.append_scalars(b"public input", v)?;
+        }
+        Ok(transcript)
+    }
+
+    /// Prove that `(v, w)` satisfies the circuit of `pk` for every `(pk, v, w)` in `instances`.
+    pub fn prove_multi_circuit<H: TranscriptHash>(
+        instances: Vec<(&IndexPK<E::Fr>, Vec<E::Fr>, Vec<E::Fr>)>,
+        pp: &PublicParameter<E>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<MultiCircuitProof<E>> {
+        if instances.is_empty() {
+            return Err(invalid_arg("should have at least one instance"));
+        }
+        let max_log_m = instances.iter().map(|(pk, _, _)| pk.log_m).max().unwrap();
+        let max_log_n = instances.iter().map(|(pk, _, _)| pk.log_n).max().unwrap();
+
+        let timer = start_timer!(|| "Commit to witnesses");
+        let mut pks = Vec::with_capacity(instances.len());
+        let mut zs = Vec::with_capacity(instances.len());
+        let mut w_polys = Vec::with_capacity(instances.len());
+        let mut commitments = Vec::with_capacity(instances.len());
+        for (pk, v, w) in instances.into_iter() {
+            let (v, w) = Self::pad_instance(pk, v, w)?;
+            let w_poly = MLExtensionArray::from_vec(w.clone())?;
+            commitments.push(ProverFirstMessage {
+                commitment: MLPolyCommit::commit(pp, w_poly.clone())?,
+            });
+            w_polys.push(w_poly);
+            zs.push(v.into_iter().chain(w.into_iter()).collect::<Vec<_>>());
+            pks.push(pk);
+        }
+        end_timer!(timer);
+        transcript.append_message(b"commitments to w", &commitments)?;
+        let tor: Vec<E::Fr> = transcript.challenge_scalars(b"tor", max_log_m)?;
+        let coefficients: Vec<E::Fr> = transcript.challenge_scalars(b"first sumcheck coefficients", pks.len())?;
+
+        // first sumcheck: eq(tor, x) * sum_i c_i * sum_j c_ij prod_{l in S_ij} M_il z_i(x)
+        let timer = start_timer!(|| "Prove first sumcheck");
+        let eq = MLExtensionArray::from_vec(eq_table(&tor))?;
+        let mut poly = ArithmeticCombination::new(max_log_m);
+        let mut tables = Vec::with_capacity(pks.len());
+        for ((pk, z), c) in pks.iter().zip(zs.iter()).zip(coefficients.iter()) {
+            let mz = pk
+                .matrices
+                .iter()
+                .map(|matrix| matrix.mul_vector(z))
+                .collect::<Result<Vec<_>, _>>()?;
+            for (c_j, multiset) in pk.products()? {
+                let mut multiplicands = Vec::with_capacity(multiset.len() + 1);
+                for l in multiset.iter() {
+                    multiplicands.push(padded_table(mz[*l].clone(), E::Fr::one(), max_log_m)?);
+                }
+                multiplicands.push(eq.multiply(*c * &c_j)?);
+                poly.add_product(multiplicands.into_iter())?;
+            }
+            tables.push(mz);
+        }
+        let (first_sumcheck, r_x) = sumcheck::prove(poly, transcript)?;
+        end_timer!(timer);
+
+        let mut evaluations = Vec::with_capacity(pks.len());
+        for (mz, pk) in tables.into_iter().zip(pks.iter()) {
+            let r_x = &r_x[..pk.log_m];
+            evaluations.push(ProverThirdMessage {
+                evaluations: mz
+                    .into_iter()
+                    .map(|table| Ok(MLExtensionArray::from_vec(table)?.eval_at(r_x)?))
+                    .collect::<SResult<Vec<_>>>()?,
+            });
+        }
+        transcript.append_message(b"M_i z(r_x)", &evaluations)?;
+        let max_num_matrices = pks.iter().map(|pk| pk.matrices.len()).max().unwrap();
+        let matrix_coefficients: Vec<E::Fr> = transcript.challenge_scalars(b"matrix coefficients", max_num_matrices)?;
+        let coefficients: Vec<E::Fr> = transcript.challenge_scalars(b"second sumcheck coefficients", pks.len())?;
+
+        // second sumcheck: sum_i d_i * M_i(r_x, y) * z_i(y), where M_i = sum_l r_l * M_il
+        let timer = start_timer!(|| "Prove second sumcheck");
+        let mut poly = ArithmeticCombination::new(max_log_n);
+        for ((pk, z), d) in pks.iter().zip(zs.into_iter()).zip(coefficients.iter()) {
+            let combination: Vec<_> = matrix_coefficients
+                .iter()
+                .zip(pk.matrices.iter())
+                .map(|(r_l, matrix)| (*d * r_l, matrix))
+                .collect();
+            let m_rx = MatrixExtension::eval_combination_on_x(&combination, &eq_table(&r_x[..pk.log_m]))?;
+            poly.add_product(
+                vec![
+                    padded_table(m_rx.into_table()?, E::Fr::one(), max_log_n)?,
+                    padded_table(z, E::Fr::one(), max_log_n)?,
+                ]
+                .into_iter(),
+            )?;
+        }
+        let (second_sumcheck, r_y) = sumcheck::prove(poly, transcript)?;
+        end_timer!(timer);
+
+        // open each w_i at r_y[..log_n_i - 1]
+        let timer = start_timer!(|| "Open witnesses");
+        let mut openings = Vec::with_capacity(pks.len());
+        for (pk, w_poly) in pks.iter().zip(w_polys.into_iter()) {
+            let (w_ry, proof_for_w_ry, _) = MLPolyCommit::open(pp, w_poly, &r_y[..pk.log_n - 1])?;
+            openings.push(ProverFifthMessage { w_ry, proof_for_w_ry });
+        }
+        end_timer!(timer);
+
+        Ok(MultiCircuitProof {
+            commitments,
+            first_sumcheck,
+            evaluations,
+            second_sumcheck,
+            openings,
+        })
+    }
+
+    /// Verify a multi-circuit proof against the index and public input of every instance.
+    pub fn verify_multi_circuit<H: TranscriptHash>(
+        instances: Vec<(&IndexVK<E::Fr>, Vec<E::Fr>)>,
+        proof: MultiCircuitProof<E>,
+        vp: &VerifierParameter<E>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<bool> {
+        let k = instances.len();
+        if k == 0 {
+            return Err(invalid_arg("should have at least one instance"));
+        }
+        if proof.commitments.len() != k || proof.evaluations.len() != k || proof.openings.len() != k {
+            return Err(invalid_arg("proof has wrong number of instances"));
+        }
+        let max_log_m = instances.iter().map(|(vk, _)| vk.log_m).max().unwrap();
+        let max_log_n = instances.iter().map(|(vk, _)| vk.log_n).max().unwrap();
+        let mut max_degree = 0;
+        let mut max_num_matrices = 0;
+        let mut vks = Vec::with_capacity(k);
+        let mut vs = Vec::with_capacity(k);
+        let checked = instances
+            .into_iter()
+            .zip(proof.commitments.iter())
+            .zip(proof.evaluations.iter());
+        for (((vk, v), commitment), e) in checked {
+            if commitment.commitment.nv + 1 != vk.log_n {
+                return Err(invalid_arg("commitment has wrong number of variables"));
+            }
+            if e.evaluations.len() != vk.matrices.len() {
+                return Err(invalid_arg("should have one evaluation for each matrix"));
+            }
+            max_degree = max(max_degree, vk.degree());
+            max_num_matrices = max(max_num_matrices, vk.matrices.len());
+            vs.push(Self::pad_public_input(vk, v)?);
+            vks.push(vk);
+        }
+        transcript.append_message(b"commitments to w", &proof.commitments)?;
+        let tor: Vec<E::Fr> = transcript.challenge_scalars(b"tor", max_log_m)?;
+        let coefficients: Vec<E::Fr> = transcript.challenge_scalars(b"first sumcheck coefficients", k)?;
+
+        // first sumcheck
+        let (r_x, expected) =
+            sumcheck::verify(proof.first_sumcheck, max_log_m, max_degree + 1, E::Fr::zero(), transcript)?;
+        let mut actual = E::Fr::zero();
+        for ((vk, e), c) in vks.iter().zip(proof.evaluations.iter()).zip(coefficients.iter()) {
+            // each factor of a product is padded, so the product is scaled by s once for each factor
+            let s = padding_factor(&r_x[vk.log_m..]);
+            let padded: Vec<_> = e.evaluations.iter().map(|e_l| *e_l * &s).collect();
+            for (c_j, multiset) in vk.products()? {
+                actual += &multiset.iter().fold(*c * &c_j, |acc, l| acc * &padded[*l]);
+            }
+        }
+        if actual * &eq_eval(&tor, &r_x)? != expected {
+            return Err(crate::Error::WrongWitness(Some(
+                "first sumcheck has wrong subclaim".into(),
+            )));
+        }
+        transcript.append_message(b"M_i z(r_x)", &proof.evaluations)?;
+        let matrix_coefficients: Vec<E::Fr> = transcript.challenge_scalars(b"matrix coefficients", max_num_matrices)?;
+        let coefficients: Vec<E::Fr> = transcript.challenge_scalars(b"second sumcheck coefficients", k)?;
+
+        // second sumcheck
+        let claimed_sum = proof
+            .evaluations
+            .iter()
+            .zip(coefficients.iter())
+            .fold(E::Fr::zero(), |acc, (e, d)| {
+                let m_z = matrix_coefficients
+                    .iter()
+                    .zip(e.evaluations.iter())
+                    .fold(E::Fr::zero(), |acc, (r_l, e_l)| acc + &(*r_l * e_l));
+                acc + &(*d * &m_z)
+            });
+        let (r_y, expected) = sumcheck::verify(proof.second_sumcheck, max_log_n, 2, claimed_sum, transcript)?;
+        let mut actual = E::Fr::zero();
+        let mut claims = Vec::with_capacity(k);
+        let instances = vks
+            .into_iter()
+            .zip(vs.into_iter())
+            .zip(proof.commitments.into_iter())
+            .zip(proof.openings.into_iter())
+            .zip(coefficients.into_iter());
+        for ((((vk, v), commitment), opening), d) in instances {
+            let s = padding_factor(&r_y[vk.log_n..]);
+            let z_ry = Self::z_eval(v, opening.w_ry, &r_y[..vk.log_n])?;
+            let m_rx_ry = Self::matrix_evaluation(vk, &r_x[..vk.log_m], &r_y[..vk.log_n], &matrix_coefficients)?;
+            actual += &(d * &m_rx_ry * &z_ry * &s * &s);
+            claims.push(OpeningClaim {
+                commitment: commitment.commitment,
+                point: r_y[..vk.log_n - 1].to_vec(),
+                eval: opening.w_ry,
+                proof: opening.proof_for_w_ry,
+            });
+        }
+        if actual != expected {
+            return Err(crate::Error::WrongWitness(Some(
+                "Cannot verify the matrices".into(),
+            )));
+        }
+
+        // all openings are checked with one multi-pairing
+        transcript.append_message(b"w(r_y)", &claims)?;
+        let challenge = transcript.challenge_scalar(b"opening challenge")?;
+        if !MLPolyCommit::verify_many(vp, &claims, challenge)? {
+            return Err(crate::Error::WrongWitness(Some("Cannot verify w_ry".into())));
+        }
+        Ok(true)
+    }
+}
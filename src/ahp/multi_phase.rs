@@ -0,0 +1,165 @@
+//! Witnesses generated in phases, with verifier challenges between the phases.
+//!
+//! Each phase is a segment of w which is committed before the challenges of that phase are sampled, so later
+//! parts of w can depend on the challenges. The challenges are appended to the public input, so the matrices
+//! reference them as public values. The final proof is linked to the commitment of every phase as a committed
+//! segment of w.
+
+use linear_sumcheck::data_structures::{Blake2s512Rng, MLExtensionArray};
+
+use crate::ahp::commit_and_prove::CommittedSegment;
+use crate::ahp::indexer::{IndexPK, VerifierIndex};
+use crate::ahp::setup::{PublicParameter, SetupIdentifier};
+use crate::ahp::MLProofForR1CS;
+use crate::commitment::commit::Commitment;
+use crate::commitment::MLPolyCommit;
+use crate::data_structures::proof::MultiPhaseProof;
+use crate::data_structures::transcript::{Transcript, TranscriptHash};
+use crate::error::{invalid_arg, SResult};
+use ark_ec::PairingEngine;
+use ark_ff::Zero;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
+
+/// Segment `w[offset..offset + 2^num_variables]` committed in one phase, followed by `num_challenges` challenges.
+#[derive(Clone, Copy, CanonicalSerialize, CanonicalDeserialize)]
+pub struct Phase {
+    pub offset: usize,
+    pub num_variables: usize,
+    pub num_challenges: usize,
+}
+
+/// Positions of the committed phases in w. The rest of w is only committed in the final proof.
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct PhaseLayout {
+    pub phases: Vec<Phase>,
+}
+
+impl PhaseLayout {
+    /// `phases[i] = (length, num_challenges)`: phase i has `length` elements, padded to a power of two and
+    /// placed after the previous phase at the first offset aligned to its size.
+    pub fn new(phases: &[(usize, usize)]) -> SResult<Self> {
+        let mut end = 0;
+        let mut layout = Vec::with_capacity(phases.len());
+        for &(length, num_challenges) in phases.iter() {
+            if length == 0 {
+                return Err(invalid_arg("phase should have at least one element"));
+            }
+            let size = ark_std::cmp::max(length, 2).next_power_of_two();
+            let offset = (end + size - 1) / size * size;
+            layout.push(Phase {
+                offset,
+                num_variables: ark_std::log2(size) as usize,
+                num_challenges,
+            });
+            end = offset + size;
+        }
+        Ok(Self { phases: layout })
+    }
+
+    /// number of challenges appended to the public input
+    pub fn num_challenges(&self) -> usize {
+        self.phases.iter().map(|phase| phase.num_challenges).sum()
+    }
+
+    /// first position of w after all committed phases
+    pub fn end(&self) -> usize {
+        self.phases.last().map_or(0, |phase| phase.offset + (1 << phase.num_variables))
+    }
+}
+
+impl<E: PairingEngine> MLProofForR1CS<E> {
+    /// Start the transcript of a multi-phase proof, where `v` is the public input without the challenges.
+    pub fn init_multi_phase_transcript<K: VerifierIndex<E::Fr>, H: TranscriptHash>(
+        setup: &SetupIdentifier,
+        index: &K,
+        layout: &PhaseLayout,
+        v: &[E::Fr],
+    ) -> SResult<Transcript<H>> {
+        if v.len() + layout.num_challenges() != index.num_instance_variables() {
+            return Err(invalid_arg("public input and challenges should fill the instance variables"));
+        }
+        let mut transcript = Self::init_transcript(setup, index, v)?;
+        transcript.append_message(b"phase layout", layout)?;
+        Ok(transcript)
+    }
+
+    /// absorb the commitment of a phase, and return the challenges of the phase
+    pub fn commit_phase_to_transcript<H: TranscriptHash>(
+        phase: &Phase,
+        commitment: &Commitment<E>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<Vec<E::Fr>> {
+        if commitment.nv != phase.num_variables {
+            return Err(invalid_arg("commitment of phase has wrong number of variables"));
+        }
+        transcript.append_message(b"phase commitment", commitment)?;
+        if phase.num_challenges == 0 {
+            return Ok(Vec::new());
+        }
+        transcript.challenge_scalars(b"phase challenges", phase.num_challenges)
+    }
+}
+
+/// Prover of a multi-phase witness. Commit each phase with `commit_phase`, then give the whole witness to
+/// `finish`.
+pub struct MultiPhaseProver<'a, E: PairingEngine, H: TranscriptHash = Blake2s512Rng> {
+    pk: &'a IndexPK<E::Fr>,
+    layout: &'a PhaseLayout,
+    pp: &'a PublicParameter<E>,
+    v: Vec<E::Fr>,
+    transcript: Transcript<H>,
+    committed: Vec<(Vec<E::Fr>, CommittedSegment<E>)>,
+}
+
+impl<'a, E: PairingEngine, H: TranscriptHash> MultiPhaseProver<'a, E, H> {
+    /// * `v`: public input without the challenges
+    pub fn new(pk: &'a IndexPK<E::Fr>, layout: &'a PhaseLayout, v: Vec<E::Fr>, pp: &'a PublicParameter<E>) -> SResult<Self> {
+        let transcript = MLProofForR1CS::init_multi_phase_transcript(pp.id(), pk, layout, &v)?;
+        Ok(Self {
+            pk,
+            layout,
+            pp,
+            v,
+            transcript,
+            committed: Vec::with_capacity(layout.phases.len()),
+        })
+    }
+
+    /// Commit to the values of the next phase, and return its challenges.
+    pub fn commit_phase(&mut self, mut values: Vec<E::Fr>) -> SResult<Vec<E::Fr>> {
+        let phase = match self.layout.phases.get(self.committed.len()) {
+            Some(phase) => *phase,
+            None => return Err(invalid_arg("all phases are committed")),
+        };
+        if values.len() > 1 << phase.num_variables {
+            return Err(invalid_arg("phase has too many values"));
+        }
+        values.resize(1 << phase.num_variables, E::Fr::zero());
+        let commitment = MLPolyCommit::commit(self.pp, MLExtensionArray::from_vec(values.clone())?)?;
+        let challenges = MLProofForR1CS::commit_phase_to_transcript(&phase, &commitment, &mut self.transcript)?;
+        self.v.extend_from_slice(&challenges);
+        self.committed.push((values, CommittedSegment::new(phase.offset, commitment)));
+        Ok(challenges)
+    }
+
+    /// Prove the circuit with private input `w`, whose phases should be the committed values.
+    pub fn finish(mut self, w: Vec<E::Fr>) -> SResult<MultiPhaseProof<E>> {
+        if self.committed.len() != self.layout.phases.len() {
+            return Err(invalid_arg("all phases should be committed before proving"));
+        }
+        for (values, segment) in self.committed.iter() {
+            let consistent = values.iter().enumerate().all(|(i, value)| {
+                w.get(segment.offset + i).map_or(value.is_zero(), |w_i| w_i == value)
+            });
+            if !consistent {
+                return Err(invalid_arg("private input does not match a committed phase"));
+            }
+        }
+        let segments: Vec<_> = self.committed.into_iter().map(|(_, segment)| segment).collect();
+        let proof = MLProofForR1CS::prove_linked(self.pk.clone(), self.v, w, &segments, self.pp, &mut self.transcript)?;
+        Ok(MultiPhaseProof {
+            phase_commitments: segments.into_iter().map(|segment| segment.commitment).collect(),
+            proof,
+        })
+    }
+}
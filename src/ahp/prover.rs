@@ -7,13 +7,14 @@ use linear_sumcheck::ml_sumcheck::ahp::prover::{
 use linear_sumcheck::ml_sumcheck::ahp::verifier::VerifierMsg as MLVerifierMsg;
 use linear_sumcheck::ml_sumcheck::ahp::AHPForMLSumcheck;
 
-use crate::ahp::indexer::IndexPK;
+use crate::ahp::indexer::{IndexPK, VerifierIndex};
 use crate::ahp::verifier::{
-    VerifierFifthMessage, VerifierFirstMessage, VerifierFourthMessage, VerifierSecondMessage,
-    VerifierThirdMessage,
+    VerifierFirstMessage, VerifierFourthMessage, VerifierSecondMessage, VerifierThirdMessage,
 };
 use crate::ahp::MLProofForR1CS;
-use crate::data_structures::eq::eq_extension;
+use crate::data_structures::eq::eq_table;
+use crate::zerocheck;
+use crate::data_structures::r1cs_reader::MatrixExtension;
 use crate::error::{invalid_arg, SResult};
 use ark_ec::PairingEngine;
 use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
@@ -21,164 +22,161 @@ use crate::commitment::commit::Commitment;
 use crate::ahp::setup::PublicParameter;
 use crate::commitment::MLPolyCommit;
 use crate::commitment::open::Proof;
-use ark_ff::Zero;
+use ark_ff::{One, Zero};
 pub struct ProverFirstState<E: PairingEngine> {
     pub v: Vec<E::Fr>,
     pub w: Vec<E::Fr>,
     pub pk: IndexPK<E::Fr>,
 }
 
+/// state after sending commitment to w
 pub struct ProverSecondState<E: PairingEngine> {
-    pub v: Vec<E::Fr>,
-    pub w: Vec<E::Fr>,
-    pub pk: IndexPK<E::Fr>,
-    z: MLExtensionArray<E::Fr>
-}
-
-/// state after sending commitment and z_rv_0
-pub struct ProverThirdState<E: PairingEngine> {
     pub pk: IndexPK<E::Fr>,
-    z: MLExtensionArray<E::Fr>,
+    /// table of z
+    z: Vec<E::Fr>,
+    w: MLExtensionArray<E::Fr>,
 }
 
 /// state when prover is doing first sumcheck
 pub struct ProverFirstSumcheckState<E: PairingEngine> {
     pub pk: IndexPK<E::Fr>,
-    z: MLExtensionArray<E::Fr>,
-    sum_az_over_y: MLExtensionArray<E::Fr>,
-    sum_bz_over_y: MLExtensionArray<E::Fr>,
-    sum_cz_over_y: MLExtensionArray<E::Fr>,
+    z: Vec<E::Fr>,
+    w: MLExtensionArray<E::Fr>,
+    /// tables of M_i z
+    mz: Vec<MLExtensionArray<E::Fr>>,
     ml_prover_state: MLProverState<E::Fr>,
 }
 
-pub struct ProverFifthState<E: PairingEngine> {
+pub struct ProverFourthState<E: PairingEngine> {
     pub pk: IndexPK<E::Fr>,
-    z: MLExtensionArray<E::Fr>,
+    z: Vec<E::Fr>,
+    w: MLExtensionArray<E::Fr>,
     r_x: Vec<E::Fr>,
 }
 
 pub struct ProverSecondSumcheckState<E: PairingEngine> {
-    z: MLExtensionArray<E::Fr>,
+    w: MLExtensionArray<E::Fr>,
+    r_x: Vec<E::Fr>,
     ml_prover_state: MLProverState<E::Fr>,
 }
 
-/// first message is the commitment
-#[derive(CanonicalSerialize, CanonicalDeserialize)]
-pub struct ProverFirstMessage<E: PairingEngine> {
-    pub commitment: Commitment<E>
+impl<E: PairingEngine> ProverSecondSumcheckState<E> {
+    /// (r_x, r_y), the point where the matrices are evaluated by the verifier, after receiving the
+    /// last randomness of the second sumcheck
+    pub fn evaluation_point(&self, v_msg: &VerifierFourthMessage<E::Fr>) -> (Vec<E::Fr>, Vec<E::Fr>) {
+        let mut r_y = self.ml_prover_state.randomness.clone();
+        r_y.push(v_msg.last_random_point);
+        (self.r_x.clone(), r_y)
+    }
 }
 
+/// first message is the commitment to w
 #[derive(CanonicalSerialize, CanonicalDeserialize)]
-pub struct ProverSecondMessage<E: PairingEngine> {
-    pub z_rv_0: E::Fr,
-    pub proof_for_z_rv_0: Proof<E>
+pub struct ProverFirstMessage<E: PairingEngine> {
+    pub commitment: Commitment<E>
 }
 
 /// contains some sumcheck info
 #[derive(CanonicalSerialize, CanonicalDeserialize)]
-pub struct ProverThirdMessage {
+pub struct ProverSecondMessage {
     pub ml_index_info: MLIndexInfo,
 }
 
-/// va, vb, vc
+/// M_i z(r_x) for each matrix M_i
 #[derive(CanonicalSerialize, CanonicalDeserialize)]
-pub struct ProverFourthMessage<E: PairingEngine> {
-    pub va: E::Fr,
-    pub vb: E::Fr,
-    pub vc: E::Fr,
+pub struct ProverThirdMessage<E: PairingEngine> {
+    pub evaluations: Vec<E::Fr>,
 }
 
 /// information for second sumcheck
 #[derive(CanonicalSerialize, CanonicalDeserialize)]
-pub struct ProverFifthMessage {
+pub struct ProverFourthMessage {
     pub index_info: MLIndexInfo,
 }
 
-/// z(r_y)
+/// w(r_y[..log_n - 1]). The verifier computes z(r_y) from it and the public input.
 #[derive(CanonicalSerialize, CanonicalDeserialize)]
-pub struct ProverSixthMessage<E: PairingEngine> {
-    pub z_ry: E::Fr,
-    pub proof_for_z_ry: Proof<E>
+pub struct ProverFifthMessage<E: PairingEngine> {
+    pub w_ry: E::Fr,
+    pub proof_for_w_ry: Proof<E>
 }
 /// final message
-pub type ProverFinalMessage<E> = ProverSixthMessage<E>;
+pub type ProverFinalMessage<E> = ProverFifthMessage<E>;
 
 impl<E: PairingEngine> MLProofForR1CS<E> {
     /// initialize the prover
-    /// * `v`: public input, whose size should be power of 2
+    /// * `v`: public input, whose first element should be one
+    /// * `w`: private input
+    ///
+    /// z is `v || w`, where both halves are padded with zeros to `2^(log_n - 1)`
     pub fn prover_init(
         pk: IndexPK<E::Fr>,
         v: Vec<E::Fr>,
         w: Vec<E::Fr>,
     ) -> SResult<ProverFirstState<E>> {
-        if !v.len().is_power_of_two() {
-            return Err(invalid_arg("public input should be power of two"));
+        let (v, w) = Self::pad_instance(&pk, v, w)?;
+        Ok(ProverFirstState { v, w, pk })
+    }
+
+    /// check the sizes of `v` and `w` and pad both to `2^(log_n - 1)`
+    pub(crate) fn pad_instance<K: VerifierIndex<E::Fr>>(
+        pk: &K,
+        mut v: Vec<E::Fr>,
+        mut w: Vec<E::Fr>,
+    ) -> SResult<(Vec<E::Fr>, Vec<E::Fr>)> {
+        if v.len() != pk.num_instance_variables() {
+            return Err(invalid_arg("public input has wrong length"));
         }
-        if v.len() + w.len() != pk.matrix_a.num_constraints {
-            return Err(invalid_arg("|v| + |w| != number of variables"));
+        if !v[0].is_one() {
+            return Err(invalid_arg("first public input should be one"));
         }
-        Ok(ProverFirstState { v, w, pk })
+        let half = 1 << (pk.log_n() - 1);
+        if v.len() > half {
+            return Err(invalid_arg("|v| > number of public input variables"));
+        }
+        if w.len() > half {
+            return Err(invalid_arg("|w| > number of witness variables"));
+        }
+        v.resize(half, E::Fr::zero());
+        w.resize(half, E::Fr::zero());
+        Ok((v, w))
     }
-    /// send commitment
+    /// send commitment to w
     pub fn prover_first_round(
         state: ProverFirstState<E>,
         pp: &PublicParameter<E>
     ) -> Result<(ProverSecondState<E>, ProverFirstMessage<E>), crate::Error> {
-        let z =
-            MLExtensionArray::from_vec(state.v.iter().chain(state.w.iter()).map(|x| *x).collect())?;
-        let commitment = MLPolyCommit::commit(pp, z.clone())?;
+        let z: Vec<_> = state.v.iter().chain(state.w.iter()).map(|x| *x).collect();
+        let w = MLExtensionArray::from_vec(state.w)?;
+        let commitment = MLPolyCommit::commit(pp, w.clone())?;
         Ok((
             ProverSecondState {
-                v: state.v,
-                w: state.w,
                 pk: state.pk,
-                z
+                z,
+                w,
             },
             ProverFirstMessage {
                 commitment,
             },
         ))
     }
-    /// receive r_v, send z_rv_0
+    /// Receive random tor from verifier and prepare for the first sumcheck.
+    /// send sumcheck index information
     pub fn prover_second_round(
         state: ProverSecondState<E>,
         v_msg: VerifierFirstMessage<E::Fr>,
-        pp: &PublicParameter<E>
-    ) -> Result<(ProverThirdState<E>, ProverSecondMessage<E>), crate::Error> {
-        let pk = state.pk;
-        let z = state.z;
-        let mut r_v = v_msg.r_v;
-        // extend r_v with zero
-        r_v.extend((0..(z.num_variables()? - ark_std::log2(state.v.len()) as usize)).map(|_|E::Fr::zero()));
-        let (z_rv_0, proof, _) = MLPolyCommit::open(pp, z.clone(), &r_v)?;
-        let state = ProverThirdState { pk, z };
-        let msg = ProverSecondMessage {
-            z_rv_0,
-            proof_for_z_rv_0: proof,
-        };
-        Ok((state, msg))
-    }
-    /// Receive random tor from verifier and prepare for the first sumcheck.
-    /// send sumcheck index information
-    pub fn prover_third_round(
-        state: ProverThirdState<E>,
-        v_msg: VerifierSecondMessage<E::Fr>,
-    ) -> Result<(ProverFirstSumcheckState<E>, ProverThirdMessage), crate::Error> {
-        let tor = v_msg.tor;
-        let eq = eq_extension(&tor)?;
+    ) -> Result<(ProverFirstSumcheckState<E>, ProverSecondMessage), crate::Error> {
         let pk = state.pk;
         let z = state.z;
-        let sum_az_over_y = pk.matrix_a.sum_over_y(&z)?;
-        let sum_bz_over_y = pk.matrix_b.sum_over_y(&z)?;
-        let sum_cz_over_y = pk.matrix_c.sum_over_y(&z)?;
+        let mz = pk
+            .matrices
+            .iter()
+            .map(|matrix| matrix.sum_over_y(&z))
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let mut g_zt_x_first = vec![sum_az_over_y.clone(), sum_bz_over_y.clone()];
-        g_zt_x_first.extend(eq.iter().map(|mle| mle.clone()));
-        let mut g_zt_x_second = vec![sum_cz_over_y.negate()?];
-        g_zt_x_second.extend(eq.iter().map(|mle| mle.clone()));
-        let mut g_zt_x = ArithmeticCombination::new(pk.log_n);
-        g_zt_x.add_product(g_zt_x_first.into_iter())?;
-        g_zt_x.add_product(g_zt_x_second.into_iter())?;
+        // zerocheck of sum_j c_j prod_{i in S_j} M_i z: eq(tor, x) is one dense table, so the sumcheck polynomial
+        // has degree one more than the constraints
+        let g_zt_x = zerocheck::masked_combination(&v_msg.tor, &mz, &pk.products()?)?;
         let ml_index = AHPForMLSumcheck::convert_to_index(g_zt_x)?;
         let ml_index_info = ml_index.info();
         let ml_prover_state = AHPForMLSumcheck::prover_init(&ml_index);
@@ -186,12 +184,11 @@ impl<E: PairingEngine> MLProofForR1CS<E> {
         let next_state = ProverFirstSumcheckState {
             pk,
             z,
-            sum_az_over_y,
-            sum_bz_over_y,
-            sum_cz_over_y,
+            w: state.w,
+            mz,
             ml_prover_state,
         };
-        let msg = ProverThirdMessage { ml_index_info };
+        let msg = ProverSecondMessage { ml_index_info };
         Ok((next_state, msg))
     }
 
@@ -206,48 +203,51 @@ impl<E: PairingEngine> MLProofForR1CS<E> {
         Ok((state, mlp_msg))
     }
 
-    /// verifier send the final point, prover send va, vb, vc
-    pub fn prove_fourth_round(
+    /// verifier send the final point, prover send M_i z(r_x)
+    pub fn prove_third_round(
         state: ProverFirstSumcheckState<E>,
-        v_msg: VerifierThirdMessage<E::Fr>,
-    ) -> Result<(ProverFifthState<E>, ProverFourthMessage<E>), crate::Error> {
+        v_msg: VerifierSecondMessage<E::Fr>,
+    ) -> Result<(ProverFourthState<E>, ProverThirdMessage<E>), crate::Error> {
         let mut r_x = state.ml_prover_state.randomness;
         r_x.push(v_msg.last_random_point);
 
-        let va = state.sum_az_over_y.eval_at(&r_x)?;
-        let vb = state.sum_bz_over_y.eval_at(&r_x)?;
-        let vc = state.sum_cz_over_y.eval_at(&r_x)?;
+        let evaluations = state
+            .mz
+            .iter()
+            .map(|table| table.eval_at(&r_x))
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let next_state = ProverFifthState {
+        let next_state = ProverFourthState {
             z: state.z,
+            w: state.w,
             pk: state.pk,
             r_x,
         };
-        let msg = ProverFourthMessage { va, vb, vc };
+        let msg = ProverThirdMessage { evaluations };
         Ok((next_state, msg))
     }
-    /// receive ra, rb, rc, and prepare for second sumcheck
-    pub fn prove_fifth_round(
-        state: ProverFifthState<E>,
-        v_msg: VerifierFourthMessage<E::Fr>,
-    ) -> Result<(ProverSecondSumcheckState<E>, ProverFifthMessage), crate::Error> {
-        let r_a = v_msg.r_a;
-        let r_b = v_msg.r_b;
-        let r_c = v_msg.r_c;
+    /// receive the coefficients r_i of the matrices, and prepare for second sumcheck
+    pub fn prove_fourth_round(
+        state: ProverFourthState<E>,
+        v_msg: VerifierThirdMessage<E::Fr>,
+    ) -> Result<(ProverSecondSumcheckState<E>, ProverFourthMessage), crate::Error> {
         let r_x = state.r_x;
         let z = state.z;
-        let az_rx_on_y = vec![state.pk.matrix_a.eval_on_x(&r_x)?.multiply(r_a)?, z.clone()];
-        let bz_rx_on_y = vec![state.pk.matrix_b.eval_on_x(&r_x)?.multiply(r_b)?, z.clone()];
-        let cz_rx_on_y = vec![state.pk.matrix_c.eval_on_x(&r_x)?.multiply(r_c)?, z.clone()];
-        let mut round2_poly = ArithmeticCombination::new(state.pk.log_n);
-        round2_poly.add_product(az_rx_on_y.into_iter())?;
-        round2_poly.add_product(bz_rx_on_y.into_iter())?;
-        round2_poly.add_product(cz_rx_on_y.into_iter())?;
+        let pk = &state.pk;
+        if v_msg.coefficients.len() != pk.matrices.len() {
+            return Err(invalid_arg("should have one coefficient for each matrix"));
+        }
+        // M = sum_i r_i * M_i, so the second sumcheck is on the two factors M(r_x, y) * z(y)
+        let eq_rx = eq_table(&r_x);
+        let combination: Vec<_> = v_msg.coefficients.into_iter().zip(pk.matrices.iter()).collect();
+        let m_rx_on_y = MatrixExtension::eval_combination_on_x(&combination, &eq_rx)?;
+        let mut round2_poly = ArithmeticCombination::new(pk.log_n);
+        round2_poly.add_product(vec![m_rx_on_y, MLExtensionArray::from_vec(z)?].into_iter())?;
         let index = AHPForMLSumcheck::convert_to_index(round2_poly)?;
         let ml_prover_state = AHPForMLSumcheck::prover_init(&index);
 
-        let next_state = ProverSecondSumcheckState { z, ml_prover_state };
-        let msg = ProverFifthMessage {
+        let next_state = ProverSecondSumcheckState { w: state.w, r_x, ml_prover_state };
+        let msg = ProverFourthMessage {
             index_info: index.info(),
         };
 
@@ -264,18 +264,20 @@ impl<E: PairingEngine> MLProofForR1CS<E> {
         state.ml_prover_state = new_prover_state;
         Ok((state, mlp_msg))
     }
-    /// final round: send z(r_y) and its corresponding proof
-    pub fn prove_sixth_round(
+    /// final round: send w(r_y[..log_n - 1]) and its corresponding proof
+    pub fn prove_fifth_round(
         state: ProverSecondSumcheckState<E>,
-        v_msg: VerifierFifthMessage<E::Fr>,
+        v_msg: VerifierFourthMessage<E::Fr>,
         pp: &PublicParameter<E>
     ) -> Result<ProverFinalMessage<E>, crate::Error> {
         let mut r_y = state.ml_prover_state.randomness;
         r_y.push(v_msg.last_random_point);
-        let (z_ry, proof_for_z_ry, _) = MLPolyCommit::open(&pp,state.z, &r_y)?;
+        // z(r_y) = (1 - r_y[last]) * v(r_y[..last]) + r_y[last] * w(r_y[..last])
+        let r_w = &r_y[..r_y.len() - 1];
+        let (w_ry, proof_for_w_ry, _) = MLPolyCommit::open(&pp, state.w, r_w)?;
         let msg = ProverFinalMessage {
-            z_ry,
-            proof_for_z_ry,
+            w_ry,
+            proof_for_w_ry,
         };
         Ok(msg)
     }
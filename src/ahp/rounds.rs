@@ -0,0 +1,150 @@
+//! Drivers running all rounds of the prover and the verifier against a transcript, shared by the arguments
+//! built on top of the protocol.
+
+use crate::ahp::indexer::{IndexPK, VerifierIndex};
+use crate::ahp::prover::ProverFinalMessage;
+use crate::ahp::setup::PublicParameter;
+use crate::ahp::verifier::VerifierFifthState;
+use crate::ahp::MLProofForR1CS;
+use crate::data_structures::proof::Proof;
+use crate::data_structures::transcript::{Transcript, TranscriptHash};
+use crate::error::{invalid_arg, SResult};
+use ark_ec::PairingEngine;
+use ark_std::collections::LinkedList;
+use ark_std::iter::FromIterator;
+use linear_sumcheck::ml_sumcheck::ahp::prover::ProverMsg;
+
+impl<E: PairingEngine> MLProofForR1CS<E> {
+    /// run all rounds of the protocol, and also return (r_x, r_y)
+    pub(crate) fn prove_rounds<H: TranscriptHash>(
+        pk: IndexPK<E::Fr>,
+        v: Vec<E::Fr>,
+        w: Vec<E::Fr>,
+        pp: &PublicParameter<E>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<(Proof<E>, (Vec<E::Fr>, Vec<E::Fr>))> {
+        let log_m = pk.log_m;
+        let log_n = pk.log_n;
+
+        let ps = Self::prover_init(pk, v, w)?;
+
+        let timer = start_timer!(||"Prove 1");
+        let (ps, pm1) = Self::prover_first_round(ps, pp)?;
+        end_timer!(timer);
+        let vm = Self::sample_first_round(log_m, &pm1, transcript)?;
+
+        let timer = start_timer!(||"Prove 2");
+        let (mut ps, pm2) = Self::prover_second_round(ps, vm)?;
+        end_timer!(timer);
+        let mut vm = Self::sample_second_round(&pm2, transcript)?;
+
+        let timer = start_timer!(||"Prove Sumcheck 1");
+        let mut sumcheck1_msgs = Vec::with_capacity(log_m);
+        for _ in 0..(log_m - 1) {
+            let (ps_new, pm) = Self::prove_first_sumcheck_round(ps, vm)?;
+            ps = ps_new;
+            vm = Self::sample_verify_first_sumcheck_ongoing_round(&pm, transcript)?;
+            sumcheck1_msgs.push(pm);
+        }
+
+        let (ps, pm) = Self::prove_first_sumcheck_round(ps, vm)?;
+        end_timer!(timer);
+        let vm = Self::sample_verify_first_sumcheck_final_round(&pm, transcript)?;
+        sumcheck1_msgs.push(pm);
+
+        let timer = start_timer!(||"Prove 3");
+        let (ps, pm3) = Self::prove_third_round(ps, vm)?;
+        end_timer!(timer);
+        let vm = Self::sample_verify_third_round(&pm3, transcript)?;
+
+        let timer = start_timer!(||"Prove 4");
+        let (mut ps, pm4) = Self::prove_fourth_round(ps, vm)?;
+        end_timer!(timer);
+        let mut vm = Self::sample_verify_fourth_round(&pm4, transcript)?;
+
+        let mut sumcheck2_msgs = Vec::with_capacity(log_n);
+        let timer = start_timer!(||"Prove Sumcheck 2");
+        for _ in 0..(log_n - 1) {
+            let (ps_new, pm) = Self::prove_second_sumcheck_round(ps, vm)?;
+            ps = ps_new;
+            vm = Self::sample_verify_second_sumcheck_ongoing_round(&pm, transcript)?;
+            sumcheck2_msgs.push(pm);
+        }
+
+        let (ps, pm) = Self::prove_second_sumcheck_round(ps, vm)?;
+        end_timer!(timer);
+        let vm = Self::sample_verify_second_sumcheck_final_round(&pm, transcript)?;
+        sumcheck2_msgs.push(pm);
+
+        let evaluation_point = ps.evaluation_point(&vm);
+        let timer = start_timer!(||"Prove 5");
+        let pm5 = Self::prove_fifth_round(ps, vm, pp)?;
+        end_timer!(timer);
+        Ok((Proof {
+            prover_first_message: pm1,
+            prover_second_message: pm2,
+            first_sumcheck_messages: sumcheck1_msgs,
+            prover_third_message: pm3,
+            prover_fourth_message: pm4,
+            second_sumcheck_messages: sumcheck2_msgs,
+            prover_fifth_message: pm5,
+        }, evaluation_point))
+    }
+
+    /// run all rounds of the verifier, where the final claim is checked by `final_round`
+    pub(crate) fn verify_with<H, I, F, T>(vk: I, v: Vec<E::Fr>, proof: Proof<E>, transcript: &mut Transcript<H>, final_round: F) -> SResult<T>
+    where
+        H: TranscriptHash,
+        I: VerifierIndex<E::Fr>,
+        F: FnOnce(VerifierFifthState<E, I>, ProverFinalMessage<E>, &mut Transcript<H>) -> SResult<T>,
+    {
+        let log_m = vk.log_m();
+        let log_n = vk.log_n();
+        let mut first_sumcheck_messages =
+            LinkedList::from_iter(proof.first_sumcheck_messages.into_iter());
+        let mut second_sumcheck_messages =
+            LinkedList::from_iter(proof.second_sumcheck_messages.into_iter());
+
+        let vs = Self::verifier_init(vk, v)?;
+
+        let pm = proof.prover_first_message;
+        let (vs, _) = Self::verify_first_round(vs, pm, transcript)?;
+
+        let pm = proof.prover_second_message;
+        let (mut vs, _) = Self::verify_second_round(vs, pm, transcript)?;
+
+        for _ in 0..(log_m - 1) {
+            let pm = Self::try_pop(&mut first_sumcheck_messages)?;
+            let (vs_new, _) =
+                Self::verify_first_sumcheck_ongoing_round(vs, pm, transcript)?;
+            vs = vs_new;
+        }
+
+        let pm = Self::try_pop(&mut first_sumcheck_messages)?;
+        let (vs, _) = Self::verify_first_sumcheck_final_round(vs, pm, transcript)?;
+
+        let pm = proof.prover_third_message;
+        let (vs, _) = Self::verify_third_round(vs, pm, transcript)?;
+
+        let pm = proof.prover_fourth_message;
+        let (mut vs, _) = Self::verify_fourth_round(vs, pm, transcript)?;
+
+        for _ in 0..(log_n - 1) {
+            let pm = Self::try_pop(&mut second_sumcheck_messages)?;
+            let (vs_new, _) =
+                Self::verify_second_sumcheck_ongoing_round(vs, pm, transcript)?;
+            vs = vs_new;
+        }
+
+        let pm = Self::try_pop(&mut second_sumcheck_messages)?;
+        let (vs, _) = Self::verify_second_sumcheck_final_round(vs, pm, transcript)?;
+
+        final_round(vs, proof.prover_fifth_message, transcript)
+    }
+
+    fn try_pop(sumcheck_messages: &mut LinkedList<ProverMsg<E::Fr>>) -> SResult<ProverMsg<E::Fr>> {
+        sumcheck_messages
+            .pop_front()
+            .ok_or(invalid_arg("malformed sumcheck message"))
+    }
+}
@@ -6,6 +6,8 @@ use crate::error::SResult;
 
 pub type PublicParameter<E> = crate::commitment::data_structures::PublicParameter<E>;
 pub type VerifierParameter<E> = crate::commitment::data_structures::VerifierParameter<E>;
+pub type DesignatedVerifierParameter<E> = crate::commitment::data_structures::DesignatedVerifierParameter<E>;
+pub type SetupIdentifier = crate::commitment::data_structures::SetupIdentifier;
 
 impl<E: PairingEngine> MLProofForR1CS<E> {
 
@@ -14,4 +16,13 @@ impl<E: PairingEngine> MLProofForR1CS<E> {
         let (pp, vp, _) = MLPolyCommit::keygen(nv, rng)?;
         Ok((pp, vp))
     }
+
+    /// Setup public parameter, verifier parameter, and the designated verifier parameter which keeps the trapdoor.
+    /// Only use the designated verifier parameter when the verifier is the one running setup.
+    pub fn setup_designated<R: RngCore>(nv: usize, rng: &mut R)
+        -> SResult<(PublicParameter<E>, VerifierParameter<E>, DesignatedVerifierParameter<E>)> {
+        let (pp, vp, t) = MLPolyCommit::keygen(nv, rng)?;
+        let dvp = DesignatedVerifierParameter::new(&vp, t);
+        Ok((pp, vp, dvp))
+    }
 }
\ No newline at end of file
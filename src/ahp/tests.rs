@@ -1,66 +1,79 @@
 use crate::ahp::MLProofForR1CS;
 use crate::error::SResult;
-use crate::test_utils::{generate_circuit_with_random_input, TestCurve, TestCurveFr};
-use ark_ff::test_rng;
+use crate::test_utils::{chain_circuit_with_instances, generate_circuit_with_random_input, serialization_round_trip, TestCurve, TestCurveFr};
+use ark_ff::{test_rng, One, UniformRand, Zero};
 use rand::RngCore;
 use crate::commitment::MLPolyCommit;
+use crate::commitment::accumulator::PairingAccumulator;
+use crate::data_structures::proof::Proof;
+use crate::ahp::multi_phase::PhaseLayout;
+use ark_relations::r1cs::Matrix;
+use crate::ahp::commit_and_prove::CommittedSegment;
+use crate::MLArgumentForR1CS;
+use crate::ahp::indexer::{CircuitId, IndexPK, VerifierIndex};
+use crate::ahp::setup::{PublicParameter, VerifierParameter};
+use crate::ahp::verifier::VerifierFirstMessage;
+use crate::data_structures::transcript::{
+    Keccak256Hash, PoseidonHash, Sha256Hash, Transcript, TranscriptHash,
+};
+use linear_sumcheck::data_structures::{Blake2s512Rng, MLExtensionArray};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 
 fn test_circuit<R: RngCore>(log_n: usize, log_v: usize, rng: &mut R) -> SResult<()> {
     let num_public = 1 << log_v;
     let num_private = (1 << log_n) - num_public;
 
-    let (pp, vp, _) = MLPolyCommit::keygen(log_n, rng)?;
-
     let (r1cs, v, w) =
         generate_circuit_with_random_input::<TestCurveFr, _>(num_public, num_private, true, 1, rng);
 
     let matrices = r1cs.to_matrices().unwrap();
-    let pk = MLProofForR1CS::<TestCurve>::index(matrices.a, matrices.b, matrices.c)?;
+    let pk = MLProofForR1CS::<TestCurve>::index(matrices.a, matrices.b, matrices.c, matrices.num_instance_variables, matrices.num_witness_variables)?;
 
     let vk = pk.vk();
+    let log_m = pk.log_m;
+    let log_n = pk.log_n;
+    let (pp, vp, _) = MLPolyCommit::keygen(log_n - 1, rng)?;
 
+    let mut transcript: Transcript = MLProofForR1CS::init_transcript(vp.id(), &vk, &v)?;
     let ps = MLProofForR1CS::<TestCurve>::prover_init(pk, v.to_vec(), w)?;
     let vs = MLProofForR1CS::verifier_init(vk, v)?;
 
     let (ps, pm) = MLProofForR1CS::prover_first_round(ps, &pp)?;
-    let (vs, vm) = MLProofForR1CS::verify_first_round(vs, pm, rng)?;
-
-    let (ps, pm) = MLProofForR1CS::prover_second_round(ps, vm, &pp)?;
-    let (vs, vm) = MLProofForR1CS::verify_second_round(vs, pm, rng)?;
+    let (vs, vm) = MLProofForR1CS::verify_first_round(vs, pm, &mut transcript)?;
 
-    let (mut ps, pm) = MLProofForR1CS::prover_third_round(ps, vm)?;
-    let (mut vs, mut vm) = MLProofForR1CS::verify_third_round(vs, pm)?;
+    let (mut ps, pm) = MLProofForR1CS::prover_second_round(ps, vm)?;
+    let (mut vs, mut vm) = MLProofForR1CS::verify_second_round(vs, pm, &mut transcript)?;
 
-    for _ in 0..(log_n - 1) {
+    for _ in 0..(log_m - 1) {
         let (ps_new, pm) = MLProofForR1CS::prove_first_sumcheck_round(ps, vm)?;
         ps = ps_new;
-        let (vs_new, vm_new) = MLProofForR1CS::verify_first_sumcheck_ongoing_round(vs, pm, rng)?;
+        let (vs_new, vm_new) = MLProofForR1CS::verify_first_sumcheck_ongoing_round(vs, pm, &mut transcript)?;
         vs = vs_new;
         vm = vm_new;
     }
 
     let (ps, pm) = MLProofForR1CS::prove_first_sumcheck_round(ps, vm)?;
-    let (vs, vm) = MLProofForR1CS::verify_first_sumcheck_final_round(vs, pm, rng)?;
+    let (vs, vm) = MLProofForR1CS::verify_first_sumcheck_final_round(vs, pm, &mut transcript)?;
 
-    let (ps, pm) = MLProofForR1CS::prove_fourth_round(ps, vm)?;
-    let (vs, vm) = MLProofForR1CS::verify_fourth_round(vs, pm, rng)?;
+    let (ps, pm) = MLProofForR1CS::prove_third_round(ps, vm)?;
+    let (vs, vm) = MLProofForR1CS::verify_third_round(vs, pm, &mut transcript)?;
 
-    let (mut ps, pm) = MLProofForR1CS::prove_fifth_round(ps, vm)?;
-    let (mut vs, mut vm) = MLProofForR1CS::verify_fifth_round(vs, pm)?;
+    let (mut ps, pm) = MLProofForR1CS::prove_fourth_round(ps, vm)?;
+    let (mut vs, mut vm) = MLProofForR1CS::verify_fourth_round(vs, pm, &mut transcript)?;
 
     for _ in 0..(log_n - 1) {
         let (ps_new, pm) = MLProofForR1CS::prove_second_sumcheck_round(ps, vm)?;
         ps = ps_new;
-        let (vs_new, vm_new) = MLProofForR1CS::verify_second_sumcheck_ongoing_round(vs, pm, rng)?;
+        let (vs_new, vm_new) = MLProofForR1CS::verify_second_sumcheck_ongoing_round(vs, pm, &mut transcript)?;
         vs = vs_new;
         vm = vm_new;
     }
 
     let (ps, pm) = MLProofForR1CS::prove_second_sumcheck_round(ps, vm)?;
-    let (vs, vm) = MLProofForR1CS::verify_second_sumcheck_final_round(vs, pm, rng)?;
+    let (vs, vm) = MLProofForR1CS::verify_second_sumcheck_final_round(vs, pm, &mut transcript)?;
 
-    let pm = MLProofForR1CS::prove_sixth_round(ps, vm, &pp)?;
-    let result = MLProofForR1CS::verify_sixth_round(vs, pm, &vp)?;
+    let pm = MLProofForR1CS::prove_fifth_round(ps, vm, &pp)?;
+    let result = MLProofForR1CS::verify_fifth_round(vs, pm, &vp)?;
 
     if result {
         Ok(())
@@ -73,3 +86,650 @@ fn test_circuit<R: RngCore>(log_n: usize, log_v: usize, rng: &mut R) -> SResult<
 fn test_small() {
     test_circuit(8, 2, &mut test_rng()).expect("fail to test small");
 }
+
+#[test]
+fn test_designated_verifier() {
+    let mut rng = test_rng();
+    let log_n = 8;
+    let num_public = 1 << 2;
+    let (r1cs, v, w) = generate_circuit_with_random_input::<TestCurveFr, _>(
+        num_public,
+        (1 << log_n) - num_public,
+        true,
+        1,
+        &mut rng,
+    );
+    let matrices = r1cs.to_matrices().unwrap();
+    let pk = MLArgumentForR1CS::<TestCurve>::index(matrices.a, matrices.b, matrices.c, matrices.num_instance_variables, matrices.num_witness_variables).unwrap();
+    let (pp, vp, dvp) = MLProofForR1CS::<TestCurve>::setup_designated(pk.log_n, &mut rng).unwrap();
+    let vk = pk.vk();
+    let proof = MLArgumentForR1CS::<TestCurve>::prove(pk, v.clone(), w, &pp).unwrap();
+    let copy = || serialization_round_trip(&proof);
+
+    assert!(MLArgumentForR1CS::<TestCurve>::verify(vk.clone(), v.clone(), copy(), &vp).unwrap());
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_designated(vk.clone(), v.clone(), copy(), &dvp).unwrap());
+
+    // the transcript is bound to the setup
+    let (_, other_vp) = MLProofForR1CS::<TestCurve>::setup(vp.nv, &mut rng).unwrap();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify(vk, v, copy(), &other_vp).is_err());
+}
+
+#[test]
+fn test_setup_id_covers_mask() {
+    let mut rng = test_rng();
+    let (r1cs, v, _) = generate_circuit_with_random_input::<TestCurveFr, _>(4, 60, true, 1, &mut rng);
+    let matrices = r1cs.to_matrices().unwrap();
+    let vk = MLArgumentForR1CS::<TestCurve>::index(matrices.a, matrices.b, matrices.c, matrices.num_instance_variables, matrices.num_witness_variables).unwrap().vk();
+    let (pp, vp) = MLProofForR1CS::<TestCurve>::setup(vk.log_n, &mut rng).unwrap();
+    assert_eq!(pp.id(), vp.id());
+
+    // same nv, g and h, different g^t1
+    let mut g_mask_random = vp.g_mask_random.clone();
+    g_mask_random[0] = vp.g;
+    let other_vp = VerifierParameter::<TestCurve>::new(vp.nv, vp.g, vp.h, g_mask_random).unwrap();
+    assert_ne!(vp.id(), other_vp.id());
+
+    let mut transcript: Transcript = MLProofForR1CS::init_transcript(vp.id(), &vk, &v).unwrap();
+    let mut other_transcript: Transcript = MLProofForR1CS::init_transcript(other_vp.id(), &vk, &v).unwrap();
+    let challenge: TestCurveFr = transcript.challenge_scalar(b"challenge").unwrap();
+    let other_challenge: TestCurveFr = other_transcript.challenge_scalar(b"challenge").unwrap();
+    assert_ne!(challenge, other_challenge);
+}
+
+#[test]
+fn test_preprocessed() {
+    let mut rng = test_rng();
+    let log_n = 6;
+    let num_public = 1 << 2;
+    let (r1cs, v, w) = generate_circuit_with_random_input::<TestCurveFr, _>(
+        num_public,
+        (1 << log_n) - num_public,
+        true,
+        1,
+        &mut rng,
+    );
+    let matrices = r1cs.to_matrices().unwrap();
+    let pk = MLArgumentForR1CS::<TestCurve>::index(matrices.a, matrices.b, matrices.c, matrices.num_instance_variables, matrices.num_witness_variables).unwrap();
+    let nv = MLProofForR1CS::<TestCurve>::num_variables_for_preprocessing(&pk);
+    let (pp, vp) = MLProofForR1CS::<TestCurve>::setup(nv, &mut rng).unwrap();
+    let pk = MLArgumentForR1CS::<TestCurve>::preprocess(pk, &pp).unwrap();
+    let vk = pk.vk();
+    let proof = MLArgumentForR1CS::<TestCurve>::prove_preprocessed(pk, v.clone(), w, &pp).unwrap();
+    let copy = || serialization_round_trip(&proof);
+
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_preprocessed(vk.clone(), v.clone(), copy(), &vp).unwrap());
+
+    // wrong public input should be rejected
+    let mut wrong_v = v;
+    wrong_v[1] += TestCurveFr::one();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_preprocessed(vk, wrong_v, copy(), &vp).is_err());
+}
+
+#[test]
+fn test_preprocessed_spark_round_trip() {
+    let mut rng = test_rng();
+    let num_public = 1 << 3;
+    let (r1cs, v, w) =
+        generate_circuit_with_random_input::<TestCurveFr, _>(num_public, (1 << 7) - num_public, true, 2, &mut rng);
+    let matrices = r1cs.to_matrices().unwrap();
+    let pk = MLArgumentForR1CS::<TestCurve>::index(matrices.a, matrices.b, matrices.c, matrices.num_instance_variables, matrices.num_witness_variables).unwrap();
+    let nv = MLProofForR1CS::<TestCurve>::num_variables_for_preprocessing(&pk);
+    let (pp, vp) = MLProofForR1CS::<TestCurve>::setup(nv, &mut rng).unwrap();
+    let pk = MLArgumentForR1CS::<TestCurve>::preprocess(pk, &pp).unwrap();
+    let vk = serialization_round_trip(&pk.vk());
+    let proof = MLArgumentForR1CS::<TestCurve>::prove_preprocessed(pk, v.clone(), w, &pp).unwrap();
+    assert_eq!(proof.matrix_evaluation_proof.evaluations.len(), 3);
+    let copy = || serialization_round_trip(&proof);
+    let verify = |proof| MLArgumentForR1CS::<TestCurve>::verify_preprocessed(vk.clone(), v.clone(), proof, &vp);
+
+    assert!(verify(copy()).unwrap());
+
+    // every part of the spark proof is checked by the preprocessed verifier
+    let mut wrong = copy();
+    wrong.matrix_evaluation_proof.evaluations[0] += TestCurveFr::one();
+    assert!(verify(wrong).is_err());
+    let mut wrong = copy();
+    wrong.matrix_evaluation_proof.sumcheck_evaluations[1] += TestCurveFr::one();
+    assert!(verify(wrong).is_err());
+    let mut wrong = copy();
+    wrong.matrix_evaluation_proof.read_write_evaluations[2] += TestCurveFr::one();
+    assert!(verify(wrong).is_err());
+    let mut wrong = copy();
+    wrong.matrix_evaluation_proof.row_audit_evaluations[0] += TestCurveFr::one();
+    assert!(verify(wrong).is_err());
+    let mut wrong = copy();
+    wrong.matrix_evaluation_proof.col_audit_evaluations[0] += TestCurveFr::one();
+    assert!(verify(wrong).is_err());
+}
+
+#[test]
+fn test_non_power_of_two() {
+    let mut rng = test_rng();
+    let num_public = 1 << 2;
+    // 50 constraints and 54 variables, without padding
+    let (r1cs, v, w) =
+        generate_circuit_with_random_input::<TestCurveFr, _>(num_public, 50, false, 1, &mut rng);
+    let matrices = r1cs.to_matrices().unwrap();
+    let pk = MLArgumentForR1CS::<TestCurve>::index(matrices.a, matrices.b, matrices.c, matrices.num_instance_variables, matrices.num_witness_variables).unwrap();
+    assert_eq!(pk.log_n, 7);
+    let vk = pk.vk();
+    let (pp, vp) = MLProofForR1CS::<TestCurve>::setup(pk.log_n, &mut rng).unwrap();
+    let proof = MLArgumentForR1CS::<TestCurve>::prove(pk, v.clone(), w, &pp).unwrap();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify(vk, v, proof, &vp).unwrap());
+}
+
+#[test]
+fn test_rectangular() {
+    let mut rng = test_rng();
+    let num_public = 1 << 2;
+    let (r1cs, v, mut w) =
+        generate_circuit_with_random_input::<TestCurveFr, _>(num_public, 200, false, 1, &mut rng);
+    let matrices = r1cs.to_matrices().unwrap();
+    // 1024 variables, where the last witness variables appear in no constraint
+    w.resize(1024 - num_public, TestCurveFr::one());
+    assert!(MLArgumentForR1CS::<TestCurve>::index(matrices.a.clone(), matrices.b.clone(), matrices.c.clone(), num_public, 10).is_err());
+
+    let pk = MLArgumentForR1CS::<TestCurve>::index(matrices.a, matrices.b, matrices.c, num_public, w.len()).unwrap();
+    assert_eq!(pk.log_m, 8);
+    assert_eq!(pk.log_n, 11);
+    let vk = pk.vk();
+    let (pp, vp) = MLProofForR1CS::<TestCurve>::setup(pk.log_n, &mut rng).unwrap();
+    let proof = MLArgumentForR1CS::<TestCurve>::prove(pk, v.clone(), w, &pp).unwrap();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify(vk, v, proof, &vp).unwrap());
+}
+
+#[test]
+fn test_public_input_layout() {
+    let mut rng = test_rng();
+    // 5 public inputs, padded to 8
+    let (r1cs, v, w) =
+        generate_circuit_with_random_input::<TestCurveFr, _>(5, 50, false, 1, &mut rng);
+    let matrices = r1cs.to_matrices().unwrap();
+    assert_eq!(v.len(), 5);
+    let pk = MLArgumentForR1CS::<TestCurve>::index(matrices.a, matrices.b, matrices.c, v.len(), w.len()).unwrap();
+    let vk = pk.vk();
+    let (pp, vp) = MLProofForR1CS::<TestCurve>::setup(pk.log_n, &mut rng).unwrap();
+    let proof = MLArgumentForR1CS::<TestCurve>::prove(pk.clone(), v.clone(), w.clone(), &pp).unwrap();
+    let copy = || serialization_round_trip(&proof);
+    assert!(MLArgumentForR1CS::<TestCurve>::verify(vk.clone(), v.clone(), copy(), &vp).unwrap());
+
+    // public input of another layout
+    let mut longer_v = v.clone();
+    longer_v.push(TestCurveFr::zero());
+    assert!(MLArgumentForR1CS::<TestCurve>::verify(vk.clone(), longer_v, copy(), &vp).is_err());
+
+    // first public input is not one
+    let mut wrong_v = v;
+    wrong_v[0] = TestCurveFr::zero();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify(vk, wrong_v.clone(), copy(), &vp).is_err());
+    assert!(MLArgumentForR1CS::<TestCurve>::prove(pk, wrong_v, w, &pp).is_err());
+}
+
+/// circuit and setup shared by the transcript hash tests
+fn hash_test_instance() -> (IndexPK<TestCurveFr>, Vec<TestCurveFr>, Vec<TestCurveFr>, PublicParameter<TestCurve>, VerifierParameter<TestCurve>) {
+    let mut rng = test_rng();
+    let (r1cs, v, w) =
+        generate_circuit_with_random_input::<TestCurveFr, _>(4, 60, true, 1, &mut rng);
+    let matrices = r1cs.to_matrices().unwrap();
+    let pk = MLProofForR1CS::<TestCurve>::index(matrices.a, matrices.b, matrices.c, matrices.num_instance_variables, matrices.num_witness_variables).unwrap();
+    let (pp, vp) = MLProofForR1CS::<TestCurve>::setup(pk.log_n, &mut rng).unwrap();
+    (pk, v, w, pp, vp)
+}
+
+fn prove_with_hash<H: TranscriptHash>() -> Vec<u8> {
+    let (pk, v, w, pp, _) = hash_test_instance();
+    let proof = MLArgumentForR1CS::<TestCurve, H>::prove(pk, v, w, &pp).unwrap();
+    let mut data: Vec<u8> = Vec::new();
+    proof.serialize(&mut data).unwrap();
+    data
+}
+
+fn verify_with_hash<H: TranscriptHash>(proof: &[u8]) -> bool {
+    let (pk, v, _, _, vp) = hash_test_instance();
+    let proof = Proof::<TestCurve>::deserialize(proof).unwrap();
+    MLArgumentForR1CS::<TestCurve, H>::verify(pk.vk(), v, proof, &vp).unwrap_or(false)
+}
+
+#[test]
+fn test_transcript_hashes() {
+    let proofs = [
+        prove_with_hash::<Blake2s512Rng>(),
+        prove_with_hash::<Sha256Hash>(),
+        prove_with_hash::<Keccak256Hash>(),
+        prove_with_hash::<PoseidonHash<TestCurveFr>>(),
+    ];
+    let verifiers: [fn(&[u8]) -> bool; 4] = [
+        verify_with_hash::<Blake2s512Rng>,
+        verify_with_hash::<Sha256Hash>,
+        verify_with_hash::<Keccak256Hash>,
+        verify_with_hash::<PoseidonHash<TestCurveFr>>,
+    ];
+    // a proof is only accepted by the verifier using the same hash
+    for (i, proof) in proofs.iter().enumerate() {
+        for (j, verify) in verifiers.iter().enumerate() {
+            assert_eq!(verify(proof), i == j);
+        }
+    }
+}
+
+#[test]
+fn test_circuit_id() {
+    let mut rng = test_rng();
+    let (r1cs, _, _) =
+        generate_circuit_with_random_input::<TestCurveFr, _>(4, 60, true, 1, &mut rng);
+    let matrices = r1cs.to_matrices().unwrap();
+    let index = |c: Vec<Vec<(TestCurveFr, usize)>>| {
+        MLProofForR1CS::<TestCurve>::index(matrices.a.clone(), matrices.b.clone(), c, matrices.num_instance_variables, matrices.num_witness_variables).unwrap()
+    };
+    let pk = index(matrices.c.clone());
+    let vk = pk.vk();
+    assert_eq!(pk.circuit_id, vk.circuit_id);
+    assert_eq!(pk.circuit_id.to_string().len(), 64);
+    // stable across indexing and serialization
+    assert_eq!(pk.circuit_id, index(matrices.c.clone()).circuit_id);
+    let vk = serialization_round_trip(&vk);
+    assert_eq!(pk.circuit_id, vk.circuit_id);
+    assert_eq!(
+        vk.circuit_id,
+        CircuitId::compute(vk.log_m, vk.log_n, vk.num_instance_variables, &vk.matrices, &vk.multisets, &vk.coefficients).unwrap()
+    );
+
+    // another circuit has another id
+    let mut c = matrices.c.clone();
+    c[0].push((TestCurveFr::one(), 0));
+    assert_ne!(pk.circuit_id, index(c).circuit_id);
+
+    // and so do other multisets or coefficients over the same matrices
+    let ccs = |multisets: Vec<Vec<usize>>, coefficients: Vec<TestCurveFr>| {
+        MLProofForR1CS::<TestCurve>::index_ccs(vec![matrices.a.clone(), matrices.b.clone(), matrices.c.clone()], multisets, coefficients, matrices.num_instance_variables, matrices.num_witness_variables).unwrap()
+    };
+    assert_eq!(pk.circuit_id, ccs(vec![vec![0, 1], vec![2]], vec![TestCurveFr::one(), -TestCurveFr::one()]).circuit_id);
+    assert_ne!(pk.circuit_id, ccs(vec![vec![0, 1], vec![2, 2]], vec![TestCurveFr::one(), -TestCurveFr::one()]).circuit_id);
+    assert_ne!(pk.circuit_id, ccs(vec![vec![0, 1], vec![2]], vec![TestCurveFr::one(), TestCurveFr::one()]).circuit_id);
+}
+
+#[test]
+fn test_first_sumcheck_degree() {
+    let mut rng = test_rng();
+    // eq(tor, x) is a single table, so the degree does not grow with the number of constraints
+    for &log_n in &[6usize, 9] {
+        let num_public = 1 << 2;
+        let (r1cs, v, w) = generate_circuit_with_random_input::<TestCurveFr, _>(
+            num_public,
+            (1 << log_n) - num_public,
+            true,
+            1,
+            &mut rng,
+        );
+        let matrices = r1cs.to_matrices().unwrap();
+        let pk = MLProofForR1CS::<TestCurve>::index(matrices.a, matrices.b, matrices.c, matrices.num_instance_variables, matrices.num_witness_variables).unwrap();
+        let log_m = pk.log_m;
+        let (pp, _) = MLProofForR1CS::<TestCurve>::setup(pk.log_n, &mut rng).unwrap();
+        let ps = MLProofForR1CS::<TestCurve>::prover_init(pk, v, w).unwrap();
+        let (ps, _) = MLProofForR1CS::prover_first_round(ps, &pp).unwrap();
+        let tor = (0..log_m).map(|_| TestCurveFr::rand(&mut rng)).collect();
+        let (_, pm) = MLProofForR1CS::prover_second_round(ps, VerifierFirstMessage { tor }).unwrap();
+        assert_eq!(pm.ml_index_info.num_variables, log_m);
+        assert_eq!(pm.ml_index_info.max_multiplicands, 3);
+    }
+
+    // one more than the degree of the constraints
+    let (matrices, v, w) = cube_chain(6, TestCurveFr::rand(&mut rng));
+    let pk = MLProofForR1CS::<TestCurve>::index_ccs(matrices, vec![vec![0, 0, 0], vec![2], vec![1]], vec![TestCurveFr::one(), TestCurveFr::from(5u64), -TestCurveFr::one()], 2, 6).unwrap();
+    let log_m = pk.log_m;
+    let (pp, _) = MLProofForR1CS::<TestCurve>::setup(pk.log_n, &mut rng).unwrap();
+    let ps = MLProofForR1CS::<TestCurve>::prover_init(pk, v, w).unwrap();
+    let (ps, _) = MLProofForR1CS::prover_first_round(ps, &pp).unwrap();
+    let tor = (0..log_m).map(|_| TestCurveFr::rand(&mut rng)).collect();
+    let (_, pm) = MLProofForR1CS::prover_second_round(ps, VerifierFirstMessage { tor }).unwrap();
+    assert_eq!(pm.ml_index_info.max_multiplicands, 4);
+}
+
+#[test]
+fn test_batch() {
+    let mut rng = test_rng();
+    let ((a, b, c), vs, ws) = chain_circuit_with_instances::<TestCurveFr, _>(4, 60, 5, &mut rng);
+    let pk = MLArgumentForR1CS::<TestCurve>::index(a, b, c, 4, 60).unwrap();
+    let vk = pk.vk();
+    let nv = MLProofForR1CS::<TestCurve>::num_variables_for_batch(&pk, vs.len());
+    assert_eq!(nv, pk.log_n - 1 + 3);
+    let (pp, vp) = MLProofForR1CS::<TestCurve>::setup(nv, &mut rng).unwrap();
+    let proof = MLArgumentForR1CS::<TestCurve>::prove_batch(&pk, vs.clone(), ws.clone(), &pp).unwrap();
+    let copy = || serialization_round_trip(&proof);
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_batch(&vk, vs.clone(), copy(), &vp).unwrap());
+
+    // each instance is checked against its own public input
+    let mut wrong_vs = vs.clone();
+    wrong_vs[3][1] += TestCurveFr::one();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_batch(&vk, wrong_vs.clone(), copy(), &vp).is_err());
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_batch(&vk, vs[..4].to_vec(), copy(), &vp).is_err());
+
+    // one unsatisfied instance makes the batch fail
+    let wrong_proof = MLArgumentForR1CS::<TestCurve>::prove_batch(&pk, wrong_vs.clone(), ws.clone(), &pp).unwrap();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_batch(&vk, wrong_vs, wrong_proof, &vp).is_err());
+
+    // a single instance, and the proof size grows with log(k)
+    let single = MLArgumentForR1CS::<TestCurve>::prove_batch(&pk, vs[..1].to_vec(), ws[..1].to_vec(), &pp).unwrap();
+    let single_size = single.serialized_size();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_batch(&vk, vs[..1].to_vec(), single, &vp).unwrap());
+    assert!(proof.serialized_size() < 2 * single_size);
+}
+
+#[test]
+fn test_multi_circuit() {
+    let mut rng = test_rng();
+    // circuits with different numbers of constraints and variables
+    let mut pks = Vec::new();
+    let mut instances = Vec::new();
+    for &(num_public, num_private) in [(4usize, 60usize), (3, 5), (8, 200)].iter() {
+        let ((a, b, c), vs, ws) = chain_circuit_with_instances::<TestCurveFr, _>(num_public, num_private, 1, &mut rng);
+        pks.push(MLArgumentForR1CS::<TestCurve>::index(a, b, c, num_public, num_private).unwrap());
+        instances.push((vs[0].clone(), ws[0].clone()));
+    }
+    let vks: Vec<_> = pks.iter().map(|pk| pk.vk()).collect();
+    let nv = MLProofForR1CS::<TestCurve>::num_variables_for_multi_circuit(&pks);
+    assert_eq!(nv, pks[2].log_n - 1);
+    let (pp, vp) = MLProofForR1CS::<TestCurve>::setup(nv, &mut rng).unwrap();
+    let prove = |instances: &[(Vec<TestCurveFr>, Vec<TestCurveFr>)]| {
+        let instances = pks.iter().zip(instances.iter()).map(|(pk, (v, w))| (pk, v.clone(), w.clone())).collect();
+        MLArgumentForR1CS::<TestCurve>::prove_multi_circuit(instances, &pp).unwrap()
+    };
+    let public = |instances: &[(Vec<TestCurveFr>, Vec<TestCurveFr>)]| -> Vec<_> {
+        vks.iter().zip(instances.iter()).map(|(vk, (v, _))| (vk, v.clone())).collect()
+    };
+
+    let proof = prove(&instances);
+    let copy = || serialization_round_trip(&proof);
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_multi_circuit(public(&instances), copy(), &vp).unwrap());
+
+    // instances are bound to their circuits
+    let mut swapped = public(&instances);
+    swapped.swap(0, 1);
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_multi_circuit(swapped, copy(), &vp).is_err());
+
+    // one unsatisfied instance makes the proof fail
+    let mut wrong = instances.clone();
+    wrong[1].0[1] += TestCurveFr::one();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_multi_circuit(public(&wrong), copy(), &vp).is_err());
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_multi_circuit(public(&wrong), prove(&wrong), &vp).is_err());
+
+    // wrong opening proof is caught by the multi-pairing
+    let mut proof = copy();
+    proof.openings[2].proof_for_w_ry.proofs.swap(0, 1);
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_multi_circuit(public(&instances), proof, &vp).is_err());
+}
+
+#[test]
+fn test_committed_segments() {
+    let mut rng = test_rng();
+    let ((a, b, c), vs, ws) = chain_circuit_with_instances::<TestCurveFr, _>(4, 60, 1, &mut rng);
+    let (v, w) = (vs[0].clone(), ws[0].clone());
+    let pk = MLArgumentForR1CS::<TestCurve>::index(a, b, c, 4, 60).unwrap();
+    let vk = pk.vk();
+    let (pp, vp) = MLProofForR1CS::<TestCurve>::setup(pk.log_n - 1, &mut rng).unwrap();
+    let segment = |offset: usize, values: Vec<TestCurveFr>| {
+        let commitment = MLPolyCommit::commit(&pp, MLExtensionArray::from_vec(values).unwrap()).unwrap();
+        CommittedSegment::new(offset, commitment)
+    };
+    // the last segment covers the padding of w
+    let mut tail = w[48..].to_vec();
+    tail.resize(16, TestCurveFr::zero());
+    let segments = vec![segment(0, w[..32].to_vec()), segment(48, tail)];
+
+    let proof = MLArgumentForR1CS::<TestCurve>::prove_with_segments(pk.clone(), v.clone(), w.clone(), &segments, &pp).unwrap();
+    let copy = || serialization_round_trip(&proof);
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_with_segments(vk.clone(), v.clone(), &segments, copy(), &vp).unwrap());
+
+    // a commitment to different data, or to another segment of w, is rejected
+    let mut values = w[..32].to_vec();
+    values[7] += TestCurveFr::one();
+    let wrong = vec![segment(0, values)];
+    let wrong_proof = MLArgumentForR1CS::<TestCurve>::prove_with_segments(pk.clone(), v.clone(), w.clone(), &wrong, &pp).unwrap();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_with_segments(vk.clone(), v.clone(), &wrong, wrong_proof, &vp).is_err());
+    let moved = vec![segment(32, w[..32].to_vec())];
+    let moved_proof = MLArgumentForR1CS::<TestCurve>::prove_with_segments(pk.clone(), v.clone(), w.clone(), &moved, &pp).unwrap();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_with_segments(vk.clone(), v.clone(), &moved, moved_proof, &vp).is_err());
+
+    // the proof is bound to its segments
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_with_segments(vk, v.clone(), &segments[..1], copy(), &vp).is_err());
+
+    // segments should be aligned to their length
+    let misaligned = vec![segment(8, w[8..24].to_vec())];
+    assert!(MLArgumentForR1CS::<TestCurve>::prove_with_segments(pk, v, w, &misaligned, &pp).is_err());
+}
+
+/// Circuit checking that b is a permutation of a, using the challenge gamma at v[1]:
+/// prod (a_i + gamma) = prod (b_i + gamma). a and b form the committed phase, followed by the partial products.
+fn permutation_circuit(n: usize) -> (Matrix<TestCurveFr>, Matrix<TestCurveFr>, Matrix<TestCurveFr>) {
+    let one = TestCurveFr::one();
+    // z = (1, gamma, a, b, products of a, products of b), given as the offsets of the lists
+    let (mut a, mut b, mut c) = (Vec::new(), Vec::new(), Vec::new());
+    for &(values, products) in [(2, 2 + 2 * n), (2 + n, 2 + 3 * n)].iter() {
+        a.push(vec![(one, values), (one, 1)]);
+        b.push(vec![(one, 0)]);
+        c.push(vec![(one, products)]);
+        for i in 1..n {
+            a.push(vec![(one, products + i - 1)]);
+            b.push(vec![(one, values + i), (one, 1)]);
+            c.push(vec![(one, products + i)]);
+        }
+    }
+    a.push(vec![(one, 2 + 3 * n - 1)]);
+    b.push(vec![(one, 0)]);
+    c.push(vec![(one, 2 + 4 * n - 1)]);
+    (a, b, c)
+}
+
+#[test]
+fn test_multi_phase() {
+    let mut rng = test_rng();
+    let n = 8;
+    let (a, b, c) = permutation_circuit(n);
+    let pk = MLArgumentForR1CS::<TestCurve>::index(a, b, c, 2, 4 * n).unwrap();
+    let vk = pk.vk();
+    let layout = PhaseLayout::new(&[(2 * n, 1)]).unwrap();
+    assert_eq!(layout.end(), 2 * n);
+    let (pp, vp) = MLProofForR1CS::<TestCurve>::setup(pk.log_n - 1, &mut rng).unwrap();
+    let values: Vec<_> = (0..n).map(|_| TestCurveFr::rand(&mut rng)).collect();
+    let products = |values: &[TestCurveFr], gamma: TestCurveFr| {
+        values.iter().scan(TestCurveFr::one(), |acc, x| {
+            *acc *= *x + gamma;
+            Some(*acc)
+        }).collect::<Vec<_>>()
+    };
+    let prove = |first: Vec<TestCurveFr>, second: Vec<TestCurveFr>| {
+        let mut prover = MLArgumentForR1CS::<TestCurve>::multi_phase_prover(&pk, &layout, vec![TestCurveFr::one()], &pp).unwrap();
+        let phase: Vec<_> = first.iter().chain(second.iter()).cloned().collect();
+        let gamma = prover.commit_phase(phase.clone()).unwrap()[0];
+        let mut w = phase;
+        w.extend(products(&first, gamma));
+        w.extend(products(&second, gamma));
+        prover.finish(w).unwrap()
+    };
+
+    let mut permuted = values.clone();
+    permuted.reverse();
+    permuted.swap(0, 3);
+    let proof = prove(values.clone(), permuted);
+    let copy = || serialization_round_trip(&proof);
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_multi_phase(vk.clone(), &layout, vec![TestCurveFr::one()], copy(), &vp).unwrap());
+
+    // not a permutation
+    let mut other = values.clone();
+    other[5] += TestCurveFr::one();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_multi_phase(vk.clone(), &layout, vec![TestCurveFr::one()], prove(values.clone(), other), &vp).is_err());
+
+    // the challenge is derived from the committed phase, so it cannot be chosen by the prover
+    let mut proof = copy();
+    proof.phase_commitments[0] = prove(values.clone(), values.clone()).phase_commitments.remove(0);
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_multi_phase(vk, &layout, vec![TestCurveFr::one()], proof, &vp).is_err());
+
+    // the witness should agree with the committed phase
+    let mut prover = MLArgumentForR1CS::<TestCurve>::multi_phase_prover(&pk, &layout, vec![TestCurveFr::one()], &pp).unwrap();
+    let gamma = prover.commit_phase(values.iter().chain(values.iter()).cloned().collect()).unwrap()[0];
+    assert!(prover.commit_phase(values.clone()).is_err());
+    let mut w: Vec<_> = values.iter().chain(values.iter()).cloned().collect();
+    w[0] += TestCurveFr::one();
+    w.extend(products(&values, gamma));
+    w.extend(products(&values, gamma));
+    assert!(prover.finish(w).is_err());
+}
+
+#[test]
+fn test_batch_verify() {
+    let mut rng = test_rng();
+    let ((a, b, c), vs, ws) = chain_circuit_with_instances::<TestCurveFr, _>(4, 60, 3, &mut rng);
+    let pk = MLArgumentForR1CS::<TestCurve>::index(a, b, c, 4, 60).unwrap();
+    let vk = pk.vk();
+    let (pp, vp) = MLProofForR1CS::<TestCurve>::setup(pk.log_n, &mut rng).unwrap();
+    let proofs: Vec<Proof<TestCurve>> = vs
+        .iter()
+        .zip(ws.iter())
+        .map(|(v, w)| MLArgumentForR1CS::<TestCurve>::prove(pk.clone(), v.clone(), w.clone(), &pp).unwrap())
+        .collect();
+    // the last proof is repeated, so that its matrix evaluations are shared
+    let batch = |tamper: &dyn Fn(usize, &mut Proof<TestCurve>)| {
+        let batch: Vec<_> = (0..4)
+            .map(|i| {
+                let j = ark_std::cmp::min(i, 2);
+                let mut proof = serialization_round_trip(&proofs[j]);
+                tamper(i, &mut proof);
+                (vs[j].clone(), proof)
+            })
+            .collect();
+        MLArgumentForR1CS::<TestCurve>::batch_verify(&vk, batch, &vp).unwrap()
+    };
+    assert_eq!(batch(&|_, _| {}), None);
+
+    // wrong w(r_y) is caught by the field checks
+    assert_eq!(batch(&|i, proof| if i == 2 { proof.prover_fifth_message.w_ry += TestCurveFr::one() }), Some(2));
+    // wrong opening proof is caught by the multi-pairing
+    assert_eq!(
+        batch(&|i, proof| if i == 1 {
+            let proofs = &mut proof.prover_fifth_message.proof_for_w_ry.proofs;
+            proofs.swap(0, 1);
+        }),
+        Some(1)
+    );
+    // truncated opening proof rejects that proof rather than the whole batch
+    assert_eq!(batch(&|i, proof| if i == 3 { proof.prover_fifth_message.proof_for_w_ry.proofs.pop(); }), Some(3));
+
+    // a malformed public input is an error rather than a rejected proof
+    let batch: Vec<_> = (0..2)
+        .map(|i| {
+            let mut v = vs[i].clone();
+            if i == 1 {
+                v.push(TestCurveFr::one());
+            }
+            (v, serialization_round_trip(&proofs[i]))
+        })
+        .collect();
+    assert!(MLArgumentForR1CS::<TestCurve>::batch_verify(&vk, batch, &vp).is_err());
+}
+
+#[test]
+fn test_verify_deferred() {
+    let mut rng = test_rng();
+    let ((a, b, c), vs, ws) = chain_circuit_with_instances::<TestCurveFr, _>(4, 60, 2, &mut rng);
+    let pk = MLArgumentForR1CS::<TestCurve>::index(a, b, c, 4, 60).unwrap();
+    let vk = pk.vk();
+    let (pp, vp) = MLProofForR1CS::<TestCurve>::setup(pk.log_n, &mut rng).unwrap();
+    let prove = |i: usize| MLArgumentForR1CS::<TestCurve>::prove(pk.clone(), vs[i].clone(), ws[i].clone(), &pp).unwrap();
+
+    let mut accumulator = PairingAccumulator::new();
+    for i in 0..2 {
+        let single = MLArgumentForR1CS::<TestCurve>::verify_deferred(vk.clone(), vs[i].clone(), prove(i), &vp).unwrap();
+        accumulator.merge(single, TestCurveFr::rand(&mut rng));
+    }
+    assert!(accumulator.decide());
+
+    // wrong opening proof passes the field checks, but not the deferred pairing check
+    let mut proof = prove(0);
+    proof.prover_fifth_message.proof_for_w_ry.proofs.swap(0, 1);
+    let wrong = MLArgumentForR1CS::<TestCurve>::verify_deferred(vk.clone(), vs[0].clone(), proof, &vp).unwrap();
+    assert!(!wrong.decide());
+    accumulator.merge(wrong, TestCurveFr::rand(&mut rng));
+    assert!(!accumulator.decide());
+
+    // wrong w(r_y) is rejected before the pairing check
+    let mut proof = prove(1);
+    proof.prover_fifth_message.w_ry += TestCurveFr::one();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_deferred(vk, vs[1].clone(), proof, &vp).is_err());
+}
+
+/// x_{i+1} = x_i^3 + 5 for i < k, with z = (1, x_0, x_1, ..., x_k) and x_0 public
+fn cube_chain(k: usize, x_0: TestCurveFr) -> (Vec<Matrix<TestCurveFr>>, Vec<TestCurveFr>, Vec<TestCurveFr>) {
+    let x_i: Matrix<TestCurveFr> = (0..k).map(|i| vec![(TestCurveFr::one(), i + 1)]).collect();
+    let x_next: Matrix<TestCurveFr> = (0..k).map(|i| vec![(TestCurveFr::one(), i + 2)]).collect();
+    let one: Matrix<TestCurveFr> = (0..k).map(|_| vec![(TestCurveFr::one(), 0)]).collect();
+    let mut w = Vec::with_capacity(k);
+    let mut x = x_0;
+    for _ in 0..k {
+        x = x * x * x + TestCurveFr::from(5u64);
+        w.push(x);
+    }
+    (vec![x_i, x_next, one], vec![TestCurveFr::one(), x_0], w)
+}
+
+#[test]
+fn test_ccs() {
+    let mut rng = test_rng();
+
+    // degree 3 gate: M_0 z ∘ M_0 z ∘ M_0 z + 5 * M_2 z - M_1 z = 0
+    let (matrices, v, w) = cube_chain(6, TestCurveFr::rand(&mut rng));
+    let multisets = vec![vec![0, 0, 0], vec![2], vec![1]];
+    let five = TestCurveFr::from(5u64);
+    let coefficients = vec![TestCurveFr::one(), five, -TestCurveFr::one()];
+    assert!(MLArgumentForR1CS::<TestCurve>::index_ccs(matrices.clone(), vec![vec![0, 3]], vec![TestCurveFr::one()], 2, 6).is_err());
+    assert!(MLArgumentForR1CS::<TestCurve>::index_ccs(matrices.clone(), multisets.clone(), vec![five], 2, 6).is_err());
+    let pk = MLArgumentForR1CS::<TestCurve>::index_ccs(matrices.clone(), multisets.clone(), coefficients.clone(), 2, 6).unwrap();
+    assert_eq!(pk.num_matrices(), 3);
+    assert_eq!(pk.degree(), 3);
+    let vk = pk.vk();
+    let (pp, vp, dvp) = MLProofForR1CS::<TestCurve>::setup_designated(pk.log_n, &mut rng).unwrap();
+
+    let proof = MLArgumentForR1CS::<TestCurve>::prove(pk.clone(), v.clone(), w.clone(), &pp).unwrap();
+    let copy = || serialization_round_trip(&proof);
+    assert!(MLArgumentForR1CS::<TestCurve>::verify(vk.clone(), v.clone(), copy(), &vp).unwrap());
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_designated(vk.clone(), v.clone(), copy(), &dvp).unwrap());
+
+    let mut wrong_v = v.clone();
+    wrong_v[1] += TestCurveFr::one();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify(vk.clone(), wrong_v, copy(), &vp).is_err());
+    let mut wrong_w = w.clone();
+    wrong_w[3] += TestCurveFr::one();
+    let proof = MLArgumentForR1CS::<TestCurve>::prove(pk.clone(), v.clone(), wrong_w, &pp).unwrap();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify(vk.clone(), v.clone(), proof, &vp).is_err());
+
+    // preprocessing commits to all t matrices
+    let nv = MLProofForR1CS::<TestCurve>::num_variables_for_preprocessing(&pk);
+    let (pp, vp) = MLProofForR1CS::<TestCurve>::setup(nv, &mut rng).unwrap();
+    let preprocessed = MLArgumentForR1CS::<TestCurve>::preprocess(pk.clone(), &pp).unwrap();
+    let preprocessed_vk = preprocessed.vk();
+    let proof = MLArgumentForR1CS::<TestCurve>::prove_preprocessed(preprocessed, v.clone(), w.clone(), &pp).unwrap();
+    assert_eq!(proof.matrix_evaluation_proof.evaluations.len(), 3);
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_preprocessed(preprocessed_vk, v.clone(), proof, &vp).unwrap());
+
+    // batch of instances of the same constraint system
+    let (_, v_1, w_1) = cube_chain(6, TestCurveFr::rand(&mut rng));
+    let (vs, ws) = (vec![v, v_1], vec![w, w_1]);
+    let nv = MLProofForR1CS::<TestCurve>::num_variables_for_batch(&pk, vs.len());
+    let (pp, vp) = MLProofForR1CS::<TestCurve>::setup(nv, &mut rng).unwrap();
+    let proof = MLArgumentForR1CS::<TestCurve>::prove_batch(&pk, vs.clone(), ws, &pp).unwrap();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify_batch(&vk, vs, proof, &vp).unwrap());
+
+    // R1CS as a special case
+    let ((a, b, c), vs, ws) = chain_circuit_with_instances::<TestCurveFr, _>(4, 60, 1, &mut rng);
+    let pk = MLArgumentForR1CS::<TestCurve>::index(a, b, c, 4, 60).unwrap();
+    assert_eq!(pk.multisets, vec![vec![0, 1], vec![2]]);
+    assert_eq!(pk.coefficients, vec![TestCurveFr::one(), -TestCurveFr::one()]);
+    assert_eq!(pk.degree(), 2);
+    let vk = pk.vk();
+    let (pp, vp) = MLProofForR1CS::<TestCurve>::setup(pk.log_n - 1, &mut rng).unwrap();
+    let proof = MLArgumentForR1CS::<TestCurve>::prove(pk.clone(), vs[0].clone(), ws[0].clone(), &pp).unwrap();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify(vk.clone(), vs[0].clone(), proof, &vp).unwrap());
+    let mut wrong = ws[0].clone();
+    wrong[0] += TestCurveFr::one();
+    let proof = MLArgumentForR1CS::<TestCurve>::prove(pk, vs[0].clone(), wrong, &pp).unwrap();
+    assert!(MLArgumentForR1CS::<TestCurve>::verify(vk, vs[0].clone(), proof, &vp).is_err());
+}
@@ -1,229 +1,206 @@
 use ark_ff::Field;
 use linear_sumcheck::data_structures::ml_extension::MLExtension;
 use linear_sumcheck::data_structures::MLExtensionArray;
-use rand::RngCore;
 
-use crate::ahp::indexer::IndexVK;
+use crate::ahp::indexer::{IndexVK, PreprocessedIndexVK, VerifierIndex};
 use crate::ahp::prover::{
-    ProverFifthMessage, ProverFinalMessage, ProverFirstMessage, ProverFourthMessage,
-    ProverSecondMessage, ProverThirdMessage,
+    ProverFinalMessage, ProverFirstMessage, ProverFourthMessage, ProverSecondMessage,
+    ProverThirdMessage,
 };
 use crate::ahp::MLProofForR1CS;
-use crate::data_structures::eq::eq_extension;
+use crate::ahp::setup::SetupIdentifier;
+use crate::data_structures::eq::eq_table;
+use crate::data_structures::transcript::{Transcript, TranscriptHash};
 use crate::error::{invalid_arg, SResult};
 use ark_ec::PairingEngine;
-use ark_ff::{One, UniformRand, Zero};
+use ark_ff::{One, Zero};
 use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
-use ark_std::log2;
 use linear_sumcheck::ml_sumcheck::ahp::prover::ProverMsg as MLProverMsg;
 use linear_sumcheck::ml_sumcheck::ahp::verifier::VerifierMsg as MLVerifierMsg;
 use linear_sumcheck::ml_sumcheck::ahp::verifier::VerifierState as MLVerifierState;
 use linear_sumcheck::ml_sumcheck::ahp::AHPForMLSumcheck;
 use crate::commitment::commit::Commitment;
-use crate::commitment::MLPolyCommit;
-use crate::ahp::setup::VerifierParameter;
-use crate::commitment::open::Proof;
-
-/// r_v: randomness
-#[derive(CanonicalSerialize, CanonicalDeserialize)]
-pub struct VerifierFirstMessage<F: Field> {
-    pub r_v: Vec<F>,
-}
+use crate::zerocheck;
+use crate::commitment::verify::{OpeningClaim, OpeningVerifierKey};
+use crate::spark::prover::SparkProof;
+use crate::spark::MLSpark;
 
 /// random tor
 #[derive(CanonicalSerialize, CanonicalDeserialize)]
-pub struct VerifierSecondMessage<F: Field> {
+pub struct VerifierFirstMessage<F: Field> {
     pub tor: Vec<F>,
 }
 
 /// the last randomness for MLSumcheck
 #[derive(CanonicalSerialize, CanonicalDeserialize)]
-pub struct VerifierThirdMessage<F: Field> {
+pub struct VerifierSecondMessage<F: Field> {
     pub last_random_point: F,
 }
 
+/// random coefficients r_i of the matrices, which combine the claims M_i z(r_x) for the second sumcheck
 #[derive(CanonicalSerialize, CanonicalDeserialize)]
-pub struct VerifierFourthMessage<F: Field> {
-    pub r_a: F,
-    pub r_b: F,
-    pub r_c: F,
+pub struct VerifierThirdMessage<F: Field> {
+    pub coefficients: Vec<F>,
 }
 
 /// the last randomness for second MLSumcheck
 #[derive(CanonicalSerialize, CanonicalDeserialize)]
-pub struct VerifierFifthMessage<F: Field> {
+pub struct VerifierFourthMessage<F: Field> {
     pub last_random_point: F,
 }
 
-pub struct VerifierFirstState<E: PairingEngine> {
+pub struct VerifierFirstState<E: PairingEngine, K = IndexVK<<E as PairingEngine>::Fr>> {
     pub v: Vec<E::Fr>,
-    pub log_v: usize,
-    pub vk: IndexVK<E::Fr>,
+    pub vk: K,
 }
 
-pub struct VerifierSecondState<E: PairingEngine> {
-    pub v: Vec<E::Fr>,
-    pub log_v: usize,
-    pub vk: IndexVK<E::Fr>,
-    pub r_v: Vec<E::Fr>,
-    pub commit: Commitment<E>, // todo: replace this with real commitment
-}
-
-pub struct VerifierThirdState<E: PairingEngine> {
-    pub vk: IndexVK<E::Fr>,
+pub struct VerifierSecondState<E: PairingEngine, K = IndexVK<<E as PairingEngine>::Fr>> {
+    pub vk: K,
     pub commit: Commitment<E>,
     pub tor: Vec<E::Fr>,
     pub v: Vec<E::Fr>,
-    pub r_v: Vec<E::Fr>,
-    pub z_rv_0: E::Fr,
-    pub z_rv_0_proof: Proof<E>
 }
 
 /// first sumcheck state
-pub struct VerifierFirstSumcheckState<E: PairingEngine> {
-    pub vk: IndexVK<E::Fr>,
+pub struct VerifierFirstSumcheckState<E: PairingEngine, K = IndexVK<<E as PairingEngine>::Fr>> {
+    pub vk: K,
     pub commit: Commitment<E>,
     pub tor: Vec<E::Fr>,
     pub ml_verifier: MLVerifierState<E::Fr>,
     pub v: Vec<E::Fr>,
-    pub r_v: Vec<E::Fr>,
-    pub z_rv_0: E::Fr,
-    pub z_rv_0_proof: Proof<E>
 }
 
-pub struct VerifierFourthState<E: PairingEngine> {
-    pub vk: IndexVK<E::Fr>,
+pub struct VerifierThirdState<E: PairingEngine, K = IndexVK<<E as PairingEngine>::Fr>> {
+    pub vk: K,
     pub commit: Commitment<E>,
     pub tor: Vec<E::Fr>,
     pub first_verifier_state: MLVerifierState<E::Fr>,
     pub v: Vec<E::Fr>,
-    pub r_v: Vec<E::Fr>,
-    pub z_rv_0: E::Fr,
-    pub z_rv_0_proof: Proof<E>
 }
 
-pub struct VerifierFifthState<E: PairingEngine> {
-    pub vk: IndexVK<E::Fr>,
+pub struct VerifierFourthState<E: PairingEngine, K = IndexVK<<E as PairingEngine>::Fr>> {
+    pub vk: K,
     pub commit: Commitment<E>,
-    pub r_a: E::Fr,
-    pub r_b: E::Fr,
-    pub r_c: E::Fr,
-    pub va: E::Fr,
-    pub vb: E::Fr,
-    pub vc: E::Fr,
+    /// r_i
+    pub coefficients: Vec<E::Fr>,
+    /// M_i z(r_x)
+    pub evaluations: Vec<E::Fr>,
     pub tor: Vec<E::Fr>,
     pub first_verifier_state: MLVerifierState<E::Fr>,
     pub v: Vec<E::Fr>,
-    pub r_v: Vec<E::Fr>,
-    pub z_rv_0: E::Fr,
-    pub z_rv_0_proof: Proof<E>
 }
 
-pub struct VerifierSecondSumcheckState<E: PairingEngine> {
-    pub vk: IndexVK<E::Fr>,
+pub struct VerifierSecondSumcheckState<E: PairingEngine, K = IndexVK<<E as PairingEngine>::Fr>> {
+    pub vk: K,
     pub commit: Commitment<E>,
-    pub va: E::Fr,
-    pub vb: E::Fr,
-    pub vc: E::Fr,
-    pub r_a: E::Fr,
-    pub r_b: E::Fr,
-    pub r_c: E::Fr,
+    /// M_i z(r_x)
+    pub evaluations: Vec<E::Fr>,
+    /// r_i
+    pub coefficients: Vec<E::Fr>,
     pub tor: Vec<E::Fr>,
     pub first_verifier_state: MLVerifierState<E::Fr>,
     pub second_verifier_state: MLVerifierState<E::Fr>,
     pub v: Vec<E::Fr>,
-    pub r_v: Vec<E::Fr>,
-    pub z_rv_0: E::Fr,
-    pub z_rv_0_proof: Proof<E>
 }
 
-pub type VerifierSixthState<E> = VerifierSecondSumcheckState<E>;
+pub type VerifierFifthState<E, K = IndexVK<<E as PairingEngine>::Fr>> = VerifierSecondSumcheckState<E, K>;
+
+/// label of this protocol in the transcript
+const PROTOCOL_LABEL: &[u8] = b"r1cs-spartan-raw/ml-argument-for-r1cs";
 
 impl<E: PairingEngine> MLProofForR1CS<E> {
-    pub fn verifier_init(vk: IndexVK<E::Fr>, v: Vec<E::Fr>) -> SResult<VerifierFirstState<E>> {
-        if !v.len().is_power_of_two() || v.len() > vk.matrix_a.num_constraints {
-            return Err(invalid_arg("public input should be power of two and has size smaller than number of constraints"));
-        }
-        let log_v = log2(v.len()) as usize;
-        Ok(VerifierFirstState { v, log_v, vk })
+    /// Start the transcript shared by the prover and the verifier. It binds the setup, the index and the
+    /// public input, so that challenges depend on all of them.
+    pub fn init_transcript<K: VerifierIndex<E::Fr>, H: TranscriptHash>(
+        setup: &SetupIdentifier,
+        index: &K,
+        v: &[E::Fr],
+    ) -> SResult<Transcript<H>> {
+        let mut transcript = Transcript::new(PROTOCOL_LABEL);
+        transcript.append_message(b"setup", setup)?;
+        index.append_to_transcript(&mut transcript)?;
+        transcript.append_scalars(b"public input", v)?;
+        Ok(transcript)
     }
 
-    /// receive commitment, generate r_v
-    pub fn verify_first_round<R: RngCore>(
-        state: VerifierFirstState<E>,
-        p_msg: ProverFirstMessage<E>,
-        rng: &mut R,
-    ) -> SResult<(VerifierSecondState<E>, VerifierFirstMessage<E::Fr>)> {
-        let commit = p_msg.commitment;
-        let vk = state.vk;
+    /// * `v`: public input, whose length should match the index and whose first element should be one
+    pub fn verifier_init<K: VerifierIndex<E::Fr>>(vk: K, v: Vec<E::Fr>) -> SResult<VerifierFirstState<E, K>> {
+        let v = Self::pad_public_input(&vk, v)?;
+        Ok(VerifierFirstState { v, vk })
+    }
 
-        let msg = Self::sample_first_round(state.log_v, rng);
-        let r_v: Vec<_> = msg.r_v.clone();
-        let next_state = VerifierSecondState {
-            v: state.v,
-            log_v: state.log_v,
-            vk,
-            commit,
-            r_v,
-        };
-        Ok((next_state, msg))
+    /// check the public input, and pad it to a power of two (at least 2) so that v is a multilinear extension
+    pub(crate) fn pad_public_input<K: VerifierIndex<E::Fr>>(vk: &K, mut v: Vec<E::Fr>) -> SResult<Vec<E::Fr>> {
+        if v.len() != vk.num_instance_variables() {
+            return Err(invalid_arg("public input has wrong length"));
+        }
+        if !v[0].is_one() {
+            return Err(invalid_arg("first public input should be one"));
+        }
+        v.resize(ark_std::cmp::max(v.len(), 2).next_power_of_two(), E::Fr::zero());
+        Ok(v)
     }
 
-    pub fn sample_first_round<R: RngCore>(
-        log_v: usize,
-        rng: &mut R,
-    ) -> VerifierFirstMessage<E::Fr> {
-        let r_v: Vec<_> = (0..log_v).map(|_| E::Fr::rand(rng)).collect();
-        VerifierFirstMessage { r_v }
+    /// Evaluate z(r_y) = (1 - r_y[last]) * v(r_y[..last]) + r_y[last] * w(r_y[..last]), given the padded
+    /// public input `v` and w(r_y[..last]). v is zero beyond its length.
+    pub(crate) fn z_eval(v: Vec<E::Fr>, w_ry: E::Fr, r_y: &[E::Fr]) -> SResult<E::Fr> {
+        let log_half = r_y.len() - 1;
+        let log_v = ark_std::log2(v.len()) as usize;
+        if log_v > log_half {
+            return Err(invalid_arg("public input is too long"));
+        }
+        let v = MLExtensionArray::from_vec(v)?;
+        let mut v_ry = v.eval_at(&r_y[..log_v])?;
+        for r in r_y[log_v..log_half].iter() {
+            v_ry *= E::Fr::one() - r;
+        }
+        Ok((E::Fr::one() - &r_y[log_half]) * &v_ry + &(r_y[log_half] * &w_ry))
     }
 
-    /// verify of z_rv_0 is correct, and send random tor
-    pub fn verify_second_round<R: RngCore>(
-        state: VerifierSecondState<E>,
-        p_msg: ProverSecondMessage<E>,
-        rng: &mut R,
-    ) -> SResult<(VerifierThirdState<E>, VerifierSecondMessage<E::Fr>)> {
-        let z_rv_0 = p_msg.z_rv_0;
-        let z_rv_0_proof = p_msg.proof_for_z_rv_0;
-        // verify z_rv_0 is correct using proof (verification done last)
-        //
-        // let vk = state.vk;
-        // let v = MLExtensionArray::from_vec(state.v)?;
-        // if v.eval_at(&state.r_v)? != z_rv_0 {
-        //     return Err(invalid_arg("public witness is inconsistent with proof"));
-        // }
-
-        // let eq = eq_extension(&tor)?;
-
-        let msg = Self::sample_second_round(state.vk.log_n, rng);
-        let state = VerifierThirdState {
+    /// receive commitment to w, send random tor
+    pub fn verify_first_round<K: VerifierIndex<E::Fr>, H: TranscriptHash>(
+        state: VerifierFirstState<E, K>,
+        p_msg: ProverFirstMessage<E>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<(VerifierSecondState<E, K>, VerifierFirstMessage<E::Fr>)> {
+        if p_msg.commitment.nv + 1 != state.vk.log_n() {
+            return Err(invalid_arg("commitment has wrong number of variables"));
+        }
+
+        let msg = Self::sample_first_round(state.vk.log_m(), &p_msg, transcript)?;
+        let commit = p_msg.commitment;
+        let next_state = VerifierSecondState {
             vk: state.vk,
-            commit: state.commit,
+            commit,
             tor: msg.tor.clone(),
             v: state.v,
-            r_v: state.r_v,
-            z_rv_0,
-            z_rv_0_proof
         };
-        Ok((state, msg))
+        Ok((next_state, msg))
     }
 
-    pub fn sample_second_round<R: RngCore>(
-        log_n: usize,
-        rng: &mut R,
-    ) -> VerifierSecondMessage<E::Fr> {
-        let tor: Vec<_> = (0..log_n).map(|_| E::Fr::rand(rng)).collect();
-        VerifierSecondMessage { tor }
+    /// absorb the commitment to w and squeeze tor
+    pub fn sample_first_round<H: TranscriptHash>(
+        log_m: usize,
+        p_msg: &ProverFirstMessage<E>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<VerifierFirstMessage<E::Fr>> {
+        transcript.append_message(b"commitment to w", p_msg)?;
+        let tor = transcript.challenge_scalars(b"tor", log_m)?;
+        Ok(VerifierFirstMessage { tor })
     }
 
     /// initial first sumcheck verifier
-    pub fn verify_third_round(
-        state: VerifierThirdState<E>,
-        p_msg: ProverThirdMessage,
-    ) -> SResult<(VerifierFirstSumcheckState<E>, Option<MLVerifierMsg<E::Fr>>)> {
+    pub fn verify_second_round<K: VerifierIndex<E::Fr>, H: TranscriptHash>(
+        state: VerifierSecondState<E, K>,
+        p_msg: ProverSecondMessage,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<(VerifierFirstSumcheckState<E, K>, Option<MLVerifierMsg<E::Fr>>)> {
+        let msg = Self::sample_second_round(&p_msg, transcript)?;
         let index_info = p_msg.ml_index_info;
-        // sanity check the index info
-        if index_info.num_variables != state.vk.log_n {
+        // sanity check the index info: eq * (sum_j c_j prod_{i in S_j} M_i z) has one more degree than the constraints
+        if index_info.num_variables != state.vk.log_m()
+            || index_info.max_multiplicands != state.vk.degree() + 1
+        {
             return Err(invalid_arg("invalid sumcheck proposal"));
         };
         let ml_verifier = AHPForMLSumcheck::verifier_init(&index_info);
@@ -233,140 +210,135 @@ impl<E: PairingEngine> MLProofForR1CS<E> {
             tor: state.tor,
             ml_verifier,
             v: state.v,
-            r_v: state.r_v,
-            z_rv_0: state.z_rv_0,
-            z_rv_0_proof: state.z_rv_0_proof
         };
 
-        Ok((next_state, None))
+        Ok((next_state, msg))
     }
 
-    #[inline]
-    pub fn sample_third_round() -> Option<MLVerifierMsg<E::Fr>> {
-        None
+    /// absorb the index of the first sumcheck. Its first round has no verifier message.
+    pub fn sample_second_round<H: TranscriptHash>(
+        p_msg: &ProverSecondMessage,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<Option<MLVerifierMsg<E::Fr>>> {
+        transcript.append_message(b"first sumcheck index", p_msg)?;
+        Ok(None)
     }
 
     /// sumcheck round except for last round
-    pub fn verify_first_sumcheck_ongoing_round<R: RngCore>(
-        state: VerifierFirstSumcheckState<E>,
+    pub fn verify_first_sumcheck_ongoing_round<K: VerifierIndex<E::Fr>, H: TranscriptHash>(
+        state: VerifierFirstSumcheckState<E, K>,
         p_msg: MLProverMsg<E::Fr>,
-        rng: &mut R,
-    ) -> SResult<(VerifierFirstSumcheckState<E>, Option<MLVerifierMsg<E::Fr>>)> {
-        let (v_msg, ml_verifier) = AHPForMLSumcheck::verify_round(p_msg, state.ml_verifier, rng)?;
+        transcript: &mut Transcript<H>,
+    ) -> SResult<(VerifierFirstSumcheckState<E, K>, Option<MLVerifierMsg<E::Fr>>)> {
+        transcript.append_message(b"first sumcheck message", &p_msg)?;
+        let (v_msg, ml_verifier) = AHPForMLSumcheck::verify_round(
+            p_msg,
+            state.ml_verifier,
+            transcript.challenge_rng(b"first sumcheck challenge")?,
+        )?;
         let next_state = VerifierFirstSumcheckState {
             ml_verifier,
             tor: state.tor,
             commit: state.commit,
             vk: state.vk,
             v: state.v,
-            r_v: state.r_v,
-            z_rv_0: state.z_rv_0,
-            z_rv_0_proof: state.z_rv_0_proof
         };
         Ok((next_state, v_msg))
     }
 
-    pub fn sample_verify_first_sumcheck_ongoing_round<R: RngCore>(
-        rng: &mut R,
-    ) -> Option<MLVerifierMsg<E::Fr>> {
-        Some(AHPForMLSumcheck::sample_round(rng))
+    pub fn sample_verify_first_sumcheck_ongoing_round<H: TranscriptHash>(
+        p_msg: &MLProverMsg<E::Fr>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<Option<MLVerifierMsg<E::Fr>>> {
+        transcript.append_message(b"first sumcheck message", p_msg)?;
+        Ok(Some(AHPForMLSumcheck::sample_round(
+            transcript.challenge_rng(b"first sumcheck challenge")?,
+        )))
     }
     /// last round of first sumcheck verifier. send last randomness to prover.
     ///
     /// message produced by this round will be received by prover's round_tail function
-    pub fn verify_first_sumcheck_final_round<R: RngCore>(
-        state: VerifierFirstSumcheckState<E>,
+    pub fn verify_first_sumcheck_final_round<K: VerifierIndex<E::Fr>, H: TranscriptHash>(
+        state: VerifierFirstSumcheckState<E, K>,
         p_msg: MLProverMsg<E::Fr>,
-        rng: &mut R,
-    ) -> SResult<(VerifierFourthState<E>, VerifierThirdMessage<E::Fr>)> {
-        let (ml_msg, ml_verifier) = AHPForMLSumcheck::verify_round(p_msg, state.ml_verifier, rng)?;
+        transcript: &mut Transcript<H>,
+    ) -> SResult<(VerifierThirdState<E, K>, VerifierSecondMessage<E::Fr>)> {
+        transcript.append_message(b"first sumcheck message", &p_msg)?;
+        let (ml_msg, ml_verifier) = AHPForMLSumcheck::verify_round(
+            p_msg,
+            state.ml_verifier,
+            transcript.challenge_rng(b"first sumcheck challenge")?,
+        )?;
         // let subclaim = AHPForMLSumcheck::subclaim(ml_verifier)?;
         let final_randomness = ml_msg.unwrap().randomness;
-        let msg = VerifierThirdMessage {
+        let msg = VerifierSecondMessage {
             last_random_point: final_randomness,
         };
-        let next_state = VerifierFourthState {
+        let next_state = VerifierThirdState {
             vk: state.vk,
             commit: state.commit,
             tor: state.tor,
             first_verifier_state: ml_verifier,
             v: state.v,
-            r_v: state.r_v,
-            z_rv_0: state.z_rv_0,
-            z_rv_0_proof: state.z_rv_0_proof
         };
         Ok((next_state, msg))
     }
 
-    pub fn sample_verify_first_sumcheck_final_round<R: RngCore>(
-        rng: &mut R,
-    ) -> VerifierThirdMessage<E::Fr> {
-        VerifierThirdMessage {
-            last_random_point: AHPForMLSumcheck::sample_round(rng).randomness,
-        }
+    pub fn sample_verify_first_sumcheck_final_round<H: TranscriptHash>(
+        p_msg: &MLProverMsg<E::Fr>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<VerifierSecondMessage<E::Fr>> {
+        let msg = Self::sample_verify_first_sumcheck_ongoing_round(p_msg, transcript)?;
+        Ok(VerifierSecondMessage {
+            last_random_point: msg.unwrap().randomness,
+        })
     }
 
-    /// receive va, rb, vc, and sample ra, rb, rc for next sumcheck
-    pub fn verify_fourth_round<R: RngCore>(
-        state: VerifierFourthState<E>,
-        p_msg: ProverFourthMessage<E>,
-        rng: &mut R,
-    ) -> SResult<(VerifierFifthState<E>, VerifierFourthMessage<E::Fr>)> {
-        let (va, vb, vc) = (p_msg.va, p_msg.vb, p_msg.vc);
-        // verify subclaim
-        // let first_subclaim = state.first_subclaim;
-        // let r_x = first_subclaim.point;
-        // {
-        //     let eq = state.eq;
-        //     let mut eq_rx: E::Fr = E::Fr::one();
-        //     for p in eq.iter() {
-        //         eq_rx *= &p.eval_at(&r_x)?;
-        //     }
-        //     if (va * &vb - &vc) * &eq_rx != first_subclaim.expected_evaluation {
-        //         return Err(crate::Error::WrongWitness(Some(
-        //             "first sumcheck has wrong subclaim".into(),
-        //         )));
-        //     }
-        // }
-
-        let msg = Self::sample_verify_fourth_round(rng);
-        let (r_a, r_b, r_c) = (msg.r_a, msg.r_b, msg.r_c);
-        let next_state = VerifierFifthState {
+    /// receive M_i z(r_x), and sample the coefficients r_i for next sumcheck
+    pub fn verify_third_round<K: VerifierIndex<E::Fr>, H: TranscriptHash>(
+        state: VerifierThirdState<E, K>,
+        p_msg: ProverThirdMessage<E>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<(VerifierFourthState<E, K>, VerifierThirdMessage<E::Fr>)> {
+        if p_msg.evaluations.len() != state.vk.num_matrices() {
+            return Err(invalid_arg("should have one evaluation for each matrix"));
+        }
+        // first sumcheck subclaim is checked in the final round
+        let msg = Self::sample_verify_third_round(&p_msg, transcript)?;
+        let next_state = VerifierFourthState {
             commit: state.commit,
             vk: state.vk,
-            r_a,
-            r_b,
-            r_c,
-            va,
-            vb,
-            vc,
+            coefficients: msg.coefficients.clone(),
+            evaluations: p_msg.evaluations,
             tor: state.tor,
             first_verifier_state: state.first_verifier_state,
             v: state.v,
-            r_v: state.r_v,
-            z_rv_0: state.z_rv_0,
-            z_rv_0_proof: state.z_rv_0_proof
         };
 
         Ok((next_state, msg))
     }
 
-    pub fn sample_verify_fourth_round<R: RngCore>(rng: &mut R) -> VerifierFourthMessage<E::Fr> {
-        VerifierFourthMessage {
-            r_a: E::Fr::rand(rng),
-            r_b: E::Fr::rand(rng),
-            r_c: E::Fr::rand(rng),
-        }
+    /// absorb M_i z(r_x) and squeeze one coefficient r_i for each of them
+    pub fn sample_verify_third_round<H: TranscriptHash>(
+        p_msg: &ProverThirdMessage<E>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<VerifierThirdMessage<E::Fr>> {
+        transcript.append_message(b"M_i z(r_x)", p_msg)?;
+        Ok(VerifierThirdMessage {
+            coefficients: transcript.challenge_scalars(b"matrix coefficients", p_msg.evaluations.len())?,
+        })
     }
 
     /// start second linear sumcheck
-    pub fn verify_fifth_round(
-        state: VerifierFifthState<E>,
-        p_msg: ProverFifthMessage,
-    ) -> SResult<(VerifierSecondSumcheckState<E>, Option<MLVerifierMsg<E::Fr>>)> {
+    pub fn verify_fourth_round<K: VerifierIndex<E::Fr>, H: TranscriptHash>(
+        state: VerifierFourthState<E, K>,
+        p_msg: ProverFourthMessage,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<(VerifierSecondSumcheckState<E, K>, Option<MLVerifierMsg<E::Fr>>)> {
+        let msg = Self::sample_verify_fourth_round(&p_msg, transcript)?;
         let index_info = p_msg.index_info;
-        // sanity check the index info
-        if index_info.num_variables != state.vk.log_n {
+        // sanity check the index info: M(r_x, y) * z(y) has degree 2
+        if index_info.num_variables != state.vk.log_n() || index_info.max_multiplicands != 2 {
             return Err(invalid_arg("invalid sumcheck proposal"));
         };
         let ml_verifier = AHPForMLSumcheck::verifier_init(&index_info);
@@ -374,92 +346,187 @@ impl<E: PairingEngine> MLProofForR1CS<E> {
         let next_state = VerifierSecondSumcheckState {
             vk: state.vk,
             commit: state.commit,
-            va: state.va,
-            vb: state.vb,
-            vc: state.vc,
-            r_a: state.r_a,
-            r_b: state.r_b,
-            r_c: state.r_c,
+            evaluations: state.evaluations,
+            coefficients: state.coefficients,
             tor: state.tor,
             first_verifier_state: state.first_verifier_state,
             second_verifier_state: ml_verifier,
             v: state.v,
-            r_v: state.r_v,
-            z_rv_0: state.z_rv_0,
-            z_rv_0_proof: state.z_rv_0_proof
         };
 
-        Ok((next_state, None))
+        Ok((next_state, msg))
     }
 
-    pub fn sample_verify_fifth_round() -> Option<MLVerifierMsg<E::Fr>> {
-        None
+    /// absorb the index of the second sumcheck. Its first round has no verifier message.
+    pub fn sample_verify_fourth_round<H: TranscriptHash>(
+        p_msg: &ProverFourthMessage,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<Option<MLVerifierMsg<E::Fr>>> {
+        transcript.append_message(b"second sumcheck index", p_msg)?;
+        Ok(None)
     }
     /// doing second sumcheck except for last round
-    pub fn verify_second_sumcheck_ongoing_round<R: RngCore>(
-        mut state: VerifierSecondSumcheckState<E>,
+    pub fn verify_second_sumcheck_ongoing_round<K: VerifierIndex<E::Fr>, H: TranscriptHash>(
+        mut state: VerifierSecondSumcheckState<E, K>,
         p_msg: MLProverMsg<E::Fr>,
-        rng: &mut R,
-    ) -> SResult<(VerifierSecondSumcheckState<E>, Option<MLVerifierMsg<E::Fr>>)> {
-        let (v_msg, ml_verifier) =
-            AHPForMLSumcheck::verify_round(p_msg, state.second_verifier_state, rng)?;
+        transcript: &mut Transcript<H>,
+    ) -> SResult<(VerifierSecondSumcheckState<E, K>, Option<MLVerifierMsg<E::Fr>>)> {
+        transcript.append_message(b"second sumcheck message", &p_msg)?;
+        let (v_msg, ml_verifier) = AHPForMLSumcheck::verify_round(
+            p_msg,
+            state.second_verifier_state,
+            transcript.challenge_rng(b"second sumcheck challenge")?,
+        )?;
         state.second_verifier_state = ml_verifier;
         Ok((state, v_msg))
     }
-    #[inline]
-    pub fn sample_verify_second_sumcheck_ongoing_round<R: RngCore>(
-        rng: &mut R,
-    ) -> Option<MLVerifierMsg<E::Fr>> {
-        Self::sample_verify_first_sumcheck_ongoing_round(rng)
+    pub fn sample_verify_second_sumcheck_ongoing_round<H: TranscriptHash>(
+        p_msg: &MLProverMsg<E::Fr>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<Option<MLVerifierMsg<E::Fr>>> {
+        transcript.append_message(b"second sumcheck message", p_msg)?;
+        Ok(Some(AHPForMLSumcheck::sample_round(
+            transcript.challenge_rng(b"second sumcheck challenge")?,
+        )))
     }
 
     /// last round of sumcheck, send final randomness
-    pub fn verify_second_sumcheck_final_round<R: RngCore>(
-        mut state: VerifierSecondSumcheckState<E>,
+    pub fn verify_second_sumcheck_final_round<K: VerifierIndex<E::Fr>, H: TranscriptHash>(
+        mut state: VerifierSecondSumcheckState<E, K>,
         p_msg: MLProverMsg<E::Fr>,
-        rng: &mut R,
-    ) -> SResult<(VerifierSixthState<E>, VerifierFifthMessage<E::Fr>)> {
-        let (ml_final_msg, ml_verifier) =
-            AHPForMLSumcheck::verify_round(p_msg, state.second_verifier_state, rng)?;
+        transcript: &mut Transcript<H>,
+    ) -> SResult<(VerifierFifthState<E, K>, VerifierFourthMessage<E::Fr>)> {
+        transcript.append_message(b"second sumcheck message", &p_msg)?;
+        let (ml_final_msg, ml_verifier) = AHPForMLSumcheck::verify_round(
+            p_msg,
+            state.second_verifier_state,
+            transcript.challenge_rng(b"second sumcheck challenge")?,
+        )?;
         // let subclaim = AHPForMLSumcheck::subclaim(ml_verifier)?;
         let final_randomness = ml_final_msg.unwrap().randomness;
         state.second_verifier_state = ml_verifier;
 
-        let msg = VerifierFifthMessage {
+        let msg = VerifierFourthMessage {
             last_random_point: final_randomness,
         };
         Ok((state, msg))
     }
 
-    pub fn sample_verify_second_sumcheck_final_round<R: RngCore>(
-        rng: &mut R,
-    ) -> VerifierFifthMessage<E::Fr> {
-        VerifierFifthMessage {
-            last_random_point: AHPForMLSumcheck::sample_round(rng).randomness,
-        }
+    pub fn sample_verify_second_sumcheck_final_round<H: TranscriptHash>(
+        p_msg: &MLProverMsg<E::Fr>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<VerifierFourthMessage<E::Fr>> {
+        let msg = Self::sample_verify_second_sumcheck_ongoing_round(p_msg, transcript)?;
+        Ok(VerifierFourthMessage {
+            last_random_point: msg.unwrap().randomness,
+        })
+    }
+
+    /// absorb w(r_y[..log_n - 1]), so that challenges of the matrix evaluation proof depend on it
+    pub fn absorb_fifth_round<H: TranscriptHash>(
+        p_msg: &ProverFinalMessage<E>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<()> {
+        transcript.append_message(b"w(r_y)", p_msg)
     }
 
-    /// receive z(r_y), verify final claim
-    pub fn verify_sixth_round(
-        state: VerifierSixthState<E>,
+    /// receive w(r_y[..log_n - 1]), verify final claim
+    ///
+    /// `vp` can be the public `VerifierParameter` or a `DesignatedVerifierParameter`.
+    pub fn verify_fifth_round<O: OpeningVerifierKey<E>>(
+        state: VerifierFifthState<E>,
         p_msg: ProverFinalMessage<E>,
-        vp: &VerifierParameter<E>
+        vp: &O
     ) -> SResult<bool> {
+        Self::verify_final_claims(state, p_msg, vp, Self::matrix_evaluation)
+    }
 
-        let eq = eq_extension(&state.tor)?;
+    /// Check the final claims of the fifth round, except for the opening of w, which is returned so that
+    /// it can be accumulated with openings of other proofs.
+    pub fn check_fifth_round(
+        state: VerifierFifthState<E>,
+        p_msg: ProverFinalMessage<E>,
+    ) -> SResult<OpeningClaim<E>> {
+        Self::check_final_claims(state, p_msg, Self::matrix_evaluation)
+    }
+
+    /// sum_i r_i * M_i(r_x, r_y) from the sparse entries of the index
+    pub(crate) fn matrix_evaluation(
+        vk: &IndexVK<E::Fr>,
+        r_x: &[E::Fr],
+        r_y: &[E::Fr],
+        coefficients: &[E::Fr],
+    ) -> SResult<E::Fr> {
+        // the matrices have the same size, so eq(r_x, x) and eq(r_y, y) are computed once
+        let (eq_rx, eq_ry) = (eq_table(r_x), eq_table(r_y));
+        let mut m_rx_ry = E::Fr::zero();
+        for (r_i, matrix) in coefficients.iter().zip(vk.matrices.iter()) {
+            m_rx_ry += &(*r_i * &matrix.eval_with_eq(&eq_rx, &eq_ry)?);
+        }
+        Ok(m_rx_ry)
+    }
+
+    /// receive w(r_y[..log_n - 1]) and the proof of M_i(r_x, r_y) for every matrix, verify final claim
+    pub fn verify_fifth_round_preprocessed<O: OpeningVerifierKey<E>, H: TranscriptHash>(
+        state: VerifierFifthState<E, PreprocessedIndexVK<E>>,
+        p_msg: ProverFinalMessage<E>,
+        matrix_evaluation_proof: SparkProof<E>,
+        vp: &O,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<bool> {
+        Self::absorb_fifth_round(&p_msg, transcript)?;
+        Self::verify_final_claims(state, p_msg, vp, |vk, r_x, r_y, coefficients| {
+            if matrix_evaluation_proof.evaluations.len() != vk.num_matrices() {
+                return Err(invalid_arg("should have one evaluation for each matrix"));
+            }
+            let evaluations = matrix_evaluation_proof.evaluations.clone();
+            if !MLSpark::verify(vp, &vk.spark, r_x, r_y, matrix_evaluation_proof, transcript)? {
+                return Err(crate::Error::WrongWitness(Some(
+                    "Cannot verify evaluations of the matrices".into(),
+                )));
+            }
+            Ok(coefficients
+                .iter()
+                .zip(evaluations.iter())
+                .fold(E::Fr::zero(), |acc, (r_i, e)| acc + &(*r_i * e)))
+        })
+    }
 
-        // verify if public witness extension asserted by prover is correct
-        let mut r_v_0 = state.r_v.clone(); // extend r_v with zero
-        r_v_0.extend((0..(state.vk.log_n - ark_std::log2(state.v.len()) as usize)).map(|_|E::Fr::zero()));
-        if !MLPolyCommit::verify(vp, &state.commit, &r_v_0, state.z_rv_0, state.z_rv_0_proof)? {
-            return Err(invalid_arg("public witness failed in commitment check"));
+    /// verify final claim, where `matrix_evaluation` gives M(r_x, r_y) for M = sum_i r_i * M_i
+    fn verify_final_claims<K, O, M>(
+        state: VerifierFifthState<E, K>,
+        p_msg: ProverFinalMessage<E>,
+        vp: &O,
+        matrix_evaluation: M,
+    ) -> SResult<bool>
+    where
+        K: VerifierIndex<E::Fr>,
+        O: OpeningVerifierKey<E>,
+        M: FnOnce(&K, &[E::Fr], &[E::Fr], &[E::Fr]) -> SResult<E::Fr>,
+    {
+        let claim = Self::check_final_claims(state, p_msg, matrix_evaluation)?;
+        // verify if w_ry is correct using proof
+        if !vp.verify_opening(&claim.commitment, &claim.point, claim.eval, claim.proof)? {
+            return Err(crate::Error::WrongWitness(Some(
+                "Cannot verify w_ry".into()
+            )))
         };
 
+        Ok(true)
+    }
+
+    /// Check the final claims except for the opening of w, which is returned so that it can be checked
+    /// together with openings of other proofs.
+    pub fn check_final_claims<K, M>(
+        state: VerifierFifthState<E, K>,
+        p_msg: ProverFinalMessage<E>,
+        matrix_evaluation: M,
+    ) -> SResult<OpeningClaim<E>>
+    where
+        K: VerifierIndex<E::Fr>,
+        M: FnOnce(&K, &[E::Fr], &[E::Fr], &[E::Fr]) -> SResult<E::Fr>,
+    {
         let vk = state.vk;
-        let v = MLExtensionArray::from_vec(state.v)?;
-        if v.eval_at(&state.r_v)? != state.z_rv_0 {
-            return Err(invalid_arg("public witness is inconsistent with proof"));
-        }
 
         // verify first sumcheck
         let first_subclaim = AHPForMLSumcheck::check_and_generate_subclaim(
@@ -468,46 +535,45 @@ impl<E: PairingEngine> MLProofForR1CS<E> {
         )?;
         let r_x = first_subclaim.point;
         {
-            let mut eq_rx: E::Fr = E::Fr::one();
-            for p in eq.iter() {
-                eq_rx *= &p.eval_at(&r_x)?;
-            }
-            if (state.va * &state.vb - &state.vc) * &eq_rx != first_subclaim.expected_evaluation {
+            let actual = zerocheck::masked_evaluation(&state.tor, &r_x, &state.evaluations, &vk.products()?)?;
+            if actual != first_subclaim.expected_evaluation {
                 return Err(crate::Error::WrongWitness(Some(
                     "first sumcheck has wrong subclaim".into(),
                 )));
             }
         }
 
-        let z_ry = p_msg.z_ry;
         // verify second sumcheck
-        let second_claimed_sum =
-            state.r_a * &state.va + &(state.r_b * &state.vb) + &(state.r_c * &state.vc);
+        let second_claimed_sum = state
+            .coefficients
+            .iter()
+            .zip(state.evaluations.iter())
+            .fold(E::Fr::zero(), |acc, (r_i, e)| acc + &(*r_i * e));
         let second_subclaim = AHPForMLSumcheck::check_and_generate_subclaim(
             state.second_verifier_state,
             second_claimed_sum,
         )?;
         let expected = second_subclaim.expected_evaluation;
-        let (r_a, r_b, r_c) = (state.r_a, state.r_b, state.r_c);
         let r_y = second_subclaim.point;
-        let a_rx_ry = vk.matrix_a.eval_on_x(&r_x)?.eval_at(&r_y)?;
-        let b_rx_ry = vk.matrix_b.eval_on_x(&r_x)?.eval_at(&r_y)?;
-        let c_rx_ry = vk.matrix_c.eval_on_x(&r_x)?.eval_at(&r_y)?;
 
-        let actual = r_a * &a_rx_ry * &z_ry + &(r_b * &b_rx_ry * &z_ry) + &(r_c * &c_rx_ry * &z_ry);
+        let log_half = vk.log_n() - 1;
+        let w_ry = p_msg.w_ry;
+        let z_ry = Self::z_eval(state.v, w_ry, &r_y)?;
+
+        let m_rx_ry = matrix_evaluation(&vk, &r_x, &r_y, &state.coefficients)?;
+
+        let actual = m_rx_ry * &z_ry;
         if expected != actual {
             return Err(crate::Error::WrongWitness(Some(
-                "Cannot verify matrix A, B, C".into(),
+                "Cannot verify the matrices".into(),
             )))
         }
 
-        // verify if z_ry is correct using proof
-        if !MLPolyCommit::verify(vp, &state.commit, &r_y, z_ry, p_msg.proof_for_z_ry)? {
-            return Err(crate::Error::WrongWitness(Some(
-                "Cannot verify z_ry".into()
-            )))
-        };
-
-        Ok(true)
+        Ok(OpeningClaim {
+            commitment: state.commit,
+            point: r_y[..log_half].to_vec(),
+            eval: w_ry,
+            proof: p_msg.proof_for_w_ry,
+        })
     }
 }
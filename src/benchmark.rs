@@ -23,14 +23,14 @@ fn test_circuit<E: PairingEngine>(
 
     let mut rng = test_rng();
 
-    let timer = start_timer!(|| format!("Setup{}", config_str));
-    let (pp, vp) = MLProofForR1CS::setup(ark_std::log2(matrices.a.len()) as usize, &mut rng)?;
-        end_timer!(timer);
-
     let timer = start_timer!(|| format!("Index{}", config_str));
-    let index_pk = MLArgumentForR1CS::<E>::index(matrices.a, matrices.b, matrices.c)?;
+    let index_pk = MLArgumentForR1CS::<E>::index(matrices.a, matrices.b, matrices.c, matrices.num_instance_variables, matrices.num_witness_variables)?;
     let index_vk = index_pk.vk();
         end_timer!(timer);
+
+    let timer = start_timer!(|| format!("Setup{}", config_str));
+    let (pp, vp) = MLProofForR1CS::setup(index_pk.log_n, &mut rng)?;
+        end_timer!(timer);
     let timer = start_timer!(|| format!("Prove{}", config_str));
     let proof = MLArgumentForR1CS::<E>::prove(index_pk, v.to_vec(), w, &pp)?;
     let proof_serialized = {
@@ -43,7 +43,7 @@ fn test_circuit<E: PairingEngine>(
     println!("Communication Cost: {} bytes", proof_serialized.len());
     let timer = start_timer!(|| format!("Verify{}", config_str));
     let proof = Proof::<E>::deserialize(&proof_serialized[..])?;
-    let result = MLArgumentForR1CS::verify(index_vk, v, proof, &vp)?;
+    let result = MLArgumentForR1CS::<E>::verify(index_vk, v, proof, &vp)?;
     assert!(result);
     end_timer!(timer);
     Ok(())
@@ -0,0 +1,181 @@
+use ark_ec::{AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::One;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
+use hashbrown::HashMap;
+
+use crate::commitment::data_structures::VerifierParameter;
+use crate::commitment::verify::OpeningClaim;
+use crate::data_structures::transcript::{Transcript, TranscriptHash};
+use crate::error::{invalid_arg, SResult};
+
+/// Pending pairing checks of the form `prod e(g1_i, g2_i) = 1`.
+///
+/// Verification of an opening can stop at an accumulator, so that accumulators of many proofs are merged
+/// and decided later with one multi-pairing and one final exponentiation.
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct PairingAccumulator<E: PairingEngine> {
+    pub pairs: Vec<(E::G1Affine, E::G2Affine)>,
+}
+
+impl<E: PairingEngine> PairingAccumulator<E> {
+    /// accumulator without pending checks, which is always accepted
+    pub fn new() -> Self {
+        Self { pairs: Vec::new() }
+    }
+
+    /// Checks of an opening claim: `e(com / g^eval, h) * prod e(g^(x_i - t_i), proof_i) = 1`.
+    pub fn from_opening(vp: &VerifierParameter<E>, claim: &OpeningClaim<E>) -> SResult<Self> {
+        let nv = claim.commitment.nv;
+        if nv == 0 || nv > vp.nv || claim.point.len() != nv || claim.proof.proofs.len() != nv {
+            return Err(invalid_arg("number of variables is not supported by verifier parameter"));
+        }
+        let offset = vp.nv - nv;
+        let mut lefts = Vec::with_capacity(nv + 1);
+        lefts.push(claim.commitment.g_product.into_projective() - &vp.g.mul(claim.eval));
+        for i in 0..nv {
+            lefts.push(vp.g.mul(claim.point[i]) - &vp.g_mask_random[offset + i].into_projective());
+        }
+        let lefts = E::G1Projective::batch_normalization_into_affine(&lefts);
+        let rights = ark_std::iter::once(vp.h).chain(claim.proof.proofs.iter().cloned());
+        Ok(Self {
+            pairs: lefts.into_iter().zip(rights).collect(),
+        })
+    }
+
+    /// Merge `other` scaled by `challenge`, i.e. accept only if both are accepted, except with small probability.
+    /// Pairs sharing the same element of G2 are combined, so merging openings from the same setup keeps one pair
+    /// for `h`.
+    ///
+    /// `challenge` must be unpredictable to whoever produced the accumulators: a prover who knows it in advance
+    /// can make a wrong check in `other` cancel one in `self`. Sample it after both accumulators are fixed, or
+    /// use `merge_with_transcript`.
+    pub fn merge(&mut self, other: Self, challenge: E::Fr) {
+        self.merge_all(ark_std::iter::once((other, challenge)))
+    }
+
+    /// Merge many accumulators, each scaled by its challenge, under the same conditions as `merge`. Pairs are
+    /// keyed by their element of G2, so this takes time linear in the number of pairs.
+    pub fn merge_all<I: IntoIterator<Item = (Self, E::Fr)>>(&mut self, others: I) {
+        let mut index: HashMap<E::G2Affine, usize> = HashMap::with_capacity(self.pairs.len());
+        let mut g1s: Vec<E::G1Projective> = Vec::with_capacity(self.pairs.len());
+        let mut g2s: Vec<E::G2Affine> = Vec::with_capacity(self.pairs.len());
+        let mut add = |g1: E::G1Projective, g2: E::G2Affine| match index.get(&g2) {
+            Some(&i) => g1s[i] += &g1,
+            None => {
+                index.insert(g2, g2s.len());
+                g1s.push(g1);
+                g2s.push(g2);
+            }
+        };
+        for (g1, g2) in ark_std::mem::take(&mut self.pairs) {
+            add(g1.into_projective(), g2);
+        }
+        for (other, challenge) in others {
+            for (g1, g2) in other.pairs {
+                add(g1.mul(challenge), g2);
+            }
+        }
+        let g1s = E::G1Projective::batch_normalization_into_affine(&g1s);
+        self.pairs = g1s.into_iter().zip(g2s.into_iter()).collect();
+    }
+
+    /// absorb the pending checks into `transcript`
+    pub fn absorb_into<H: TranscriptHash>(&self, transcript: &mut Transcript<H>) -> SResult<()> {
+        transcript.append_message(b"pairing accumulator", self)
+    }
+
+    /// Merge `other` with a challenge squeezed from `transcript` after absorbing both accumulators, so that the
+    /// challenge is bound to them.
+    pub fn merge_with_transcript<H: TranscriptHash>(&mut self, other: Self, transcript: &mut Transcript<H>) -> SResult<()> {
+        self.absorb_into(transcript)?;
+        other.absorb_into(transcript)?;
+        let challenge = transcript.challenge_scalar(b"accumulator challenge")?;
+        self.merge(other, challenge);
+        Ok(())
+    }
+
+    /// Evaluate the pending checks with one product of pairings.
+    pub fn decide(&self) -> bool {
+        let timer = start_timer!(|| "Decide pairing accumulator");
+        let pairings: Vec<(E::G1Prepared, E::G2Prepared)> = self
+            .pairs
+            .iter()
+            .map(|(g1, g2)| (E::G1Prepared::from(*g1), E::G2Prepared::from(*g2)))
+            .collect();
+        let result = E::product_of_pairings(pairings.iter()).is_one();
+        end_timer!(timer);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commitment::accumulator::PairingAccumulator;
+    use crate::commitment::verify::OpeningClaim;
+    use crate::commitment::open::Proof;
+    use crate::commitment::MLPolyCommit;
+    use crate::data_structures::transcript::Transcript;
+    use crate::test_utils::TestCurve;
+    use ark_ec::PairingEngine;
+    use ark_ff::{test_rng, One, UniformRand};
+    use linear_sumcheck::data_structures::MLExtensionArray;
+
+    type E = TestCurve;
+    type Fr = <E as PairingEngine>::Fr;
+
+    #[test]
+    fn test_merge_and_decide() {
+        let mut rng = test_rng();
+        let (pp, vp, _) = MLPolyCommit::<E>::keygen(6, &mut rng).unwrap();
+        // openings of polynomials with different numbers of variables at different points
+        let claims: Vec<_> = [6usize, 4, 5]
+            .iter()
+            .map(|&nv| {
+                let poly = MLExtensionArray::from_vec((0..(1 << nv)).map(|_| Fr::rand(&mut rng)).collect()).unwrap();
+                let point: Vec<_> = (0..nv).map(|_| Fr::rand(&mut rng)).collect();
+                let commitment = MLPolyCommit::commit(&pp, poly.clone()).unwrap();
+                let (eval, proof, _) = MLPolyCommit::open(&pp, poly, &point).unwrap();
+                OpeningClaim { commitment, point, eval, proof }
+            })
+            .collect();
+
+        let mut acc = PairingAccumulator::new();
+        assert!(acc.decide());
+        for claim in claims.iter() {
+            let single = PairingAccumulator::from_opening(&vp, claim).unwrap();
+            assert!(single.decide());
+            acc.merge(single, Fr::rand(&mut rng));
+        }
+        assert!(acc.decide());
+        // all pairs with h are combined
+        assert_eq!(acc.pairs.len(), 1 + 6 + 4 + 5);
+
+        // wrong evaluation poisons the merged accumulator
+        let claim = &claims[1];
+        let wrong_claim = OpeningClaim {
+            commitment: claim.commitment.clone(),
+            point: claim.point.clone(),
+            eval: claim.eval + Fr::one(),
+            proof: Proof { h: claim.proof.h, proofs: claim.proof.proofs.clone() },
+        };
+        let wrong = PairingAccumulator::from_opening(&vp, &wrong_claim).unwrap();
+        assert!(!wrong.decide());
+        acc.merge(wrong.clone(), Fr::rand(&mut rng));
+        assert!(!acc.decide());
+
+        // merging all at once, and merging with challenges from a transcript
+        let singles: Vec<_> = claims.iter().map(|claim| PairingAccumulator::from_opening(&vp, claim).unwrap()).collect();
+        let mut all = PairingAccumulator::new();
+        all.merge_all(singles.iter().cloned().map(|single| (single, Fr::rand(&mut rng))));
+        assert!(all.decide());
+        assert_eq!(all.pairs.len(), 1 + 6 + 4 + 5);
+        let mut transcript: Transcript = Transcript::new(b"test");
+        let mut acc = PairingAccumulator::new();
+        for single in singles {
+            acc.merge_with_transcript(single, &mut transcript).unwrap();
+        }
+        assert!(acc.decide());
+        acc.merge_with_transcript(wrong, &mut transcript).unwrap();
+        assert!(!acc.decide());
+    }
+}
@@ -0,0 +1,117 @@
+use ark_ec::{PairingEngine, ProjectiveCurve, AffineCurve};
+use crate::commitment::MLPolyCommit;
+use crate::commitment::commit::Commitment;
+use crate::commitment::data_structures::PublicParameter;
+use crate::commitment::open::Proof;
+use crate::commitment::verify::{OpeningClaim, OpeningVerifierKey};
+use crate::error::{SResult, invalid_arg};
+use linear_sumcheck::data_structures::MLExtensionArray;
+use linear_sumcheck::data_structures::ml_extension::MLExtension;
+use ark_ff::{One, Zero};
+
+impl<E: PairingEngine> MLPolyCommit<E> {
+    /// Open several polynomials with the same number of variables at the same point using one proof.
+    ///
+    /// The polynomials are combined using powers of `challenge`, which should be sampled after
+    /// the commitments and evaluations are sent.
+    pub fn batch_open(pp: &PublicParameter<E>,
+                      polynomials: Vec<MLExtensionArray<E::Fr>>,
+                      point: &[E::Fr],
+                      challenge: E::Fr) -> SResult<Proof<E>> {
+        let mut polynomials = polynomials.into_iter();
+        let first = polynomials.next().ok_or(invalid_arg("no polynomial to open"))?;
+        let nv = first.num_variables()?;
+        let mut combined = first.into_table()?;
+        let mut coefficient = challenge;
+        for poly in polynomials {
+            if poly.num_variables()? != nv {
+                return Err(invalid_arg("polynomials opened together should have same number of variables"));
+            }
+            for (acc, x) in combined.iter_mut().zip(poly.into_table()?.into_iter()) {
+                *acc += coefficient * x;
+            }
+            coefficient *= challenge;
+        }
+        let (_, proof, _) = Self::open(pp, MLExtensionArray::from_vec(combined)?, point)?;
+        Ok(proof)
+    }
+
+    /// Verify that `evals[i]` is the evaluation of the polynomial committed in `commitments[i]` at `point`,
+    /// given a proof produced by `batch_open`.
+    pub fn batch_verify<K: OpeningVerifierKey<E>>(vp: &K,
+                                                   commitments: &[&Commitment<E>],
+                                                   point: &[E::Fr],
+                                                   evals: &[E::Fr],
+                                                   challenge: E::Fr,
+                                                   proof: Proof<E>) -> SResult<bool> {
+        let claim = Self::batch_claim(commitments, point, evals, challenge, proof)?;
+        vp.verify_opening(&claim.commitment, &claim.point, claim.eval, claim.proof)
+    }
+
+    /// Combine the commitments and evaluations opened by `batch_open` into one opening claim, so that it can be
+    /// checked together with other claims by `verify_many`.
+    pub fn batch_claim(commitments: &[&Commitment<E>],
+                       point: &[E::Fr],
+                       evals: &[E::Fr],
+                       challenge: E::Fr,
+                       proof: Proof<E>) -> SResult<OpeningClaim<E>> {
+        if commitments.is_empty() || commitments.len() != evals.len() {
+            return Err(invalid_arg("number of commitments and evaluations mismatch"));
+        }
+        let nv = commitments[0].nv;
+        let mut combined_commitment = E::G1Projective::zero();
+        let mut combined_eval = E::Fr::zero();
+        let mut coefficient = E::Fr::one();
+        for (commitment, eval) in commitments.iter().zip(evals.iter()) {
+            if commitment.nv != nv {
+                return Err(invalid_arg("polynomials opened together should have same number of variables"));
+            }
+            combined_commitment += &commitment.g_product.mul(coefficient);
+            combined_eval += coefficient * eval;
+            coefficient *= challenge;
+        }
+        Ok(OpeningClaim {
+            commitment: Commitment {
+                nv,
+                g_product: combined_commitment.into_affine(),
+            },
+            point: point.to_vec(),
+            eval: combined_eval,
+            proof,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commitment::MLPolyCommit;
+    use crate::test_utils::TestCurve;
+    use linear_sumcheck::data_structures::MLExtensionArray;
+    use linear_sumcheck::data_structures::ml_extension::MLExtension;
+    use ark_ec::PairingEngine;
+    use ark_ff::{test_rng, UniformRand, One};
+
+    type E = TestCurve;
+    type Fr = <E as PairingEngine>::Fr;
+
+    #[test]
+    fn batch_open_test() {
+        let mut rng = test_rng();
+        let (pp, vp, _) = MLPolyCommit::<E>::keygen(6, &mut rng).unwrap();
+        let polys: Vec<_> = (0..4).map(|_| MLExtensionArray::from_vec((0..(1 << 5))
+            .map(|_| Fr::rand(&mut rng)).collect()).unwrap()).collect();
+        let point: Vec<_> = (0..5).map(|_| Fr::rand(&mut rng)).collect();
+        let commitments: Vec<_> = polys.iter()
+            .map(|p| MLPolyCommit::commit(&pp, p.clone()).unwrap()).collect();
+        let mut evals: Vec<_> = polys.iter().map(|p| p.eval_at(&point).unwrap()).collect();
+        let challenge = Fr::rand(&mut rng);
+        let commitments: Vec<_> = commitments.iter().collect();
+
+        let proof = MLPolyCommit::batch_open(&pp, polys.clone(), &point, challenge).unwrap();
+        assert!(MLPolyCommit::batch_verify(&vp, &commitments, &point, &evals, challenge, proof).unwrap());
+
+        evals[2] += Fr::one();
+        let proof = MLPolyCommit::batch_open(&pp, polys, &point, challenge).unwrap();
+        assert!(!MLPolyCommit::batch_verify(&vp, &commitments, &point, &evals, challenge, proof).unwrap());
+    }
+}
@@ -1,7 +1,7 @@
 use ark_ec::{PairingEngine, ProjectiveCurve};
 use crate::commitment::MLPolyCommit;
 use crate::commitment::data_structures::PublicParameter;
-use crate::error::SResult;
+use crate::error::{SResult, invalid_arg};
 use linear_sumcheck::data_structures::MLExtensionArray;
 use linear_sumcheck::data_structures::ml_extension::MLExtension;
 use ark_ec::msm::VariableBaseMSM;
@@ -13,16 +13,31 @@ pub struct Commitment<E: PairingEngine>{
     pub g_product: E::G1Affine,
 }
 
+impl<E: PairingEngine> Clone for Commitment<E> {
+    fn clone(&self) -> Self {
+        Self {
+            nv: self.nv,
+            g_product: self.g_product,
+        }
+    }
+}
+
 impl<E: PairingEngine> MLPolyCommit<E> {
+    /// Commit to a polynomial. The polynomial can have fewer variables than the public parameter,
+    /// in which case it is committed using the last `nv` variables of the trapdoor.
     pub fn commit(pp: &PublicParameter<E>, polynomial: MLExtensionArray<E::Fr>) -> SResult<Commitment<E>> {
         let nv = polynomial.num_variables()?;
+        if nv == 0 || nv > pp.nv {
+            return Err(invalid_arg("number of variables is not supported by public parameter"));
+        }
+        let offset = pp.nv - nv;
         let timer = start_timer!(||"mapping variables into representation");
         let scalars: Vec<_> = polynomial.into_table()?
             .into_iter().map(|x|x.into_repr()).collect();
         end_timer!(timer);
         // let g_bases: Vec<_> = E::G1Projective::batch_normalization_into_affine(&pp.powers_of_g[0]);  // do it in setup
         let timer = start_timer!(||"MSM");
-        let g_product: E::G1Projective = VariableBaseMSM::multi_scalar_mul(&pp.powers_of_g[0], scalars.as_slice());
+        let g_product: E::G1Projective = VariableBaseMSM::multi_scalar_mul(&pp.powers_of_g[offset], scalars.as_slice());
         end_timer!(timer);
         
         Ok(Commitment{nv, g_product: g_product.into_affine()})
@@ -65,4 +80,17 @@ mod test{
 
     }
 
+    #[test]
+    fn commit_fewer_variables_test(){
+        let mut rng = test_rng();
+        let (pp, _, t) = MLPolyCommit::<E>::keygen(6, &mut rng).unwrap();
+        let poly =
+            MLExtensionArray::from_vec((0..(1<<4))
+                .map(|_|Fr::rand(&mut rng)).collect()).unwrap();
+        let commit_expected = naive_commit(&pp, poly.clone(), &t[2..]).unwrap();
+        let commit_actual = MLPolyCommit::commit(&pp, poly).unwrap();
+
+        assert_eq!(commit_actual.g_product, commit_expected.g_product);
+    }
+
 }
\ No newline at end of file
@@ -1,5 +1,7 @@
 use ark_ec::PairingEngine;
 use ark_serialize::{CanonicalSerialize, CanonicalDeserialize, Read, Write, SerializationError};
+use sha2::Digest;
+use crate::error::SResult;
 #[allow(type_alias_bounds)]
 pub type EvaluationHyperCubeOnG1<E: PairingEngine> = Vec<E::G1Affine>;
 #[allow(type_alias_bounds)]
@@ -14,6 +16,8 @@ pub struct PublicParameter<E: PairingEngine> {
     pub powers_of_h: Vec<EvaluationHyperCubeOnG2<E>>,
     pub g: E::G1Affine,
     pub h: E::G2Affine,
+    /// identifier of the verifier parameter generated along with this parameter
+    pub id: SetupIdentifier,
 }
 
 #[derive(CanonicalSerialize, CanonicalDeserialize)]
@@ -23,4 +27,81 @@ pub struct VerifierParameter<E: PairingEngine> {
     pub h: E::G2Affine,
     /// g^t1, g^t2, ...
     pub g_mask_random: Vec<E::G1Affine>,
-}
\ No newline at end of file
+    /// digest of all fields above, computed once at setup
+    pub id: SetupIdentifier,
+}
+
+/// Verifier parameter for a verifier who ran the setup itself. Knowing the trapdoor `t`,
+/// it checks an opening with two pairings instead of `nv + 1`.
+/// This parameter must never be revealed to the prover.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct DesignatedVerifierParameter<E: PairingEngine> {
+    pub nv: usize,
+    pub g: E::G1Affine,
+    pub h: E::G2Affine,
+    /// t1, t2, ... used in setup
+    pub t: Vec<E::Fr>,
+    /// identifier of the public verifier parameter
+    pub id: SetupIdentifier,
+}
+
+/// Identifies the setup that produced a parameter: the SHA-256 digest of the serialized verifier parameter,
+/// which covers `g_mask_random`. It is stored in the public parameter and in both kinds of verifier parameter,
+/// so that the prover and the verifier can bind the same setup into a transcript.
+#[derive(Clone, PartialEq, Eq, Debug, CanonicalSerialize, CanonicalDeserialize)]
+pub struct SetupIdentifier(pub Vec<u8>);
+
+impl SetupIdentifier {
+    /// Compute the digest. Use it to check the identifier of a parameter from an untrusted source.
+    pub fn compute<E: PairingEngine>(
+        nv: usize,
+        g: &E::G1Affine,
+        h: &E::G2Affine,
+        g_mask_random: &[E::G1Affine],
+    ) -> SResult<Self> {
+        let mut bytes = Vec::new();
+        b"r1cs-spartan-raw/setup-id".to_vec().serialize(&mut bytes)?;
+        nv.serialize(&mut bytes)?;
+        g.serialize(&mut bytes)?;
+        h.serialize(&mut bytes)?;
+        g_mask_random.to_vec().serialize(&mut bytes)?;
+        Ok(SetupIdentifier(sha2::Sha256::digest(&bytes).to_vec()))
+    }
+}
+
+impl<E: PairingEngine> PublicParameter<E> {
+    pub fn id(&self) -> &SetupIdentifier {
+        &self.id
+    }
+}
+
+impl<E: PairingEngine> VerifierParameter<E> {
+    /// build verifier parameter and compute its identifier
+    pub fn new(nv: usize, g: E::G1Affine, h: E::G2Affine, g_mask_random: Vec<E::G1Affine>) -> SResult<Self> {
+        let id = SetupIdentifier::compute::<E>(nv, &g, &h, &g_mask_random)?;
+        Ok(Self { nv, g, h, g_mask_random, id })
+    }
+
+    pub fn id(&self) -> &SetupIdentifier {
+        &self.id
+    }
+}
+
+impl<E: PairingEngine> DesignatedVerifierParameter<E> {
+    pub fn id(&self) -> &SetupIdentifier {
+        &self.id
+    }
+}
+
+impl<E: PairingEngine> DesignatedVerifierParameter<E> {
+    /// build designated verifier parameter from public verifier parameter and the setup trapdoor
+    pub fn new(vp: &VerifierParameter<E>, t: Vec<E::Fr>) -> Self {
+        Self {
+            nv: vp.nv,
+            g: vp.g,
+            h: vp.h,
+            t,
+            id: vp.id.clone(),
+        }
+    }
+}
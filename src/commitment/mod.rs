@@ -5,6 +5,8 @@ pub mod setup;
 pub mod commit;
 pub mod open;
 pub mod verify;
+pub mod batch;
+pub mod accumulator;
 
 use ark_ec::PairingEngine;
 
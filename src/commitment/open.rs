@@ -2,7 +2,7 @@ use ark_ec::{PairingEngine, ProjectiveCurve};
 use crate::commitment::MLPolyCommit;
 use crate::commitment::data_structures::PublicParameter;
 use linear_sumcheck::data_structures::MLExtensionArray;
-use crate::error::SResult;
+use crate::error::{SResult, invalid_arg};
 use linear_sumcheck::data_structures::ml_extension::MLExtension;
 use ark_ff::{One, Zero, PrimeField};
 use ark_ec::msm::VariableBaseMSM;
@@ -16,6 +16,7 @@ pub struct Proof<E: PairingEngine> {
 
 impl<E: PairingEngine> MLPolyCommit<E> {
     // evaluate the polynomial and calculate the proof
+    // polynomial with fewer variables than `pp` is opened against the last `nv` variables of the trapdoor
     pub fn open(pp: &PublicParameter<E>,
                 polynomial: MLExtensionArray<E::Fr>,
                 point: &[E::Fr]) -> SResult<(E::Fr, Proof<E>, Vec<Vec<E::Fr>>)> {
@@ -23,6 +24,10 @@ impl<E: PairingEngine> MLPolyCommit<E> {
         let eval_result = polynomial.eval_at(point)?;
         end_timer!(timer);
         let nv = polynomial.num_variables()?;
+        if nv == 0 || nv > pp.nv || point.len() != nv {
+            return Err(invalid_arg("number of variables is not supported by public parameter"));
+        }
+        let offset = pp.nv - nv;
         let mut r: Vec<Vec<E::Fr>> = (0..nv+1)
             .map(|_|Vec::new())
             .collect();
@@ -46,7 +51,7 @@ impl<E: PairingEngine> MLPolyCommit<E> {
             let scalars: Vec<_> = (0..(1 << k)).map(|x|q[k][x >> 1].into_repr())  // fine
                 .collect();
 
-            let pi_h = VariableBaseMSM::multi_scalar_mul(&pp.powers_of_h[i], &scalars).into_affine(); // no need to move outside and partition
+            let pi_h = VariableBaseMSM::multi_scalar_mul(&pp.powers_of_h[offset + i], &scalars).into_affine(); // no need to move outside and partition
             proofs.push(pi_h);
         }
         end_timer!(timer);
@@ -79,27 +79,23 @@ impl<E: PairingEngine> MLPolyCommit<E> {
         }
 
         end_timer!(variable_mul_timer);
-        let pp = PublicParameter{
-            nv,
-            g,
-            h,
-            powers_of_g,
-            powers_of_h
-        };
         // calculate vp
         let vp_generation_timer = start_timer!(||"VP generation");
         let vp = {
             let window_size = FixedBaseMSM::get_mul_window_size(nv);
             let g_table = FixedBaseMSM::get_window_table(scalar_bits, window_size, g.into_projective());
             let g_mask = E::G1Projective::batch_normalization_into_affine(&FixedBaseMSM::multi_scalar_mul(scalar_bits, window_size, &g_table, &t));
-            VerifierParameter{
-                nv,
-                g,
-                h,
-                g_mask_random: g_mask,
-            }
+            VerifierParameter::new(nv, g, h, g_mask)?
         };
         end_timer!(vp_generation_timer);
+        let pp = PublicParameter{
+            nv,
+            g,
+            h,
+            powers_of_g,
+            powers_of_h,
+            id: vp.id.clone(),
+        };
 
         Ok((pp, vp, t))
     }
@@ -110,7 +106,7 @@ mod tests{
     use rand::RngCore;
     use ark_ec::{PairingEngine, ProjectiveCurve, AffineCurve};
     use crate::error::SResult;
-    use crate::commitment::data_structures::{PublicParameter, EvaluationHyperCubeOnG1, EvaluationHyperCubeOnG2};
+    use crate::commitment::data_structures::{PublicParameter, EvaluationHyperCubeOnG1, EvaluationHyperCubeOnG2, SetupIdentifier};
     use ark_ff::{UniformRand, test_rng};
     use crate::data_structures::eq::eq_extension;
     use linear_sumcheck::data_structures::ml_extension::ArithmeticCombination;
@@ -139,7 +135,8 @@ mod tests{
             g: g.into_affine(),
             h: h.into_affine(),
             powers_of_g,
-            powers_of_h
+            powers_of_h,
+            id: SetupIdentifier(Vec::new()),
         })
     }
 
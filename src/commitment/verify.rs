@@ -1,16 +1,67 @@
 use ark_ec::{PairingEngine, ProjectiveCurve, AffineCurve};
 use crate::commitment::MLPolyCommit;
+use crate::commitment::accumulator::PairingAccumulator;
 use crate::commitment::commit::Commitment;
-use crate::commitment::data_structures::VerifierParameter;
+use crate::commitment::data_structures::{VerifierParameter, DesignatedVerifierParameter};
 use crate::commitment::open::Proof;
-use ark_ff::PrimeField;
-use crate::error::SResult;
-use ark_ec::msm::FixedBaseMSM;
+use ark_ff::{One, PrimeField};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
+use crate::error::{SResult, invalid_arg};
+use ark_ec::msm::{FixedBaseMSM, VariableBaseMSM};
 
+/// Key which is able to check an opening of a commitment.
+pub trait OpeningVerifierKey<E: PairingEngine> {
+    /// check that `eval` is the evaluation at `point` of the polynomial committed in `commitment`
+    fn verify_opening(&self, commitment: &Commitment<E>, point: &[E::Fr], eval: E::Fr, proof: Proof<E>) -> SResult<bool>;
+}
+
+impl<E: PairingEngine> OpeningVerifierKey<E> for VerifierParameter<E> {
+    fn verify_opening(&self, commitment: &Commitment<E>, point: &[E::Fr], eval: E::Fr, proof: Proof<E>) -> SResult<bool> {
+        MLPolyCommit::verify(self, commitment, point, eval, proof)
+    }
+}
+
+impl<E: PairingEngine> OpeningVerifierKey<E> for DesignatedVerifierParameter<E> {
+    fn verify_opening(&self, commitment: &Commitment<E>, point: &[E::Fr], eval: E::Fr, proof: Proof<E>) -> SResult<bool> {
+        MLPolyCommit::verify_designated(self, commitment, point, eval, proof)
+    }
+}
+
+/// Claim that the polynomial committed in `commitment` evaluates to `eval` at `point`, with its opening proof.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct OpeningClaim<E: PairingEngine> {
+    pub commitment: Commitment<E>,
+    pub point: Vec<E::Fr>,
+    pub eval: E::Fr,
+    pub proof: Proof<E>,
+}
 
 impl<E: PairingEngine> MLPolyCommit<E> {
+    /// Verify many opening claims, possibly at different points, with one multi-pairing.
+    ///
+    /// Claim `j` is scaled by `challenge^j`, so `challenge` should be sampled after all claims are fixed. All
+    /// checks `e(com / g^eval, h) = prod e(g^(t_i - x_i), proof_i)` are combined into one product of pairings
+    /// sharing the final exponentiation, where the terms paired with `h` are merged into one.
+    pub fn verify_many(vp: &VerifierParameter<E>, claims: &[OpeningClaim<E>], challenge: E::Fr) -> SResult<bool> {
+        let mut others = Vec::with_capacity(claims.len());
+        let mut coefficient = E::Fr::one();
+        for claim in claims.iter() {
+            others.push((PairingAccumulator::from_opening(vp, claim)?, coefficient));
+            coefficient *= challenge;
+        }
+        let mut accumulator = PairingAccumulator::new();
+        accumulator.merge_all(others);
+        Ok(accumulator.decide())
+    }
+
     pub fn verify(vp: &VerifierParameter<E>,commitment: &Commitment<E>, point: &[E::Fr], eval: E::Fr, proof: Proof<E>)
     ->SResult<bool>{
+        let nv = commitment.nv;
+        if nv == 0 || nv > vp.nv || point.len() != nv || proof.proofs.len() != nv {
+            return Err(invalid_arg("number of variables is not supported by verifier parameter"));
+        }
+        // polynomial with fewer variables is committed using the last `nv` variables of the trapdoor
+        let offset = vp.nv - nv;
         let left =
             E::pairing(commitment.g_product.into_projective() - &vp.g.mul(eval), vp.h);
         // let mut right = E::Fqk::one();
@@ -18,15 +69,15 @@ impl<E: PairingEngine> MLPolyCommit<E> {
         //     right *= &E::pairing(vp.g_mask_random[i] - &vp.g.mul(point[i]), proof.proofs[i]);
         // }
         let scalar_size = E::Fr::size_in_bits();
-        let window_size = FixedBaseMSM::get_mul_window_size(vp.nv);
+        let window_size = FixedBaseMSM::get_mul_window_size(nv);
         let timer = start_timer!(||"MSM");
         let vp_g_table = FixedBaseMSM::get_window_table(scalar_size, window_size, vp.g.into_projective());
         let vp_g_mul: Vec<E::G1Projective> = FixedBaseMSM::multi_scalar_mul(scalar_size, window_size, &vp_g_table, point); // may have overhead
         end_timer!(timer);
         let timer = start_timer!(||"Pairing");
         let timer2 = start_timer!(||"Calculating Left");
-        let pairing_lefts: Vec<_> = (0..vp.nv).map(|i|
-            vp.g_mask_random[i].into_projective() - &vp_g_mul[i]).collect();
+        let pairing_lefts: Vec<_> = (0..nv).map(|i|
+            vp.g_mask_random[offset + i].into_projective() - &vp_g_mul[i]).collect();
         let pairing_lefts: Vec<E::G1Affine> = E::G1Projective::batch_normalization_into_affine(&pairing_lefts);
         let pairing_lefts: Vec<E::G1Prepared> = pairing_lefts.into_iter().map(|x|E::G1Prepared::from(x)).collect();
         end_timer!(timer2);
@@ -43,6 +94,28 @@ impl<E: PairingEngine> MLPolyCommit<E> {
         end_timer!(timer);
         Ok(left == right)
     }
+
+    /// Verify the opening using the setup trapdoor. Instead of pairing each proof element with
+    /// `g^(t_i - x_i)`, fold the proof into `h^(sum (t_i - x_i) q_i(t))` and check
+    /// `e(com / g^eval, h) = e(g, h^(sum (t_i - x_i) q_i(t)))`.
+    pub fn verify_designated(dvp: &DesignatedVerifierParameter<E>, commitment: &Commitment<E>, point: &[E::Fr], eval: E::Fr, proof: Proof<E>)
+    -> SResult<bool> {
+        let nv = commitment.nv;
+        if nv == 0 || nv > dvp.nv || point.len() != nv || proof.proofs.len() != nv || dvp.t.len() != dvp.nv {
+            return Err(invalid_arg("number of variables is not supported by designated verifier parameter"));
+        }
+        let offset = dvp.nv - nv;
+        let scalars: Vec<_> = dvp.t[offset..].iter().zip(point.iter())
+            .map(|(t, x)| (*t - x).into_repr()).collect();
+        let timer = start_timer!(||"MSM");
+        let folded_proof: E::G2Projective = VariableBaseMSM::multi_scalar_mul(&proof.proofs, &scalars);
+        end_timer!(timer);
+        let timer = start_timer!(||"Pairing");
+        let left = E::pairing(commitment.g_product.into_projective() - &dvp.g.mul(eval), dvp.h);
+        let right = E::pairing(dvp.g, folded_proof);
+        end_timer!(timer);
+        Ok(left == right)
+    }
 }
 
 #[cfg(test)]
@@ -54,6 +127,7 @@ mod sanity {
     use ark_ff::{UniformRand, Zero};
     use ark_ec::{PairingEngine, AffineCurve};
     use linear_sumcheck::data_structures::ml_extension::MLExtension;
+    use crate::commitment::data_structures::DesignatedVerifierParameter;
 
     type E = TestCurve;
     type Fr = <TestCurve as PairingEngine>::Fr;
@@ -93,4 +167,26 @@ mod sanity {
         let result = MLPolyCommit::verify(&vp, &com, &point, ev, pf).expect("cannot verify");
         assert!(result);
     }
+
+    #[test]
+    fn designated_verifier_agrees_with_public_verifier() {
+        let nv = 8;
+        let mut rng = test_rng();
+        let (pp, vp, t) = MLPolyCommit::<E>::keygen(nv, &mut rng).unwrap();
+        let dvp = DesignatedVerifierParameter::new(&vp, t);
+        let poly =
+            MLExtensionArray::from_vec((0..(1<<nv))
+                .map(|_|Fr::rand(&mut rng)).collect()).unwrap();
+        let point: Vec<_> = (0..nv).map(|_|Fr::rand(&mut rng)).collect();
+        let com = MLPolyCommit::commit(&pp, poly.clone()).expect("cannot commit");
+
+        let (ev, pf, _) = MLPolyCommit::open(&pp, poly.clone(), &point).expect("cannot open");
+        assert!(MLPolyCommit::verify_designated(&dvp, &com, &point, ev, pf).expect("cannot verify"));
+
+        // a wrong evaluation should be rejected by both verifiers
+        let (ev, pf, _) = MLPolyCommit::open(&pp, poly.clone(), &point).expect("cannot open");
+        assert!(!MLPolyCommit::verify_designated(&dvp, &com, &point, ev + Fr::one(), pf).expect("cannot verify"));
+        let (ev, pf, _) = MLPolyCommit::open(&pp, poly, &point).expect("cannot open");
+        assert!(!MLPolyCommit::verify(&vp, &com, &point, ev + Fr::one(), pf).expect("cannot verify"));
+    }
 }
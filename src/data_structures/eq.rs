@@ -1,5 +1,6 @@
 use ark_ff::Field;
 use linear_sumcheck::data_structures::MLExtensionArray;
+use crate::error::{invalid_arg, SResult};
 
 /// Generate polynomial P(x) = eq(t,x) represented by products of multilinear polynomials
 pub fn eq_extension<F: Field>(t: &[F]) -> Result<Vec<MLExtensionArray<F>>, crate::Error> {
@@ -19,12 +20,41 @@ pub fn eq_extension<F: Field>(t: &[F]) -> Result<Vec<MLExtensionArray<F>>, crate
     Ok(result)
 }
 
+/// Generate evaluations of eq(t,x) on the boolean hypercube as one dense table, where bit `i` of the index is `x_i`
+pub fn eq_table<F: Field>(t: &[F]) -> Vec<F> {
+    let mut table = Vec::with_capacity(1 << t.len());
+    table.push(F::one());
+    for (i, ti) in t.iter().enumerate() {
+        let one_minus_ti = F::one() - ti;
+        for x in 0..(1 << i) {
+            let val = table[x];
+            table.push(val * ti);
+            table[x] = val * &one_minus_ti;
+        }
+    }
+    table
+}
+
+/// Evaluate eq(t,x) at arbitrary point x
+pub fn eq_eval<F: Field>(t: &[F], x: &[F]) -> SResult<F> {
+    if t.len() != x.len() {
+        return Err(invalid_arg("dimension of t and x should be the same"));
+    }
+    let mut result = F::one();
+    for (ti, xi) in t.iter().zip(x.iter()) {
+        let ti_xi = *ti * xi;
+        result *= ti_xi + ti_xi - xi - ti + F::one();
+    }
+    Ok(result)
+}
+
 #[cfg(test)]
 mod test {
-    use crate::data_structures::eq::eq_extension;
+    use crate::data_structures::eq::{eq_extension, eq_table, eq_eval};
     use crate::test_utils::bits_to_field_elements;
-    use ark_ff::{One, Zero};
+    use ark_ff::{One, Zero, test_rng, UniformRand};
     use linear_sumcheck::data_structures::ml_extension::MLExtension;
+    use linear_sumcheck::data_structures::MLExtensionArray;
 
     #[test]
     fn functionality_test() {
@@ -44,4 +74,23 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn eq_table_test() {
+        type F = crate::test_utils::TestCurveFr;
+        let mut rng = test_rng();
+        let t: Vec<F> = (0..7).map(|_| F::rand(&mut rng)).collect();
+        let eq_ext = eq_extension(&t).unwrap();
+        let table = eq_table(&t);
+        for x in 0..(1 << 7) {
+            let mut expected = F::one();
+            for mle in eq_ext.iter() {
+                expected *= mle.eval_binary(x).unwrap();
+            }
+            assert_eq!(table[x], expected);
+        }
+        let point: Vec<F> = (0..7).map(|_| F::rand(&mut rng)).collect();
+        let table = MLExtensionArray::from_vec(table).unwrap();
+        assert_eq!(table.eval_at(&point).unwrap(), eq_eval(&t, &point).unwrap());
+    }
 }
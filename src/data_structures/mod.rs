@@ -6,6 +6,9 @@ pub mod eq;
 /// proof generated by prover
 pub mod proof;
 
+/// Fiat-Shamir transcript
+pub mod transcript;
+
 #[cfg(test)]
 /// a constraint synthesizer
 pub mod constraints;
@@ -1,7 +1,11 @@
 use crate::ahp::prover::{
     ProverFifthMessage, ProverFirstMessage, ProverFourthMessage, ProverSecondMessage,
-    ProverSixthMessage, ProverThirdMessage,
+    ProverThirdMessage,
 };
+use crate::ahp::commit_and_prove::SegmentOpening;
+use crate::commitment::commit::Commitment;
+use crate::spark::prover::SparkProof;
+use crate::sumcheck::SumcheckProof;
 use ark_ec::PairingEngine;
 use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
 use linear_sumcheck::ml_sumcheck::ahp::prover::ProverMsg as MLProverMsg;
@@ -10,11 +14,58 @@ use linear_sumcheck::ml_sumcheck::ahp::prover::ProverMsg as MLProverMsg;
 #[derive(CanonicalSerialize, CanonicalDeserialize)]
 pub struct Proof<E: PairingEngine> {
     pub prover_first_message: ProverFirstMessage<E>,
-    pub prover_second_message: ProverSecondMessage<E>,
-    pub prover_third_message: ProverThirdMessage,
+    pub prover_second_message: ProverSecondMessage,
     pub first_sumcheck_messages: Vec<MLProverMsg<E::Fr>>,
-    pub prover_fourth_message: ProverFourthMessage<E>,
-    pub prover_fifth_message: ProverFifthMessage,
+    pub prover_third_message: ProverThirdMessage<E>,
+    pub prover_fourth_message: ProverFourthMessage,
     pub second_sumcheck_messages: Vec<MLProverMsg<E::Fr>>,
-    pub prover_sixth_message: ProverSixthMessage<E>,
+    pub prover_fifth_message: ProverFifthMessage<E>,
+}
+
+/// Proof that k instances of the same circuit are satisfied. The first sumcheck runs over the constraints and
+/// the instances, so the proof grows with log(k).
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct BatchProof<E: PairingEngine> {
+    /// commitment to the witnesses of all instances
+    pub prover_first_message: ProverFirstMessage<E>,
+    pub first_sumcheck: SumcheckProof<E::Fr>,
+    pub prover_third_message: ProverThirdMessage<E>,
+    pub second_sumcheck: SumcheckProof<E::Fr>,
+    pub prover_fifth_message: ProverFifthMessage<E>,
+}
+
+/// Proof that instances of different circuits are satisfied. Both sumchecks are shared by all instances, while
+/// each instance has its own commitment and opening.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct MultiCircuitProof<E: PairingEngine> {
+    /// commitment to the witness of each instance
+    pub commitments: Vec<ProverFirstMessage<E>>,
+    pub first_sumcheck: SumcheckProof<E::Fr>,
+    /// Az(r_x), Bz(r_x), Cz(r_x) of each instance
+    pub evaluations: Vec<ProverThirdMessage<E>>,
+    pub second_sumcheck: SumcheckProof<E::Fr>,
+    /// w(r_y) of each instance with its opening proof
+    pub openings: Vec<ProverFifthMessage<E>>,
+}
+
+/// proof for a preprocessed index: the proof of the circuit, and the proof of
+/// A(r_x, r_y), B(r_x, r_y), C(r_x, r_y) against the committed matrices
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct PreprocessedProof<E: PairingEngine> {
+    pub proof: Proof<E>,
+    pub matrix_evaluation_proof: SparkProof<E>,
+}
+
+/// proof of the circuit, and the openings showing that segments of w match the given commitments
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct LinkedProof<E: PairingEngine> {
+    pub proof: Proof<E>,
+    pub segment_openings: Vec<SegmentOpening<E>>,
+}
+
+/// commitment to each phase of w, and the proof linked to these commitments
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct MultiPhaseProof<E: PairingEngine> {
+    pub phase_commitments: Vec<Commitment<E>>,
+    pub proof: LinkedProof<E>,
 }
@@ -1,135 +1,304 @@
 //! reader interpreting r1cs matrix as dense MLExtension
 
 use ark_ff::Field;
+use crate::data_structures::eq::eq_table;
 use ark_relations::r1cs::Matrix;
 use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
-use linear_sumcheck::data_structures::ml_extension::{MLExtension, SparseMLExtension};
-use linear_sumcheck::data_structures::{MLExtensionArray, SparseMLExtensionMap};
-#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+use linear_sumcheck::data_structures::MLExtensionArray;
+
+/// R1CS matrix in compressed sparse row (CSR) form. Row `x` holds the entries
+/// `row_ptr[x]..row_ptr[x + 1]` of `col_idx` and `values`, with indices stored as `u32` to halve the memory
+/// traffic of the matrix-vector products.
+///
+/// Deserialization checks the CSR invariants (see `check`), so that a matrix from an untrusted key cannot
+/// index out of bounds.
+#[derive(Clone, CanonicalSerialize)]
 pub struct MatrixExtension<F: Field> {
-    constraint: Matrix<F>,
-    /// number of constraints
+    row_ptr: Vec<u32>,
+    col_idx: Vec<u32>,
+    values: Vec<F>,
+    /// number of constraints (rows), after padding to a power of two
     pub num_constraints: usize,
-}
-
-/// given a 2D location (x,y) in the matrix,
-/// return the 1d location in the multilinear extension array
-/// Thus Matrix[x,y] is mapped to extended polynomial P(xy_combine(x,y))
-/// * x: location x
-/// * y: location y
-/// * s: number of bits used to represent x and y (should be log(num_constraints))
-#[inline]
-fn xy_combine(x: usize, y: usize, s: usize) -> usize {
-    (y << s) + x
-}
-
-#[inline]
-#[allow(dead_code)]
-fn xy_decompose(xy: usize, s: usize) -> (usize, usize) {
-    let x = xy & ((1 << s) - 1);
-    let y = xy >> s;
-    (x, y)
+    /// number of variables (columns), after padding to a power of two
+    pub num_variables: usize,
 }
 
 impl<F: Field> MatrixExtension<F> {
-    /// setup the MLExtension. The provided matrix should be square.
-    pub fn new(matrix: Matrix<F>, num_constraints: usize) -> Result<Self, crate::Error> {
+    /// setup the MLExtension. The provided matrix should have at most `num_constraints` rows, and
+    /// its column indices should be smaller than `num_variables`. The matrix is padded with zero rows
+    /// and columns so that both dimensions are powers of two.
+    pub fn new(matrix: Matrix<F>, num_constraints: usize, num_variables: usize) -> Result<Self, crate::Error> {
         // sanity check
-        if !num_constraints.is_power_of_two() {
-            // for now, we assume number constraints are power of two.
-            // we can release the constraint by adding padding later.
+        if num_constraints == 0 || num_variables == 0 {
             return Err(crate::Error::InvalidArgument(Some(
-                "num of constraints should be power of two".into(),
+                "num of constraints and num of variables should be positive".into(),
             )));
         }
 
-        // the length of matrix should be num_constraints
-        if matrix.len() != num_constraints {
+        // the length of matrix should be at most num_constraints
+        if matrix.len() > num_constraints {
             return Err(crate::Error::InvalidArgument(Some(
                 "matrix size is inconsistent with number of constraints".into(),
             )));
         }
 
-        let idx_bound = num_constraints;
-        // each term should within num_constraints
+        let num_constraints = num_constraints.next_power_of_two();
+        let num_variables = num_variables.next_power_of_two();
+        // indices and offsets are stored as u32
+        let nnz: usize = matrix.iter().map(|row| row.len()).sum();
+        if num_variables - 1 > u32::MAX as usize || nnz > u32::MAX as usize {
+            return Err(crate::Error::InvalidArgument(Some(
+                "matrix is too large for u32 indices".into(),
+            )));
+        }
+
+        let idx_bound = num_variables;
+        let mut row_ptr = Vec::with_capacity(num_constraints + 1);
+        let mut col_idx = Vec::with_capacity(nnz);
+        let mut values = Vec::with_capacity(nnz);
+        row_ptr.push(0);
         for line in matrix.iter() {
-            for &(_, idx) in line {
+            // each term should within num_variables
+            for &(value, idx) in line {
                 if idx >= idx_bound {
                     return Err(crate::Error::InvalidArgument(Some(
                         "sparse index out of bound".into(),
                     )));
                 }
+                col_idx.push(idx as u32);
+                values.push(value);
             }
+            row_ptr.push(values.len() as u32);
         }
+        // padding rows are empty
+        row_ptr.resize(num_constraints + 1, values.len() as u32);
 
         let s = Self {
-            constraint: matrix,
+            row_ptr,
+            col_idx,
+            values,
             num_constraints,
+            num_variables,
         };
         Ok(s)
     }
 
-    /// Convert the matrix A(x,y) to sum over y A(x,y)Z(y), given z
+    /// Check the CSR invariants: both dimensions are powers of two, `row_ptr` has one more entry than there are
+    /// rows, starts at zero, is monotone and ends at the number of entries, `col_idx` and `values` have the same
+    /// length, and every column index is smaller than `num_variables`.
+    pub fn check(&self) -> Result<(), crate::Error> {
+        if !self.num_constraints.is_power_of_two() || !self.num_variables.is_power_of_two() {
+            return Err(crate::Error::InvalidArgument(Some(
+                "matrix dimensions should be powers of two".into(),
+            )));
+        }
+        if self.col_idx.len() != self.values.len() {
+            return Err(crate::Error::InvalidArgument(Some(
+                "column indices and values have different lengths".into(),
+            )));
+        }
+        if self.row_ptr.len() != self.num_constraints + 1
+            || self.row_ptr[0] != 0
+            || self.row_ptr.windows(2).any(|w| w[0] > w[1])
+            || self.row_ptr[self.num_constraints] as usize != self.values.len()
+        {
+            return Err(crate::Error::InvalidArgument(Some("invalid row offsets".into())));
+        }
+        if self.col_idx.iter().any(|y| *y as usize >= self.num_variables) {
+            return Err(crate::Error::InvalidArgument(Some(
+                "sparse index out of bound".into(),
+            )));
+        }
+        Ok(())
+    }
+
+    /// iterate over entries of row `x` as (column, value)
+    pub fn row(&self, x: usize) -> impl Iterator<Item = (usize, F)> + '_ {
+        let range = self.row_ptr[x] as usize..self.row_ptr[x + 1] as usize;
+        self.col_idx[range.clone()]
+            .iter()
+            .zip(self.values[range].iter())
+            .map(|(y, value)| (*y as usize, *value))
+    }
+
+    /// iterate over entries of the matrix as (row, column, value)
+    pub fn non_zero_entries(&self) -> impl Iterator<Item = (usize, usize, F)> + '_ {
+        (0..self.num_constraints).flat_map(move |x| self.row(x).map(move |(y, value)| (x, y, value)))
+    }
+
+    /// number of entries stored in the matrix
+    pub fn num_non_zero(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Compute the matrix-vector product `A * v`, where `v` has `num_variables` entries.
+    pub fn mul_vector(&self, v: &[F]) -> Result<Vec<F>, crate::Error> {
+        if v.len() != self.num_variables {
+            return Err(crate::Error::InvalidArgument(Some("vector has wrong length".into())));
+        }
+        Ok((0..self.num_constraints)
+            .map(|x| self.row(x).map(|(y, value)| value * &v[y]).sum())
+            .collect())
+    }
+
+    /// Compute the vector-matrix product `u^T * A`, where `u` has `num_constraints` entries.
+    pub fn left_mul_vector(&self, u: &[F]) -> Result<Vec<F>, crate::Error> {
+        if u.len() != self.num_constraints {
+            return Err(crate::Error::InvalidArgument(Some("vector has wrong length".into())));
+        }
+        let mut result = vec![F::zero(); self.num_variables];
+        for (x, ux) in u.iter().enumerate() {
+            if ux.is_zero() {
+                continue;
+            }
+            for (y, value) in self.row(x) {
+                result[y] += value * ux;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Convert the matrix A(x,y) to sum over y A(x,y)Z(y), given the table of z
     ///
-    /// return: multilinear extension sum over y A(x,y)Z(y) with `num_constraints` variables
-    pub fn sum_over_y(&self, z: &MLExtensionArray<F>) -> Result<MLExtensionArray<F>, crate::Error> {
-        if z.num_variables()? != ark_std::log2(self.num_constraints) as usize {
+    /// return: multilinear extension sum over y A(x,y)Z(y) with `log(num_constraints)` variables
+    pub fn sum_over_y(&self, z: &[F]) -> Result<MLExtensionArray<F>, crate::Error> {
+        if z.len() != self.num_variables {
             return Err(crate::Error::InvalidArgument(Some("invalid z".into())));
         }
-        let temp: Vec<F> = self
-            .constraint
-            .iter()
-            .map(|v| v.iter().map(|(a, y)| *a * z.eval_binary(*y).unwrap()).sum())
-            .collect();
-        Ok(MLExtensionArray::from_slice(&temp)?)
+        Ok(MLExtensionArray::from_vec(self.mul_vector(z)?)?)
     }
 
-    //noinspection RsBorrowChecker
     /// Given A(x,y) and randomness r_x
     ///
-    /// return: multilinear extension A(r_x,y) with `num_constraints` variables
+    /// return: multilinear extension A(r_x,y) with `log(num_variables)` variables
     pub fn eval_on_x(&self, r_x: &[F]) -> Result<MLExtensionArray<F>, crate::Error> {
         if (1 << r_x.len()) != self.num_constraints {
             return Err(crate::Error::InvalidArgument(Some(
                 "2^(r_x) should have size: num_constraints".into(),
             )));
         }
+        self.eval_on_x_with_eq(&eq_table(r_x))
+    }
+
+    /// Same as `eval_on_x`, given the table of eq(r_x, x) over all rows x, so that matrices of the same
+    /// size can share it. Takes one pass over the non-zero entries: A(r_x,y) = sum over x eq(r_x,x)A(x,y).
+    pub fn eval_on_x_with_eq(&self, eq_rx: &[F]) -> Result<MLExtensionArray<F>, crate::Error> {
+        Ok(MLExtensionArray::from_vec(self.left_mul_vector(eq_rx)?)?)
+    }
 
-        // create a sparse map
-        let s = ark_std::log2(self.num_constraints) as usize;
-        let mut map = Vec::new();
-        for (x, arr) in self.constraint.iter().enumerate() {
-            for (value, y) in arr.iter() {
-                map.push((xy_combine(x, *y, s), *value));
+    /// Given pairs (c_i, M_i) of coefficients and matrices of the same size, and the table of eq(r_x, x),
+    /// return the multilinear extension of sum over i c_i M_i(r_x,y), scanning each matrix once.
+    pub fn eval_combination_on_x(matrices: &[(F, &Self)], eq_rx: &[F]) -> Result<MLExtensionArray<F>, crate::Error> {
+        let num_variables = match matrices.first() {
+            Some((_, m)) => m.num_variables,
+            None => return Err(crate::Error::InvalidArgument(Some("no matrix to combine".into()))),
+        };
+        let mut result = vec![F::zero(); num_variables];
+        for (coefficient, matrix) in matrices.iter() {
+            if matrix.num_variables != num_variables || eq_rx.len() != matrix.num_constraints {
+                return Err(crate::Error::InvalidArgument(Some("matrices have different sizes".into())));
+            }
+            for (x, eq) in eq_rx.iter().enumerate() {
+                let scale = *coefficient * eq;
+                for (y, value) in matrix.row(x) {
+                    result[y] += value * &scale;
+                }
             }
         }
+        Ok(MLExtensionArray::from_vec(result)?)
+    }
 
-        let sparse_mle = SparseMLExtensionMap::from_slice(&map, s * 2)?;
-        let partially_evaluated_sparse = sparse_mle.eval_partial_at(r_x)?;
+    /// Evaluate the multilinear extension A(r_x,r_y) directly from the non-zero entries, without
+    /// materializing A(r_x,y).
+    pub fn eval_at(&self, r_x: &[F], r_y: &[F]) -> Result<F, crate::Error> {
+        if (1 << r_x.len()) != self.num_constraints || (1 << r_y.len()) != self.num_variables {
+            return Err(crate::Error::InvalidArgument(Some(
+                "2^(r_x) and 2^(r_y) should have size: num_constraints and num_variables".into(),
+            )));
+        }
+        self.eval_with_eq(&eq_table(r_x), &eq_table(r_y))
+    }
 
-        // convert this to array
-        let mut ans = Vec::with_capacity(1 << s);
-        ans.resize(1 << s, F::zero());
-        for (y, val) in partially_evaluated_sparse.sparse_table()? {
-            ans[y] = val;
+    /// Same as `eval_at`, given the tables of eq(r_x, x) and eq(r_y, y), so that matrices of the same size
+    /// can share them: A(r_x,r_y) = sum over non-zero entries A(x,y)eq(r_x,x)eq(r_y,y).
+    pub fn eval_with_eq(&self, eq_rx: &[F], eq_ry: &[F]) -> Result<F, crate::Error> {
+        if eq_rx.len() != self.num_constraints || eq_ry.len() != self.num_variables {
+            return Err(crate::Error::InvalidArgument(Some("eq table has wrong length".into())));
         }
-        Ok(MLExtensionArray::from_vec(ans)?)
+        Ok(eq_rx
+            .iter()
+            .enumerate()
+            .map(|(x, eq)| self.row(x).map(|(y, value)| value * &eq_ry[y]).sum::<F>() * eq)
+            .sum())
+    }
+
+    /// Given A(x,y) and randomness r_y
+    ///
+    /// return: multilinear extension A(x,r_y) with `log(num_constraints)` variables
+    pub fn eval_on_y(&self, r_y: &[F]) -> Result<MLExtensionArray<F>, crate::Error> {
+        if (1 << r_y.len()) != self.num_variables {
+            return Err(crate::Error::InvalidArgument(Some(
+                "2^(r_y) should have size: num_variables".into(),
+            )));
+        }
+        Ok(MLExtensionArray::from_vec(self.mul_vector(&eq_table(r_y))?)?)
+    }
+}
+
+impl<F: Field> CanonicalDeserialize for MatrixExtension<F> {
+    fn deserialize<R: Read>(mut reader: R) -> Result<Self, SerializationError> {
+        let matrix = Self {
+            row_ptr: CanonicalDeserialize::deserialize(&mut reader)?,
+            col_idx: CanonicalDeserialize::deserialize(&mut reader)?,
+            values: CanonicalDeserialize::deserialize(&mut reader)?,
+            num_constraints: CanonicalDeserialize::deserialize(&mut reader)?,
+            num_variables: CanonicalDeserialize::deserialize(&mut reader)?,
+        };
+        matrix.check().map_err(|_| SerializationError::InvalidData)?;
+        Ok(matrix)
+    }
+
+    fn deserialize_uncompressed<R: Read>(mut reader: R) -> Result<Self, SerializationError> {
+        let matrix = Self {
+            row_ptr: CanonicalDeserialize::deserialize_uncompressed(&mut reader)?,
+            col_idx: CanonicalDeserialize::deserialize_uncompressed(&mut reader)?,
+            values: CanonicalDeserialize::deserialize_uncompressed(&mut reader)?,
+            num_constraints: CanonicalDeserialize::deserialize_uncompressed(&mut reader)?,
+            num_variables: CanonicalDeserialize::deserialize_uncompressed(&mut reader)?,
+        };
+        matrix.check().map_err(|_| SerializationError::InvalidData)?;
+        Ok(matrix)
+    }
+
+    fn deserialize_unchecked<R: Read>(mut reader: R) -> Result<Self, SerializationError> {
+        // the CSR invariants are still checked: they guard indexing, not the validity of field elements
+        let matrix = Self {
+            row_ptr: CanonicalDeserialize::deserialize_unchecked(&mut reader)?,
+            col_idx: CanonicalDeserialize::deserialize_unchecked(&mut reader)?,
+            values: CanonicalDeserialize::deserialize_unchecked(&mut reader)?,
+            num_constraints: CanonicalDeserialize::deserialize_unchecked(&mut reader)?,
+            num_variables: CanonicalDeserialize::deserialize_unchecked(&mut reader)?,
+        };
+        matrix.check().map_err(|_| SerializationError::InvalidData)?;
+        Ok(matrix)
     }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::data_structures::eq::eq_table;
     use crate::data_structures::r1cs_reader::MatrixExtension;
     use crate::test_utils::{random_matrix, TestCurveFr};
-    use ark_ff::{test_rng, One, Zero};
+    use ark_ff::{test_rng, One, UniformRand, Zero};
     use linear_sumcheck::data_structures::ml_extension::MLExtension;
+    use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 
     #[test]
     fn test_eval_on_x_sanity() {
         let mut rng = test_rng();
         let matrix = random_matrix(6, 1 << 9, &mut rng);
         let expected_evaluations = &matrix[0b110010];
-        let mat_ext = MatrixExtension::new(matrix.clone(), 1 << 6).unwrap();
+        let mat_ext = MatrixExtension::new(matrix.clone(), 1 << 6, 1 << 6).unwrap();
         let eval_point = vec![
             TestCurveFr::zero(),
             TestCurveFr::one(),
@@ -143,4 +312,163 @@ mod test {
             assert_eq!(actual_evaluations.eval_binary(*idx).unwrap(), *val);
         }
     }
+
+    #[test]
+    fn test_padding() {
+        let mut rng = test_rng();
+        let mut matrix = random_matrix(6, 1 << 9, &mut rng);
+        // drop rows and columns so that the matrix is 50 x 50
+        matrix.truncate(50);
+        for row in matrix.iter_mut() {
+            row.retain(|(_, y)| *y < 50);
+        }
+        let mat_ext = MatrixExtension::new(matrix.clone(), 50, 50).unwrap();
+        assert_eq!(mat_ext.num_constraints, 64);
+        let padded = MatrixExtension::new(matrix.clone(), 64, 64).unwrap();
+        let r_x: Vec<_> = (0..6).map(|_| TestCurveFr::rand(&mut rng)).collect();
+        let r_y: Vec<_> = (0..6).map(|_| TestCurveFr::rand(&mut rng)).collect();
+        assert_eq!(
+            mat_ext.eval_on_x(&r_x).unwrap().eval_at(&r_y).unwrap(),
+            padded.eval_on_x(&r_x).unwrap().eval_at(&r_y).unwrap()
+        );
+
+        // more rows than the claimed size
+        assert!(MatrixExtension::new(matrix.clone(), 40, 50).is_err());
+        // column index out of bound
+        assert!(MatrixExtension::new(matrix, 50, 40).is_err());
+    }
+
+    #[test]
+    fn test_rectangular() {
+        let mut rng = test_rng();
+        // 16 x 64 matrix
+        let mut matrix = random_matrix(6, 1 << 9, &mut rng);
+        matrix.truncate(16);
+        let mat_ext = MatrixExtension::new(matrix.clone(), 16, 64).unwrap();
+        let r_x: Vec<_> = (0..4).map(|_| TestCurveFr::rand(&mut rng)).collect();
+        let r_y: Vec<_> = (0..6).map(|_| TestCurveFr::rand(&mut rng)).collect();
+        let z: Vec<_> = (0..64).map(|_| TestCurveFr::rand(&mut rng)).collect();
+
+        let a_rx = mat_ext.eval_on_x(&r_x).unwrap();
+        assert_eq!(a_rx.num_variables().unwrap(), 6);
+        let az = mat_ext.sum_over_y(&z).unwrap();
+        assert_eq!(az.num_variables().unwrap(), 4);
+
+        // compare with the square matrix padded with zero rows
+        let square = MatrixExtension::new(matrix, 64, 64).unwrap();
+        let mut r_x_0 = r_x.clone();
+        r_x_0.extend((0..2).map(|_| TestCurveFr::zero()));
+        assert_eq!(
+            a_rx.eval_at(&r_y).unwrap(),
+            square.eval_on_x(&r_x_0).unwrap().eval_at(&r_y).unwrap()
+        );
+        assert_eq!(
+            az.eval_at(&r_x).unwrap(),
+            square.sum_over_y(&z).unwrap().eval_at(&r_x_0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sparse_products() {
+        let mut rng = test_rng();
+        let mut matrix = random_matrix(6, 1 << 9, &mut rng);
+        matrix.truncate(16);
+        let mat_ext = MatrixExtension::new(matrix.clone(), 16, 64).unwrap();
+        assert_eq!(mat_ext.num_non_zero(), matrix.iter().map(|row| row.len()).sum::<usize>());
+        for (x, row) in matrix.iter().enumerate() {
+            assert_eq!(mat_ext.row(x).collect::<Vec<_>>(), row.iter().map(|(v, y)| (*y, *v)).collect::<Vec<_>>());
+        }
+
+        // A * v and u^T * A against the dense matrix
+        let v: Vec<_> = (0..64).map(|_| TestCurveFr::rand(&mut rng)).collect();
+        let u: Vec<_> = (0..16).map(|_| TestCurveFr::rand(&mut rng)).collect();
+        let mut av = vec![TestCurveFr::zero(); 16];
+        let mut ua = vec![TestCurveFr::zero(); 64];
+        for (x, row) in matrix.iter().enumerate() {
+            for (value, y) in row {
+                av[x] += *value * v[*y];
+                ua[*y] += *value * u[x];
+            }
+        }
+        assert_eq!(mat_ext.mul_vector(&v).unwrap(), av);
+        assert_eq!(mat_ext.left_mul_vector(&u).unwrap(), ua);
+        assert!(mat_ext.mul_vector(&u).is_err());
+
+        // partial evaluations on rows and columns agree
+        let r_x: Vec<_> = (0..4).map(|_| TestCurveFr::rand(&mut rng)).collect();
+        let r_y: Vec<_> = (0..6).map(|_| TestCurveFr::rand(&mut rng)).collect();
+        assert_eq!(
+            mat_ext.eval_on_x(&r_x).unwrap().eval_at(&r_y).unwrap(),
+            mat_ext.eval_on_y(&r_y).unwrap().eval_at(&r_x).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_deserialize_checks() {
+        let mut rng = test_rng();
+        let mut matrix = random_matrix(6, 1 << 9, &mut rng);
+        matrix.truncate(16);
+        let mat_ext = MatrixExtension::new(matrix, 16, 64).unwrap();
+        let round_trip = |m: &MatrixExtension<TestCurveFr>| {
+            let mut data = Vec::new();
+            m.serialize(&mut data).unwrap();
+            MatrixExtension::<TestCurveFr>::deserialize(&data[..])
+        };
+        assert!(mat_ext.check().is_ok());
+        assert!(round_trip(&mat_ext).is_ok());
+
+        // row offsets are not monotone
+        let mut bad = mat_ext.clone();
+        bad.row_ptr[1] = bad.row_ptr[2] + 1;
+        assert!(bad.check().is_err());
+        assert!(round_trip(&bad).is_err());
+        // column index out of range
+        let mut bad = mat_ext.clone();
+        bad.col_idx[0] = 64;
+        assert!(bad.check().is_err());
+        assert!(round_trip(&bad).is_err());
+        // values and column indices have different lengths
+        let mut bad = mat_ext.clone();
+        bad.values.pop();
+        assert!(round_trip(&bad).is_err());
+        // number of rows does not match the row offsets
+        let mut bad = mat_ext;
+        bad.num_constraints = 32;
+        assert!(round_trip(&bad).is_err());
+    }
+
+    #[test]
+    fn test_eval_on_x_shared_eq() {
+        let mut rng = test_rng();
+        let matrix = random_matrix(5, 1 << 7, &mut rng);
+        let mat_ext = MatrixExtension::new(matrix.clone(), 32, 32).unwrap();
+        let r_x: Vec<_> = (0..5).map(|_| TestCurveFr::rand(&mut rng)).collect();
+        let r_y: Vec<_> = (0..5).map(|_| TestCurveFr::rand(&mut rng)).collect();
+        let eq_rx = eq_table(&r_x);
+        let eq_ry = eq_table(&r_y);
+        let mut expected = TestCurveFr::zero();
+        for (x, row) in matrix.iter().enumerate() {
+            for (value, y) in row {
+                expected += *value * eq_rx[x] * eq_ry[*y];
+            }
+        }
+        assert_eq!(mat_ext.eval_on_x(&r_x).unwrap().eval_at(&r_y).unwrap(), expected);
+        assert_eq!(mat_ext.eval_on_x_with_eq(&eq_rx).unwrap().eval_at(&r_y).unwrap(), expected);
+        assert!(mat_ext.eval_on_x_with_eq(&eq_rx[..16]).is_err());
+        assert_eq!(mat_ext.eval_at(&r_x, &r_y).unwrap(), expected);
+        assert_eq!(mat_ext.eval_with_eq(&eq_rx, &eq_ry).unwrap(), expected);
+        assert!(mat_ext.eval_at(&r_x, &r_y[..4]).is_err());
+
+        // r_a * A + r_b * B, with B = 2A
+        let double: Vec<Vec<_>> = matrix
+            .iter()
+            .map(|row| row.iter().map(|(v, y)| (*v + v, *y)).collect())
+            .collect();
+        let double = MatrixExtension::new(double, 32, 32).unwrap();
+        let (r_a, r_b) = (TestCurveFr::rand(&mut rng), TestCurveFr::rand(&mut rng));
+        let combined = MatrixExtension::eval_combination_on_x(&[(r_a, &mat_ext), (r_b, &double)], &eq_rx).unwrap();
+        assert_eq!(combined.eval_at(&r_y).unwrap(), (r_a + r_b + r_b) * expected);
+        let wide = MatrixExtension::new(matrix, 32, 64).unwrap();
+        assert!(MatrixExtension::eval_combination_on_x(&[(r_a, &mat_ext), (r_b, &wide)], &eq_rx).is_err());
+    }
 }
@@ -0,0 +1,107 @@
+use ark_std::marker::PhantomData;
+use digest::Digest;
+use rand::{Error as RandError, RngCore};
+
+use crate::data_structures::transcript::TranscriptHash;
+
+/// Transcript hash built from a `Digest`.
+///
+/// Absorbing replaces the seed by `H(seed || bytes)`. Bytes are read from `H(seed || counter)` for
+/// `counter = 0, 1, ...`, and the counter is reset by the next absorb.
+pub struct DigestHash<D: Digest> {
+    seed: Vec<u8>,
+    counter: u64,
+    buffer: Vec<u8>,
+    _digest: PhantomData<D>,
+}
+
+/// SHA-256 transcript hash
+pub type Sha256Hash = DigestHash<sha2::Sha256>;
+
+/// Keccak-256 transcript hash, which is cheap to verify on the EVM
+pub type Keccak256Hash = DigestHash<sha3::Keccak256>;
+
+impl<D: Digest> TranscriptHash for DigestHash<D> {
+    fn new() -> Self {
+        Self {
+            seed: D::digest(&[]).to_vec(),
+            counter: 0,
+            buffer: Vec::new(),
+            _digest: PhantomData,
+        }
+    }
+
+    fn absorb(&mut self, bytes: &[u8]) {
+        let mut hasher = D::new();
+        hasher.update(&self.seed);
+        hasher.update(bytes);
+        self.seed = hasher.finalize().to_vec();
+        self.counter = 0;
+        self.buffer.clear();
+    }
+}
+
+impl<D: Digest> RngCore for DigestHash<D> {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for byte in dest.iter_mut() {
+            if self.buffer.is_empty() {
+                let mut hasher = D::new();
+                hasher.update(&self.seed);
+                hasher.update(&self.counter.to_le_bytes());
+                // bytes are popped from the back, so reverse to read the block in order
+                self.buffer = hasher.finalize().iter().rev().cloned().collect();
+                self.counter += 1;
+            }
+            *byte = self.buffer.pop().unwrap();
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), RandError> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data_structures::transcript::{Keccak256Hash, Sha256Hash, TranscriptHash};
+    use digest::Digest;
+    use rand::RngCore;
+
+    #[test]
+    fn test_output_blocks() {
+        // the first 32 bytes after absorbing "abc" are H(H(H("") || "abc") || 0u64)
+        let mut hash = Sha256Hash::new();
+        hash.absorb(b"abc");
+        let mut output = [0u8; 40];
+        hash.fill_bytes(&mut output);
+
+        let seed = sha2::Sha256::new()
+            .chain(&sha2::Sha256::digest(&[]))
+            .chain(b"abc")
+            .finalize();
+        let first_block = sha2::Sha256::new().chain(&seed).chain(&0u64.to_le_bytes()).finalize();
+        let second_block = sha2::Sha256::new().chain(&seed).chain(&1u64.to_le_bytes()).finalize();
+        assert_eq!(&output[..32], &first_block[..]);
+        assert_eq!(&output[32..], &second_block[..8]);
+
+        // absorbing restarts the output
+        let mut keccak = Keccak256Hash::new();
+        keccak.absorb(b"abc");
+        let first = keccak.next_u64();
+        keccak.absorb(b"abc");
+        assert_ne!(first, keccak.next_u64());
+    }
+}
@@ -0,0 +1,200 @@
+//! Fiat-Shamir transcript with domain separation
+
+use ark_ff::Field;
+use ark_serialize::CanonicalSerialize;
+use linear_sumcheck::data_structures::random::FeedableRNG;
+use linear_sumcheck::data_structures::Blake2s512Rng;
+use rand::RngCore;
+
+use crate::error::SResult;
+
+/// transcript hash based on `digest` hash functions, e.g. SHA-256 and Keccak-256
+pub mod digest_hash;
+
+/// algebraic transcript hash based on the Poseidon sponge
+pub mod poseidon;
+
+pub use self::digest_hash::{DigestHash, Keccak256Hash, Sha256Hash};
+pub use self::poseidon::PoseidonHash;
+
+/// Hash function behind a transcript. Challenges are read from the hash as an rng, so that sub-protocols
+/// which sample their own randomness (e.g. `AHPForMLSumcheck`) can use it directly.
+pub trait TranscriptHash: RngCore {
+    /// initial state
+    fn new() -> Self;
+    /// absorb `bytes`. Bytes read from the rng afterwards depend on everything absorbed so far.
+    fn absorb(&mut self, bytes: &[u8]);
+
+    /// Absorb field elements. By default they are serialized and absorbed as bytes; an algebraic hash over
+    /// the same field absorbs them natively.
+    fn absorb_field<F: Field>(&mut self, elements: &[F]) -> SResult<()> {
+        let mut bytes = Vec::new();
+        elements.to_vec().serialize(&mut bytes)?;
+        self.absorb(&bytes);
+        Ok(())
+    }
+
+    /// Squeeze a field element. By default it is sampled from the rng; an algebraic hash over the same field
+    /// squeezes it natively.
+    fn challenge_field<F: Field>(&mut self) -> F {
+        F::rand(self)
+    }
+}
+
+impl TranscriptHash for Blake2s512Rng {
+    fn new() -> Self {
+        Blake2s512Rng::setup()
+    }
+
+    fn absorb(&mut self, bytes: &[u8]) {
+        self.feed_randomness(&bytes.to_vec())
+            .expect("serializing a byte vector never fails");
+    }
+}
+
+/// Transcript of a non-interactive protocol. Every absorbed message and every squeezed challenge is
+/// preceded by its label, so that the same bytes under different labels lead to different challenges.
+pub struct Transcript<H: TranscriptHash = Blake2s512Rng> {
+    hash: H,
+}
+
+impl<H: TranscriptHash> Transcript<H> {
+    /// start a transcript for the protocol named `protocol_label`
+    pub fn new(protocol_label: &'static [u8]) -> Self {
+        let mut hash = H::new();
+        Self::absorb_label(&mut hash, protocol_label);
+        Self { hash }
+    }
+
+    /// absorb `msg` under `label`
+    pub fn append_message<T: CanonicalSerialize>(
+        &mut self,
+        label: &'static [u8],
+        msg: &T,
+    ) -> SResult<()> {
+        let mut bytes = Vec::new();
+        msg.serialize(&mut bytes)?;
+        Self::absorb_label(&mut self.hash, label);
+        self.hash.absorb(&bytes);
+        Ok(())
+    }
+
+    /// absorb field elements under `label`, natively if the hash is algebraic over the same field
+    pub fn append_scalars<F: Field>(&mut self, label: &'static [u8], elements: &[F]) -> SResult<()> {
+        Self::absorb_label(&mut self.hash, label);
+        self.hash.absorb_field(elements)
+    }
+
+    /// squeeze a field element under `label`
+    pub fn challenge_scalar<F: Field>(&mut self, label: &'static [u8]) -> SResult<F> {
+        Ok(self.challenge_rng(label)?.challenge_field())
+    }
+
+    /// squeeze `n` field elements under `label`
+    pub fn challenge_scalars<F: Field>(&mut self, label: &'static [u8], n: usize) -> SResult<Vec<F>> {
+        let hash = self.challenge_rng(label)?;
+        Ok((0..n).map(|_| hash.challenge_field()).collect())
+    }
+
+    /// Absorb `label` and return the underlying hash, for sub-protocols which sample their own
+    /// challenges (e.g. `AHPForMLSumcheck::verify_round`). Only one challenge should be drawn from it
+    /// before the next message is absorbed.
+    pub fn challenge_rng(&mut self, label: &'static [u8]) -> SResult<&mut H> {
+        Self::absorb_label(&mut self.hash, label);
+        Ok(&mut self.hash)
+    }
+
+    /// labels are prefixed with their length, so that a label and the following message cannot be confused
+    fn absorb_label(hash: &mut H, label: &[u8]) {
+        hash.absorb(&(label.len() as u64).to_le_bytes());
+        hash.absorb(label);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data_structures::transcript::{
+        Keccak256Hash, PoseidonHash, Sha256Hash, Transcript, TranscriptHash,
+    };
+    use crate::test_utils::TestCurveFr;
+    use ark_serialize::CanonicalSerialize;
+    use linear_sumcheck::data_structures::Blake2s512Rng;
+    use rand::RngCore;
+
+    fn challenge<H: TranscriptHash>(protocol: &'static [u8], label: &'static [u8], msg: u64) -> TestCurveFr {
+        let mut transcript = Transcript::<H>::new(protocol);
+        transcript.append_message(label, &msg).unwrap();
+        transcript.challenge_scalar(b"challenge").unwrap()
+    }
+
+    fn test_domain_separation<H: TranscriptHash>() {
+        let expected = challenge::<H>(b"protocol", b"message", 1);
+        assert_eq!(expected, challenge::<H>(b"protocol", b"message", 1));
+        assert_ne!(expected, challenge::<H>(b"protocol", b"message", 2));
+        assert_ne!(expected, challenge::<H>(b"protocol", b"other message", 1));
+        assert_ne!(expected, challenge::<H>(b"other protocol", b"message", 1));
+
+        let mut transcript = Transcript::<H>::new(b"protocol");
+        let a: TestCurveFr = transcript.challenge_scalar(b"a").unwrap();
+        let b: TestCurveFr = transcript.challenge_scalar(b"b").unwrap();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_all_hashes() {
+        test_domain_separation::<Blake2s512Rng>();
+        test_domain_separation::<Sha256Hash>();
+        test_domain_separation::<Keccak256Hash>();
+        test_domain_separation::<PoseidonHash<TestCurveFr>>();
+    }
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// the fixed sequence of labeled appends behind the known answers
+    fn known_transcript<H: TranscriptHash>() -> Transcript<H> {
+        let mut transcript = Transcript::new(b"r1cs-spartan-raw/known-answers");
+        transcript.append_message(b"number", &42u64).unwrap();
+        transcript.append_message(b"bytes", &b"abc".to_vec()).unwrap();
+        transcript
+            .append_scalars(b"scalars", &[TestCurveFr::from(1u64), TestCurveFr::from(2u64)])
+            .unwrap();
+        transcript
+    }
+
+    /// first 32 bytes read from the hash after the label `challenge`
+    fn challenge_bytes<H: TranscriptHash>() -> String {
+        let mut transcript = known_transcript::<H>();
+        let mut bytes = [0u8; 32];
+        transcript.challenge_rng(b"challenge").unwrap().fill_bytes(&mut bytes);
+        to_hex(&bytes)
+    }
+
+    #[test]
+    fn test_known_answers() {
+        assert_eq!(
+            challenge_bytes::<Blake2s512Rng>(),
+            "ab34edb907dbdb2a3aedf501c0fc7a801ab0af4a431b9222fbfe56b853509402"
+        );
+        assert_eq!(
+            challenge_bytes::<Sha256Hash>(),
+            "47dbf1e571cd7e46f479fa537db662d1a3500020fe80927bcd71205289c49dc7"
+        );
+        // Keccak-256 rather than SHA3-256
+        assert_eq!(
+            challenge_bytes::<Keccak256Hash>(),
+            "ea5fe052b818be49d003780022d150118e6dba8c1ee6c0e204d4d78b165c0bdf"
+        );
+
+        // Poseidon squeezes the challenge as a field element, serialized as little-endian bytes
+        let mut transcript = known_transcript::<PoseidonHash<TestCurveFr>>();
+        let challenge: TestCurveFr = transcript.challenge_scalar(b"challenge").unwrap();
+        let mut bytes = Vec::new();
+        challenge.serialize(&mut bytes).unwrap();
+        assert_eq!(
+            to_hex(&bytes),
+            "3e1ae2b8c2466bcd11ea0a4aaed6d29fa18583c9eab550fe71d38186c2dace58"
+        );
+    }
+}
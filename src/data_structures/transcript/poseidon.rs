@@ -0,0 +1,362 @@
+use ark_ff::{Field, PrimeField};
+use ark_serialize::CanonicalSerialize;
+use ark_std::any::{Any, TypeId};
+use ark_std::collections::VecDeque;
+use rand::{Error as RandError, RngCore};
+
+use crate::data_structures::transcript::TranscriptHash;
+use crate::error::SResult;
+
+/// width of the permutation
+const WIDTH: usize = 3;
+/// number of elements absorbed or squeezed per permutation
+const RATE: usize = 2;
+const FULL_ROUNDS: usize = 8;
+const PARTIAL_ROUNDS: usize = 57;
+/// the s-box is x^5, which is a permutation only when gcd(5, p - 1) = 1. This is checked when the parameters
+/// are generated.
+const ALPHA: u64 = 5;
+
+/// Round constants and MDS matrix of the Poseidon permutation over `F`.
+///
+/// Round constants are generated by the Grain LFSR as in the reference script of the Poseidon paper
+/// (`generate_parameters_grain.sage`), initialised with a prime field, the x^alpha s-box, `n = size_in_bits`,
+/// `t = WIDTH`, `R_F = FULL_ROUNDS` and `R_P = PARTIAL_ROUNDS`: each constant is the first `n`-bit output,
+/// read most significant bit first, which is smaller than p. For the scalar field of BLS12-381 these are the
+/// round constants of the reference implementation with t = 3, starting with
+/// `0x6c4ffa723eaf1a7bf74905cc7dae4ca9ff4a2c3bc81d42e09540d1f250910880`.
+///
+/// The MDS matrix is the Cauchy matrix `1 / (x_i + y_j)` with `x_i = i` and `y_j = WIDTH + j`, which is MDS
+/// because the `x_i` are distinct, the `y_j` are distinct and no `x_i + y_j` is zero.
+pub struct PoseidonParameters<F: PrimeField> {
+    pub round_constants: Vec<F>,
+    pub mds: Vec<Vec<F>>,
+}
+
+impl<F: PrimeField> PoseidonParameters<F> {
+    /// generate the parameters
+    ///
+    /// panics if x^5 is not a permutation of `F`
+    pub fn generate() -> Self {
+        // 2^64 = 1 mod 5, so p mod 5 is the sum of its limbs mod 5
+        let p_mod_5 = F::characteristic().iter().fold(0u64, |acc, limb| (acc + limb % 5) % 5);
+        assert_ne!(p_mod_5, 1, "x^5 is not a permutation of a field with 5 | p - 1");
+
+        let n = F::size_in_bits();
+        let mut grain = Grain::new(n);
+        let round_constants = (0..(FULL_ROUNDS + PARTIAL_ROUNDS) * WIDTH)
+            .map(|_| grain.next_field_element())
+            .collect();
+        let mds = (0..WIDTH)
+            .map(|i| {
+                (0..WIDTH)
+                    .map(|j| F::from((i + WIDTH + j) as u64).inverse().unwrap())
+                    .collect()
+            })
+            .collect();
+        Self {
+            round_constants,
+            mds,
+        }
+    }
+}
+
+/// self-shrinking Grain LFSR with 80 bits of state
+struct Grain {
+    bits: VecDeque<bool>,
+    n: usize,
+}
+
+impl Grain {
+    fn new(n: usize) -> Self {
+        let mut bits = VecDeque::with_capacity(80);
+        let mut push = |value: usize, len: usize| {
+            for i in (0..len).rev() {
+                bits.push_back((value >> i) & 1 == 1);
+            }
+        };
+        // prime field
+        push(1, 2);
+        // x^alpha
+        push(0, 4);
+        push(n, 12);
+        push(WIDTH, 12);
+        push(FULL_ROUNDS, 10);
+        push(PARTIAL_ROUNDS, 10);
+        push((1 << 30) - 1, 30);
+        let mut grain = Self { bits, n };
+        for _ in 0..160 {
+            grain.step();
+        }
+        grain
+    }
+
+    fn step(&mut self) -> bool {
+        let b = &self.bits;
+        let bit = b[62] ^ b[51] ^ b[38] ^ b[23] ^ b[13] ^ b[0];
+        self.bits.pop_front();
+        self.bits.push_back(bit);
+        bit
+    }
+
+    /// output bits in pairs: the second bit is kept only when the first one is set
+    fn next_bit(&mut self) -> bool {
+        loop {
+            let keep = self.step();
+            let bit = self.step();
+            if keep {
+                return bit;
+            }
+        }
+    }
+
+    /// rejection sample an `n`-bit integer smaller than p
+    fn next_field_element<F: PrimeField>(&mut self) -> F {
+        let modulus = F::characteristic();
+        loop {
+            let mut limbs = vec![0u64; modulus.len()];
+            for i in (0..self.n).rev() {
+                if self.next_bit() {
+                    limbs[i / 64] |= 1 << (i % 64);
+                }
+            }
+            let smaller = limbs
+                .iter()
+                .rev()
+                .zip(modulus.iter().rev())
+                .find(|(a, p)| a != p)
+                .map_or(false, |(a, p)| a < p);
+            if smaller {
+                let bytes: Vec<u8> = limbs.iter().flat_map(|limb| limb.to_le_bytes().to_vec()).collect();
+                return F::from_le_bytes_mod_order(&bytes);
+            }
+        }
+    }
+}
+
+/// Transcript hash based on the Poseidon sponge over `F`, so that a verifier arithmetised over `F` can
+/// recompute the challenges cheaply. Field elements of `F` are absorbed and squeezed natively, and absorbed
+/// bytes are packed into field elements of `(size_in_bits - 1) / 8` bytes, preceded by the number of bytes.
+pub struct PoseidonHash<F: PrimeField> {
+    state: Vec<F>,
+    /// generated when the sponge is created
+    parameters: PoseidonParameters<F>,
+    /// position in the rate part of the state
+    position: usize,
+    squeezing: bool,
+    buffer: Vec<u8>,
+}
+
+impl<F: PrimeField> PoseidonHash<F> {
+    fn bytes_per_element() -> usize {
+        (F::size_in_bits() - 1) / 8
+    }
+
+    fn permute(&mut self) {
+        let half_full_rounds = FULL_ROUNDS / 2;
+        let parameters = &self.parameters;
+        for round in 0..(FULL_ROUNDS + PARTIAL_ROUNDS) {
+            for (s, c) in self
+                .state
+                .iter_mut()
+                .zip(parameters.round_constants[round * WIDTH..(round + 1) * WIDTH].iter())
+            {
+                *s += c;
+            }
+            if round < half_full_rounds || round >= half_full_rounds + PARTIAL_ROUNDS {
+                for s in self.state.iter_mut() {
+                    *s = s.pow(&[ALPHA]);
+                }
+            } else {
+                self.state[0] = self.state[0].pow(&[ALPHA]);
+            }
+            let state = &self.state;
+            self.state = parameters
+                .mds
+                .iter()
+                .map(|row| row.iter().zip(state.iter()).map(|(m, s)| *m * s).sum())
+                .collect();
+        }
+    }
+
+    /// absorb one field element into the rate part of the state
+    pub fn absorb_element(&mut self, element: F) {
+        if self.squeezing || self.position == RATE {
+            self.permute();
+            self.position = 0;
+            self.squeezing = false;
+            self.buffer.clear();
+        }
+        self.state[self.position] += element;
+        self.position += 1;
+    }
+
+    /// squeeze one field element from the rate part of the state
+    pub fn squeeze_element(&mut self) -> F {
+        if !self.squeezing || self.position == RATE {
+            self.permute();
+            self.position = 0;
+            self.squeezing = true;
+        }
+        let element = self.state[self.position];
+        self.position += 1;
+        element
+    }
+}
+
+impl<F: PrimeField> TranscriptHash for PoseidonHash<F> {
+    fn new() -> Self {
+        Self {
+            state: vec![F::zero(); WIDTH],
+            parameters: PoseidonParameters::generate(),
+            position: 0,
+            squeezing: false,
+            buffer: Vec::new(),
+        }
+    }
+
+    fn absorb(&mut self, bytes: &[u8]) {
+        self.absorb_element(F::from(bytes.len() as u64));
+        for chunk in bytes.chunks(Self::bytes_per_element()) {
+            self.absorb_element(F::from_le_bytes_mod_order(chunk));
+        }
+    }
+
+    /// elements of `F` are absorbed as they are, preceded by their number
+    fn absorb_field<G: Field>(&mut self, elements: &[G]) -> SResult<()> {
+        if TypeId::of::<G>() != TypeId::of::<F>() {
+            let mut bytes = Vec::new();
+            elements.to_vec().serialize(&mut bytes)?;
+            self.absorb(&bytes);
+            return Ok(());
+        }
+        self.absorb_element(F::from(elements.len() as u64));
+        for element in elements.iter() {
+            let element = (element as &dyn Any).downcast_ref::<F>().expect("G is F");
+            self.absorb_element(*element);
+        }
+        Ok(())
+    }
+
+    /// elements of `F` are squeezed as they are
+    fn challenge_field<G: Field>(&mut self) -> G {
+        if TypeId::of::<G>() != TypeId::of::<F>() {
+            return G::rand(self);
+        }
+        let element = self.squeeze_element();
+        *(&element as &dyn Any).downcast_ref::<G>().expect("G is F")
+    }
+}
+
+impl<F: PrimeField> RngCore for PoseidonHash<F> {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for byte in dest.iter_mut() {
+            if self.buffer.is_empty() {
+                let element = self.squeeze_element();
+                let mut bytes = Vec::new();
+                element
+                    .serialize(&mut bytes)
+                    .expect("serializing a field element never fails");
+                // only the low bytes are close to uniform
+                bytes.truncate(Self::bytes_per_element());
+                bytes.reverse();
+                self.buffer = bytes;
+            }
+            *byte = self.buffer.pop().unwrap();
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), RandError> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data_structures::transcript::poseidon::PoseidonParameters;
+    use crate::data_structures::transcript::{PoseidonHash, TranscriptHash};
+    use crate::test_utils::TestCurveFr;
+    use ark_ff::{Field, One, Zero};
+    use ark_serialize::CanonicalSerialize;
+
+    fn to_hex(element: &TestCurveFr) -> String {
+        let mut bytes = Vec::new();
+        element.serialize(&mut bytes).unwrap();
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_parameters() {
+        let parameters = PoseidonParameters::<TestCurveFr>::generate();
+        // parameters are deterministic, so that every sponge over the same field agrees
+        let again = PoseidonParameters::<TestCurveFr>::generate();
+        assert_eq!(parameters.round_constants, again.round_constants);
+        assert_eq!(parameters.mds, again.mds);
+        assert_eq!(parameters.round_constants.len(), (8 + 57) * 3);
+        // first and last round constants from the grain LFSR, as little-endian bytes
+        assert_eq!(to_hex(&parameters.round_constants[0]), FIRST_ROUND_CONSTANT);
+        assert_eq!(to_hex(parameters.round_constants.last().unwrap()), LAST_ROUND_CONSTANT);
+
+        // mds matrix is the Cauchy matrix
+        assert_eq!(parameters.mds[1][2] * TestCurveFr::from(6u64), TestCurveFr::one());
+        assert_eq!(parameters.mds[0][0].inverse().unwrap(), TestCurveFr::from(3u64));
+    }
+
+    #[test]
+    fn test_permutation() {
+        let mut hash = PoseidonHash::<TestCurveFr>::new();
+        hash.state = vec![TestCurveFr::zero(), TestCurveFr::one(), TestCurveFr::from(2u64)];
+        hash.permute();
+        let state: Vec<_> = hash.state.iter().map(to_hex).collect();
+        assert_eq!(state, PERMUTATION_OF_0_1_2.to_vec());
+    }
+
+    #[test]
+    fn test_sponge() {
+        let mut hash = PoseidonHash::<TestCurveFr>::new();
+        // permutation of the zero state is not trivial and is deterministic
+        let first = hash.squeeze_element();
+        assert!(!first.is_zero());
+        assert_eq!(first, PoseidonHash::<TestCurveFr>::new().squeeze_element());
+        assert_ne!(first, hash.squeeze_element());
+
+        // absorbed bytes change the output, and bytes of different lengths are distinguished
+        let mut a = PoseidonHash::<TestCurveFr>::new();
+        a.absorb(&[1, 2]);
+        let a = a.squeeze_element();
+        assert_ne!(first, a);
+        let mut b = PoseidonHash::<TestCurveFr>::new();
+        b.absorb(&[1, 2, 0]);
+        assert_ne!(a, b.squeeze_element());
+
+        // elements of the field are absorbed and squeezed natively
+        let mut native = PoseidonHash::<TestCurveFr>::new();
+        native.absorb_field(&[TestCurveFr::from(7u64)]).unwrap();
+        let mut expected = PoseidonHash::<TestCurveFr>::new();
+        expected.absorb_element(TestCurveFr::one());
+        expected.absorb_element(TestCurveFr::from(7u64));
+        assert_eq!(native.challenge_field::<TestCurveFr>(), expected.squeeze_element());
+    }
+
+    /// 0x6c4ffa723eaf1a7bf74905cc7dae4ca9ff4a2c3bc81d42e09540d1f250910880
+    const FIRST_ROUND_CONSTANT: &str = "80089150f2d14095e0421dc83b2c4affa94cae7dcc0549f77b1aaf3e72fa4f6c";
+    const LAST_ROUND_CONSTANT: &str = "25f7efaa35e8e2c56ba325ef68c6078d9732c4e1567b89778382ffae9430b357";
+    const PERMUTATION_OF_0_1_2: [&str; 3] = [
+        "e7ccd4a4a9a47cdec08d215c80826bc2c7351afb5d23e9fcfe23a455188e8658",
+        "5420707ff5c8bf50a5793cda354792296c761511962d335e79188ab635bfd438",
+        "bc90208c5d058646c52f905de2c3adae4b8ab5199342cb9ab999705f868d6940",
+    ];
+}
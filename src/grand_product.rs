@@ -0,0 +1,355 @@
+//! grand product argument, used by offline memory checking of Spark
+//!
+//! For leaves `v` with `m` entries, the prover commits to the product tree `f` with `log(m) + 1` variables, where
+//! `f(x, 0) = v(x)`, `f(x, 1) = f(0, x) * f(1, x)` and the root `f(0, 1, ..., 1)` is the product of all leaves.
+//! The relation between layers is checked by a sumcheck on `eq(tau, x) * (f(x, 1) - f(0, x) * f(1, x))`.
+
+use ark_ec::PairingEngine;
+use ark_ff::{Field, One, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
+use ark_std::iter::once;
+use linear_sumcheck::data_structures::ml_extension::{ArithmeticCombination, MLExtension};
+use linear_sumcheck::data_structures::MLExtensionArray;
+
+use crate::commitment::commit::Commitment;
+use crate::commitment::data_structures::PublicParameter;
+use crate::commitment::open::Proof;
+use crate::commitment::verify::OpeningVerifierKey;
+use crate::commitment::MLPolyCommit;
+use crate::data_structures::eq::{eq_eval, eq_table};
+use crate::data_structures::transcript::{Transcript, TranscriptHash};
+use crate::error::{invalid_arg, SResult};
+use crate::sumcheck::{self, SumcheckProof};
+
+/// proof that each committed product tree multiplies its leaves to the claimed product
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct GrandProductProof<E: PairingEngine> {
+    pub products: Vec<E::Fr>,
+    pub tree_commitments: Vec<Commitment<E>>,
+    pub sumcheck: SumcheckProof<E::Fr>,
+    /// f(r, 0), which is the leaves evaluated at r
+    pub leaf_evaluations: Vec<E::Fr>,
+    /// f(r, 1)
+    pub parent_evaluations: Vec<E::Fr>,
+    /// f(0, r)
+    pub left_child_evaluations: Vec<E::Fr>,
+    /// f(1, r)
+    pub right_child_evaluations: Vec<E::Fr>,
+    pub proof_for_r_c: Proof<E>,
+    pub proof_for_c_r: Proof<E>,
+    pub proof_for_root: Proof<E>,
+}
+
+/// proof of the products of committed polynomials: the product trees, and the opening of the committed
+/// polynomials at the point where the leaves are evaluated
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct CommittedGrandProductProof<E: PairingEngine> {
+    pub product: GrandProductProof<E>,
+    pub proof_for_leaves: Proof<E>,
+}
+
+/// table of the product tree: leaves, followed by each layer of products, followed by a zero
+fn product_tree<F: Field>(leaves: &[F]) -> Vec<F> {
+    let m = leaves.len();
+    let mut tree = Vec::with_capacity(m << 1);
+    tree.extend_from_slice(leaves);
+    for i in 0..(m - 1) {
+        let product = tree[i << 1] * &tree[(i << 1) + 1];
+        tree.push(product);
+    }
+    tree.push(F::zero());
+    tree
+}
+
+/// location of the root f(0, 1, ..., 1)
+fn root_point<F: Field>(num_variables: usize) -> Vec<F> {
+    once(F::zero())
+        .chain((0..num_variables).map(|_| F::one()))
+        .collect()
+}
+
+/// Prove the products of several vectors of the same power-of-two length.
+///
+/// return: the proof and the point `r` at which the leaves are evaluated
+pub(crate) fn prove<E: PairingEngine, H: TranscriptHash>(
+    pp: &PublicParameter<E>,
+    leaves: Vec<Vec<E::Fr>>,
+    transcript: &mut Transcript<H>,
+) -> SResult<(GrandProductProof<E>, Vec<E::Fr>)> {
+    let m = leaves
+        .first()
+        .map(|v| v.len())
+        .ok_or(invalid_arg("no leaves for grand product"))?;
+    if m < 2 || !m.is_power_of_two() || leaves.iter().any(|v| v.len() != m) {
+        return Err(invalid_arg(
+            "leaves of grand product should have same power of two length",
+        ));
+    }
+    let nv = ark_std::log2(m) as usize;
+
+    let trees: Vec<Vec<E::Fr>> = leaves.iter().map(|v| product_tree(v)).collect();
+    let products: Vec<_> = trees.iter().map(|f| f[(m << 1) - 2]).collect();
+    let tree_mles = trees
+        .iter()
+        .map(|f| MLExtensionArray::from_slice(f))
+        .collect::<Result<Vec<_>, _>>()?;
+    let tree_commitments = tree_mles
+        .iter()
+        .map(|f| MLPolyCommit::commit(pp, f.clone()))
+        .collect::<SResult<Vec<_>>>()?;
+    transcript.append_message(b"product tree commitments", &tree_commitments)?;
+    transcript.append_message(b"products", &products)?;
+
+    let tau: Vec<E::Fr> = transcript.challenge_scalars(b"tau", nv)?;
+    let lambda: E::Fr = transcript.challenge_scalar(b"lambda")?;
+
+    // sum over x: eq(tau, x) * sum_j lambda^j (f_j(x, 1) - f_j(0, x) * f_j(1, x))
+    let eq = MLExtensionArray::from_vec(eq_table(&tau))?;
+    let mut poly = ArithmeticCombination::new(nv);
+    let mut coefficient = E::Fr::one();
+    for f in trees.iter() {
+        let parent = MLExtensionArray::from_slice(&f[m..])?;
+        let left = MLExtensionArray::from_vec((0..m).map(|x| f[x << 1]).collect())?;
+        let right = MLExtensionArray::from_vec((0..m).map(|x| f[(x << 1) + 1]).collect())?;
+        poly.add_product(vec![eq.clone(), parent.multiply(coefficient)?].into_iter())?;
+        poly.add_product(vec![eq.clone(), left.multiply(-coefficient)?, right].into_iter())?;
+        coefficient *= lambda;
+    }
+    let (sumcheck, r) = sumcheck::prove(poly, transcript)?;
+
+    let r_0: Vec<_> = r.iter().cloned().chain(once(E::Fr::zero())).collect();
+    let r_1: Vec<_> = r.iter().cloned().chain(once(E::Fr::one())).collect();
+    let zero_r: Vec<_> = once(E::Fr::zero()).chain(r.iter().cloned()).collect();
+    let one_r: Vec<_> = once(E::Fr::one()).chain(r.iter().cloned()).collect();
+    let evaluate = |point: &[E::Fr]| {
+        tree_mles
+            .iter()
+            .map(|f| f.eval_at(point))
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let leaf_evaluations = evaluate(&r_0)?;
+    let parent_evaluations = evaluate(&r_1)?;
+    let left_child_evaluations = evaluate(&zero_r)?;
+    let right_child_evaluations = evaluate(&one_r)?;
+    transcript.append_scalars(b"leaf evaluations", &leaf_evaluations)?;
+    transcript.append_scalars(b"parent evaluations", &parent_evaluations)?;
+    transcript.append_scalars(b"left child evaluations", &left_child_evaluations)?;
+    transcript.append_scalars(b"right child evaluations", &right_child_evaluations)?;
+
+    let c_leaf: E::Fr = transcript.challenge_scalar(b"c_leaf")?;
+    let c_child: E::Fr = transcript.challenge_scalar(b"c_child")?;
+    let mu: E::Fr = transcript.challenge_scalar(b"mu")?;
+    let r_c: Vec<_> = r.iter().cloned().chain(once(c_leaf)).collect();
+    let c_r: Vec<_> = once(c_child).chain(r.iter().cloned()).collect();
+    let proof_for_r_c = MLPolyCommit::batch_open(pp, tree_mles.clone(), &r_c, mu)?;
+    let proof_for_c_r = MLPolyCommit::batch_open(pp, tree_mles.clone(), &c_r, mu)?;
+    let proof_for_root = MLPolyCommit::batch_open(pp, tree_mles, &root_point(nv), mu)?;
+
+    let proof = GrandProductProof {
+        products,
+        tree_commitments,
+        sumcheck,
+        leaf_evaluations,
+        parent_evaluations,
+        left_child_evaluations,
+        right_child_evaluations,
+        proof_for_r_c,
+        proof_for_c_r,
+        proof_for_root,
+    };
+    Ok((proof, r))
+}
+
+/// Verify the grand product proof, where leaves have `num_variables` variables.
+///
+/// return: the products, the point `r`, and the claimed evaluations of leaves at `r`, which should be checked by the caller
+pub(crate) fn verify<E: PairingEngine, K: OpeningVerifierKey<E>, H: TranscriptHash>(
+    vp: &K,
+    proof: GrandProductProof<E>,
+    num_variables: usize,
+    transcript: &mut Transcript<H>,
+) -> SResult<(Vec<E::Fr>, Vec<E::Fr>, Vec<E::Fr>)> {
+    let n = proof.tree_commitments.len();
+    if n == 0
+        || proof.products.len() != n
+        || proof.leaf_evaluations.len() != n
+        || proof.parent_evaluations.len() != n
+        || proof.left_child_evaluations.len() != n
+        || proof.right_child_evaluations.len() != n
+        || proof
+            .tree_commitments
+            .iter()
+            .any(|c| c.nv != num_variables + 1)
+    {
+        return Err(invalid_arg("malformed grand product proof"));
+    }
+    transcript.append_message(b"product tree commitments", &proof.tree_commitments)?;
+    transcript.append_message(b"products", &proof.products)?;
+
+    let tau: Vec<E::Fr> = transcript.challenge_scalars(b"tau", num_variables)?;
+    let lambda: E::Fr = transcript.challenge_scalar(b"lambda")?;
+
+    let (r, expected) = sumcheck::verify(proof.sumcheck, num_variables, 3, E::Fr::zero(), transcript)?;
+    let mut actual = E::Fr::zero();
+    let mut coefficient = E::Fr::one();
+    for j in 0..n {
+        actual += coefficient
+            * (proof.parent_evaluations[j]
+                - proof.left_child_evaluations[j] * proof.right_child_evaluations[j]);
+        coefficient *= lambda;
+    }
+    if eq_eval(&tau, &r)? * actual != expected {
+        return Err(crate::Error::WrongWitness(Some(
+            "grand product has wrong subclaim".into(),
+        )));
+    }
+
+    transcript.append_scalars(b"leaf evaluations", &proof.leaf_evaluations)?;
+    transcript.append_scalars(b"parent evaluations", &proof.parent_evaluations)?;
+    transcript.append_scalars(b"left child evaluations", &proof.left_child_evaluations)?;
+    transcript.append_scalars(b"right child evaluations", &proof.right_child_evaluations)?;
+
+    let c_leaf: E::Fr = transcript.challenge_scalar(b"c_leaf")?;
+    let c_child: E::Fr = transcript.challenge_scalar(b"c_child")?;
+    let mu: E::Fr = transcript.challenge_scalar(b"mu")?;
+    let r_c: Vec<_> = r.iter().cloned().chain(once(c_leaf)).collect();
+    let c_r: Vec<_> = once(c_child).chain(r.iter().cloned()).collect();
+    let r_c_evaluations: Vec<_> = proof
+        .leaf_evaluations
+        .iter()
+        .zip(proof.parent_evaluations.iter())
+        .map(|(leaf, parent)| (E::Fr::one() - c_leaf) * leaf + c_leaf * parent)
+        .collect();
+    let c_r_evaluations: Vec<_> = proof
+        .left_child_evaluations
+        .iter()
+        .zip(proof.right_child_evaluations.iter())
+        .map(|(left, right)| (E::Fr::one() - c_child) * left + c_child * right)
+        .collect();
+    let commitments: Vec<_> = proof.tree_commitments.iter().collect();
+    if !MLPolyCommit::batch_verify(vp, &commitments, &r_c, &r_c_evaluations, mu, proof.proof_for_r_c)?
+        || !MLPolyCommit::batch_verify(vp, &commitments, &c_r, &c_r_evaluations, mu, proof.proof_for_c_r)?
+        || !MLPolyCommit::batch_verify(
+            vp,
+            &commitments,
+            &root_point(num_variables),
+            &proof.products,
+            mu,
+            proof.proof_for_root,
+        )?
+    {
+        return Err(crate::Error::WrongWitness(Some(
+            "cannot verify product tree".into(),
+        )));
+    }
+
+    Ok((proof.products, r, proof.leaf_evaluations))
+}
+
+/// Prove the product of the evaluations over the boolean hypercube of each polynomial, where `polynomials[j]`
+/// is committed in `commitments[j]`. All polynomials should have the same number of variables.
+///
+/// return: the product of each polynomial, and the proof
+pub fn prove_committed<E: PairingEngine, H: TranscriptHash>(
+    pp: &PublicParameter<E>,
+    polynomials: Vec<MLExtensionArray<E::Fr>>,
+    commitments: &[Commitment<E>],
+    transcript: &mut Transcript<H>,
+) -> SResult<(Vec<E::Fr>, CommittedGrandProductProof<E>)> {
+    if polynomials.len() != commitments.len() {
+        return Err(invalid_arg("should have one commitment for each polynomial"));
+    }
+    transcript.append_message(b"grand product commitments", &commitments.to_vec())?;
+    let leaves = polynomials
+        .iter()
+        .map(|p| p.clone().into_table())
+        .collect::<Result<Vec<_>, _>>()?;
+    let (product, r) = prove(pp, leaves, transcript)?;
+    let mu: E::Fr = transcript.challenge_scalar(b"leaf challenge")?;
+    let proof_for_leaves = MLPolyCommit::batch_open(pp, polynomials, &r, mu)?;
+    Ok((
+        product.products.clone(),
+        CommittedGrandProductProof {
+            product,
+            proof_for_leaves,
+        },
+    ))
+}
+
+/// Verify that the product of the evaluations over the boolean hypercube of the polynomial committed in
+/// `commitments[j]` is `products[j]`.
+///
+/// `vp` can be the public `VerifierParameter` or a `DesignatedVerifierParameter`.
+pub fn verify_committed<E: PairingEngine, K: OpeningVerifierKey<E>, H: TranscriptHash>(
+    vp: &K,
+    commitments: &[Commitment<E>],
+    products: &[E::Fr],
+    proof: CommittedGrandProductProof<E>,
+    transcript: &mut Transcript<H>,
+) -> SResult<bool> {
+    let nv = commitments
+        .first()
+        .map(|c| c.nv)
+        .ok_or(invalid_arg("no polynomial for grand product"))?;
+    if commitments.len() != products.len() || commitments.iter().any(|c| c.nv != nv) {
+        return Err(invalid_arg("should have one product for each polynomial of the same size"));
+    }
+    transcript.append_message(b"grand product commitments", &commitments.to_vec())?;
+    let (claimed_products, r, leaf_evaluations) = verify(vp, proof.product, nv, transcript)?;
+    if claimed_products.as_slice() != products {
+        return Err(crate::Error::WrongWitness(Some("grand product has wrong product".into())));
+    }
+    let mu: E::Fr = transcript.challenge_scalar(b"leaf challenge")?;
+    let commitments: Vec<_> = commitments.iter().collect();
+    if !MLPolyCommit::batch_verify(vp, &commitments, &r, &leaf_evaluations, mu, proof.proof_for_leaves)? {
+        return Err(crate::Error::WrongWitness(Some("cannot verify leaves of grand product".into())));
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commitment::MLPolyCommit;
+    use crate::data_structures::transcript::Transcript;
+    use crate::grand_product;
+    use crate::test_utils::TestCurve;
+    use ark_ec::PairingEngine;
+    use ark_ff::{test_rng, One, UniformRand};
+    use linear_sumcheck::data_structures::MLExtensionArray;
+
+    type E = TestCurve;
+    type Fr = <E as PairingEngine>::Fr;
+
+    #[test]
+    fn test_committed_grand_product() {
+        let mut rng = test_rng();
+        let nv = 5;
+        // the product tree has one more variable than the leaves
+        let (pp, vp, _) = MLPolyCommit::<E>::keygen(nv + 1, &mut rng).unwrap();
+        let tables: Vec<Vec<Fr>> = (0..3).map(|_| (0..(1 << nv)).map(|_| Fr::rand(&mut rng)).collect()).collect();
+        let expected: Vec<Fr> = tables.iter().map(|t| t.iter().fold(Fr::one(), |acc, x| acc * x)).collect();
+        let polynomials: Vec<_> = tables.iter().map(|t| MLExtensionArray::from_slice(t).unwrap()).collect();
+        let commitments: Vec<_> = polynomials.iter().map(|p| MLPolyCommit::commit(&pp, p.clone()).unwrap()).collect();
+
+        let mut transcript: Transcript = Transcript::new(b"test");
+        let (products, proof) = grand_product::prove_committed(&pp, polynomials.clone(), &commitments, &mut transcript).unwrap();
+        assert_eq!(products, expected);
+        let mut transcript: Transcript = Transcript::new(b"test");
+        assert!(grand_product::verify_committed(&vp, &commitments, &products, proof, &mut transcript).unwrap());
+
+        // wrong claimed product
+        let mut transcript: Transcript = Transcript::new(b"test");
+        let (mut products, proof) = grand_product::prove_committed(&pp, polynomials.clone(), &commitments, &mut transcript).unwrap();
+        products[1] += Fr::one();
+        let mut transcript: Transcript = Transcript::new(b"test");
+        assert!(grand_product::verify_committed(&vp, &commitments, &products, proof, &mut transcript).is_err());
+
+        // product trees of other polynomials do not match the commitments
+        let mut other = polynomials;
+        other[2] = MLExtensionArray::from_slice(&tables[0]).unwrap();
+        let mut transcript: Transcript = Transcript::new(b"test");
+        let (products, proof) = grand_product::prove_committed(&pp, other, &commitments, &mut transcript).unwrap();
+        let mut transcript: Transcript = Transcript::new(b"test");
+        assert!(grand_product::verify_committed(&vp, &commitments, &products, proof, &mut transcript).is_err());
+    }
+}
@@ -14,20 +14,40 @@ mod benchmark;
 
 pub mod commitment;
 
-use crate::ahp::indexer::{IndexPK, IndexVK};
+/// computation commitments to sparse matrices
+pub mod spark;
+
+/// sumcheck over an arithmetic combination, driven by a Fiat-Shamir transcript
+pub mod sumcheck;
+
+/// argument for products of a public sparse matrix and a committed vector
+pub mod mat_vec;
+
+/// zerocheck of products of committed multilinear polynomials
+pub mod zerocheck;
+
+/// grand product of the evaluations of committed multilinear polynomials
+pub mod grand_product;
+
+/// sumcheck over sums of products of committed and public multilinear polynomials
+pub mod virtual_polynomial;
+
+use crate::ahp::indexer::{IndexPK, IndexVK, PreprocessedIndexPK, PreprocessedIndexVK};
 use crate::ahp::MLProofForR1CS;
-use crate::data_structures::proof::Proof;
+use crate::data_structures::proof::{BatchProof, LinkedProof, MultiCircuitProof, MultiPhaseProof, PreprocessedProof, Proof};
+use crate::ahp::multi_phase::{MultiPhaseProver, PhaseLayout};
+use crate::ahp::commit_and_prove::CommittedSegment;
+use crate::data_structures::transcript::{Transcript, TranscriptHash};
 use crate::error::{invalid_arg, SResult};
 use ark_ec::PairingEngine;
 use ark_relations::r1cs::Matrix;
-use ark_std::collections::LinkedList;
-use ark_std::iter::FromIterator;
-use ark_std::log2;
 pub use error::Error;
-use linear_sumcheck::data_structures::random::FeedableRNG;
 use linear_sumcheck::data_structures::Blake2s512Rng;
-use linear_sumcheck::ml_sumcheck::ahp::prover::ProverMsg;
-use crate::ahp::setup::{PublicParameter, VerifierParameter};
+use crate::ahp::setup::{PublicParameter, VerifierParameter, DesignatedVerifierParameter};
+use crate::spark::MLSpark;
+use crate::commitment::accumulator::PairingAccumulator;
+use crate::data_structures::eq::eq_table;
+use ark_ff::Zero;
 
 /// module for interpret r1cs as ML Extension used by linear sumcheck
 pub mod data_structures;
@@ -38,16 +58,31 @@ mod error;
 #[cfg(test)]
 pub(crate) mod test_utils;
 
-pub struct MLArgumentForR1CS<E: PairingEngine>(#[doc(hidden)] PhantomData<E>);
+/// Non-interactive argument for R1CS, where `H` is the hash function of the Fiat-Shamir transcript.
+pub struct MLArgumentForR1CS<E: PairingEngine, H: TranscriptHash = Blake2s512Rng>(
+    #[doc(hidden)] PhantomData<(E, H)>,
+);
 
-impl<E: PairingEngine> MLArgumentForR1CS<E> {
+impl<E: PairingEngine, H: TranscriptHash> MLArgumentForR1CS<E, H> {
     /// generate prover key and verifier key
+    /// * `num_instance_variables`: |v|, including the constant one at v\[0\]
+    /// * `num_witness_variables`: |w|
     pub fn index(
         matrix_a: Matrix<E::Fr>,
         matrix_b: Matrix<E::Fr>,
         matrix_c: Matrix<E::Fr>,
+        num_instance_variables: usize,
+        num_witness_variables: usize,
     ) -> Result<IndexPK<E::Fr>, crate::Error> {
-        MLProofForR1CS::<E>::index(matrix_a, matrix_b, matrix_c)
+        MLProofForR1CS::<E>::index(matrix_a, matrix_b, matrix_c, num_instance_variables, num_witness_variables)
+    }
+
+    /// generate the index of the customizable constraint system `sum_j c_j ∘_{i in S_j} M_i z = 0`, where `M_i`,
+    /// `S_j` and `c_j` are `matrices[i]`, `multisets[j]` and `coefficients[j]`
+    /// * `num_instance_variables`: |v|, including the constant one at v\[0\]
+    /// * `num_witness_variables`: |w|
+    pub fn index_ccs(matrices: Vec<Matrix<E::Fr>>, multisets: Vec<Vec<usize>>, coefficients: Vec<E::Fr>, num_instance_variables: usize, num_witness_variables: usize) -> SResult<IndexPK<E::Fr>> {
+        MLProofForR1CS::<E>::index_ccs(matrices, multisets, coefficients, num_instance_variables, num_witness_variables)
     }
 
     /// prove the circuit, giving the index
@@ -56,164 +91,196 @@ impl<E: PairingEngine> MLArgumentForR1CS<E> {
     /// * `w`: private input
     /// * `pp`: public parameter
     pub fn prove(pk: IndexPK<E::Fr>, v: Vec<E::Fr>, w: Vec<E::Fr>, pp: &PublicParameter<E>) -> SResult<Proof<E>> {
-        let log_n = pk.log_n;
-
-        let mut fs_rng = Blake2s512Rng::setup();
-        fs_rng.feed_randomness(&pk.matrix_a)?;
-        fs_rng.feed_randomness(&pk.matrix_b)?;
-        fs_rng.feed_randomness(&pk.matrix_c)?;
-        fs_rng.feed_randomness(&v)?;
+        let mut transcript = MLProofForR1CS::init_transcript(pp.id(), &pk, &v)?;
+        let (proof, _) = MLProofForR1CS::prove_rounds(pk, v, w, pp, &mut transcript)?;
+        Ok(proof)
+    }
 
-        let log_v = log2(v.len()) as usize;
+    /// Prove k instances of the circuit in one proof, where `vs[j]` and `ws[j]` are the public and private
+    /// input of instance j. `pp` should support `MLProofForR1CS::num_variables_for_batch(&pk, k)` variables.
+    pub fn prove_batch(pk: &IndexPK<E::Fr>, vs: Vec<Vec<E::Fr>>, ws: Vec<Vec<E::Fr>>, pp: &PublicParameter<E>) -> SResult<BatchProof<E>> {
+        let mut transcript = MLProofForR1CS::init_batch_transcript(pp.id(), pk, &vs)?;
+        MLProofForR1CS::prove_batch(pk, vs, ws, pp, &mut transcript)
+    }
 
-        let ps = MLProofForR1CS::prover_init(pk, v, w)?;
+    /// verify a batch proof against the public inputs of all instances
+    pub fn verify_batch(vk: &IndexVK<E::Fr>, vs: Vec<Vec<E::Fr>>, proof: BatchProof<E>, vp: &VerifierParameter<E>) -> SResult<bool> {
+        let mut transcript = MLProofForR1CS::init_batch_transcript(vp.id(), vk, &vs)?;
+        MLProofForR1CS::verify_batch(vk, vs, proof, vp, &mut transcript)
+    }
 
-        let timer = start_timer!(||"Prove 1");
-        let (ps, pm1) = MLProofForR1CS::prover_first_round(ps, pp)?;
-        end_timer!(timer);
-        fs_rng.feed_randomness(&pm1)?;
-        let vm = MLProofForR1CS::<E>::sample_first_round(log_v, &mut fs_rng);
+    /// Prove instances of different circuits in one proof, where each instance is `(pk, v, w)`. `pp` should
+    /// support `MLProofForR1CS::num_variables_for_multi_circuit` variables of all indices.
+    pub fn prove_multi_circuit(instances: Vec<(&IndexPK<E::Fr>, Vec<E::Fr>, Vec<E::Fr>)>, pp: &PublicParameter<E>) -> SResult<MultiCircuitProof<E>> {
+        let bound: Vec<_> = instances.iter().map(|(pk, v, _)| (*pk, v.as_slice())).collect();
+        let mut transcript = MLProofForR1CS::init_multi_circuit_transcript(pp.id(), &bound)?;
+        MLProofForR1CS::prove_multi_circuit(instances, pp, &mut transcript)
+    }
 
-        let timer = start_timer!(||"Prove 2");
-        let (ps, pm2) = MLProofForR1CS::prover_second_round(ps, vm, pp)?;
-        end_timer!(timer);
-        fs_rng.feed_randomness(&pm2)?;
-        let vm = MLProofForR1CS::<E>::sample_second_round(ps.pk.log_n, &mut fs_rng);
+    /// verify a multi-circuit proof against the verifier key and public input of every instance
+    pub fn verify_multi_circuit(instances: Vec<(&IndexVK<E::Fr>, Vec<E::Fr>)>, proof: MultiCircuitProof<E>, vp: &VerifierParameter<E>) -> SResult<bool> {
+        let bound: Vec<_> = instances.iter().map(|(vk, v)| (*vk, v.as_slice())).collect();
+        let mut transcript = MLProofForR1CS::init_multi_circuit_transcript(vp.id(), &bound)?;
+        MLProofForR1CS::verify_multi_circuit(instances, proof, vp, &mut transcript)
+    }
 
-        let timer = start_timer!(||"Prove 3");
-        let (mut ps, pm3) = MLProofForR1CS::prover_third_round(ps, vm)?;
-        end_timer!(timer);
-        fs_rng.feed_randomness(&pm3)?;
-        let mut vm = MLProofForR1CS::<E>::sample_third_round();
-
-        let timer = start_timer!(||"Prove Sumcheck 1");
-        let mut sumcheck1_msgs = Vec::with_capacity(log_n);
-        for _ in 0..(log_n - 1) {
-            let (ps_new, pm) = MLProofForR1CS::prove_first_sumcheck_round(ps, vm)?;
-            ps = ps_new;
-            fs_rng.feed_randomness(&pm)?;
-            sumcheck1_msgs.push(pm);
-            vm = MLProofForR1CS::<E>::sample_verify_first_sumcheck_ongoing_round(&mut fs_rng);
-        }
+    /// Prove the circuit, and that segments of w match the given commitments, so that proofs sharing a
+    /// committed segment are linked through it. Each segment should be committed with `pp`.
+    pub fn prove_with_segments(pk: IndexPK<E::Fr>, v: Vec<E::Fr>, w: Vec<E::Fr>, segments: &[CommittedSegment<E>], pp: &PublicParameter<E>) -> SResult<LinkedProof<E>> {
+        let mut transcript = MLProofForR1CS::init_transcript(pp.id(), &pk, &v)?;
+        MLProofForR1CS::prove_linked(pk, v, w, segments, pp, &mut transcript)
+    }
 
-        let (ps, pm) = MLProofForR1CS::prove_first_sumcheck_round(ps, vm)?;
-        end_timer!(timer);
-        fs_rng.feed_randomness(&pm)?;
-        sumcheck1_msgs.push(pm);
-        let vm = MLProofForR1CS::<E>::sample_verify_first_sumcheck_final_round(&mut fs_rng);
+    /// verify the proof and the openings of the committed segments with one multi-pairing
+    pub fn verify_with_segments(vk: IndexVK<E::Fr>, v: Vec<E::Fr>, segments: &[CommittedSegment<E>], proof: LinkedProof<E>, vp: &VerifierParameter<E>) -> SResult<bool> {
+        let mut transcript = MLProofForR1CS::init_transcript(vp.id(), &vk, &v)?;
+        MLProofForR1CS::verify_linked(vk, v, segments, proof, vp, &mut transcript)
+    }
 
-        let timer = start_timer!(||"Prove 4");
-        let (ps, pm4) = MLProofForR1CS::prove_fourth_round(ps, vm)?;
-        end_timer!(timer);
-        fs_rng.feed_randomness(&pm4)?;
-        let vm = MLProofForR1CS::<E>::sample_verify_fourth_round(&mut fs_rng);
+    /// Prove the circuit after the phases of `layout` are committed. `v` is the public input without the
+    /// challenges, which are appended to it in order.
+    pub fn multi_phase_prover<'a>(pk: &'a IndexPK<E::Fr>, layout: &'a PhaseLayout, v: Vec<E::Fr>, pp: &'a PublicParameter<E>) -> SResult<MultiPhaseProver<'a, E, H>> {
+        MultiPhaseProver::new(pk, layout, v, pp)
+    }
 
-        let timer = start_timer!(||"Prove 5");
-        let (mut ps, pm5) = MLProofForR1CS::prove_fifth_round(ps, vm)?;
-        end_timer!(timer);
-        fs_rng.feed_randomness(&pm5)?;
-        let mut vm = MLProofForR1CS::<E>::sample_verify_fifth_round();
-
-        let mut sumcheck2_msgs = Vec::with_capacity(log_n);
-        let timer = start_timer!(||"Prove Sumcheck 2");
-        for _ in 0..(log_n - 1) {
-            let (ps_new, pm) = MLProofForR1CS::prove_second_sumcheck_round(ps, vm)?;
-            ps = ps_new;
-            fs_rng.feed_randomness(&pm)?;
-            sumcheck2_msgs.push(pm);
-            vm = MLProofForR1CS::<E>::sample_verify_second_sumcheck_ongoing_round(&mut fs_rng);
+    /// verify a multi-phase proof, where `v` is the public input without the challenges
+    pub fn verify_multi_phase(vk: IndexVK<E::Fr>, layout: &PhaseLayout, mut v: Vec<E::Fr>, proof: MultiPhaseProof<E>, vp: &VerifierParameter<E>) -> SResult<bool> {
+        if proof.phase_commitments.len() != layout.phases.len() {
+            return Err(invalid_arg("should have one commitment for each phase"));
+        }
+        let mut transcript = MLProofForR1CS::init_multi_phase_transcript(vp.id(), &vk, layout, &v)?;
+        let mut segments = Vec::with_capacity(layout.phases.len());
+        for (phase, commitment) in layout.phases.iter().zip(proof.phase_commitments.into_iter()) {
+            v.extend(MLProofForR1CS::commit_phase_to_transcript(phase, &commitment, &mut transcript)?);
+            segments.push(CommittedSegment::new(phase.offset, commitment));
         }
+        MLProofForR1CS::verify_linked(vk, v, &segments, proof.proof, vp, &mut transcript)
+    }
 
-        let (ps, pm) = MLProofForR1CS::prove_second_sumcheck_round(ps, vm)?;
-        end_timer!(timer);
-        fs_rng.feed_randomness(&pm)?;
-        sumcheck2_msgs.push(pm);
-        let vm = MLProofForR1CS::<E>::sample_verify_second_sumcheck_final_round(&mut fs_rng);
+    /// commit to the matrices of `pk`, so that the verifier only needs the commitments
+    pub fn preprocess(pk: IndexPK<E::Fr>, pp: &PublicParameter<E>) -> SResult<PreprocessedIndexPK<E>> {
+        MLProofForR1CS::preprocess(pk, pp)
+    }
+
+    /// prove the circuit using preprocessed index. The proof also proves the evaluations of the matrices.
+    pub fn prove_preprocessed(pk: PreprocessedIndexPK<E>, v: Vec<E::Fr>, w: Vec<E::Fr>, pp: &PublicParameter<E>) -> SResult<PreprocessedProof<E>> {
+        let mut transcript = MLProofForR1CS::init_transcript(pp.id(), &pk, &v)?;
+        let (proof, (r_x, r_y)) = MLProofForR1CS::prove_rounds(pk.index, v, w, pp, &mut transcript)?;
+        MLProofForR1CS::absorb_fifth_round(&proof.prover_fifth_message, &mut transcript)?;
 
-        let timer = start_timer!(||"Prove 6");
-        let pm6 = MLProofForR1CS::prove_sixth_round(ps, vm, pp)?;
+        let timer = start_timer!(||"Prove matrix evaluations");
+        let matrix_evaluation_proof = MLSpark::prove(pp, &pk.spark, &r_x, &r_y, &mut transcript)?;
         end_timer!(timer);
-        Ok(Proof {
-            prover_first_message: pm1,
-            prover_second_message: pm2,
-            prover_third_message: pm3,
-            first_sumcheck_messages: sumcheck1_msgs,
-            prover_fourth_message: pm4,
-            prover_fifth_message: pm5,
-            second_sumcheck_messages: sumcheck2_msgs,
-            prover_sixth_message: pm6,
+        Ok(PreprocessedProof {
+            proof,
+            matrix_evaluation_proof,
         })
     }
+
+    /// verify the proof using public verifier parameter
     pub fn verify(vk: IndexVK<E::Fr>, v: Vec<E::Fr>, proof: Proof<E>, vp: &VerifierParameter<E>) -> SResult<bool> {
-        let log_n = vk.log_n;
-        let mut first_sumcheck_messages =
-            LinkedList::from_iter(proof.first_sumcheck_messages.into_iter());
-        let mut second_sumcheck_messages =
-            LinkedList::from_iter(proof.second_sumcheck_messages.into_iter());
-
-        let mut fs_rng = Blake2s512Rng::setup();
-        fs_rng.feed_randomness(&vk.matrix_a)?;
-        fs_rng.feed_randomness(&vk.matrix_b)?;
-        fs_rng.feed_randomness(&vk.matrix_c)?;
-        fs_rng.feed_randomness(&v)?;
-
-        let vs = MLProofForR1CS::<E>::verifier_init(vk, v)?;
-
-        let pm = proof.prover_first_message;
-        fs_rng.feed_randomness(&pm)?;
-        let (vs, _) = MLProofForR1CS::verify_first_round(vs, pm, &mut fs_rng)?;
-
-        let pm = proof.prover_second_message;
-        fs_rng.feed_randomness(&pm)?;
-        let (vs, _) = MLProofForR1CS::verify_second_round(vs, pm, &mut fs_rng)?;
-
-        let pm = proof.prover_third_message;
-        fs_rng.feed_randomness(&pm)?;
-        let (mut vs, _) = MLProofForR1CS::verify_third_round(vs, pm)?;
-
-        for _ in 0..(log_n - 1) {
-            let pm = Self::try_pop(&mut first_sumcheck_messages)?;
-            fs_rng.feed_randomness(&pm)?;
-            let (vs_new, _) =
-                MLProofForR1CS::verify_first_sumcheck_ongoing_round(vs, pm, &mut fs_rng)?;
-            vs = vs_new;
-        }
+        let mut transcript = MLProofForR1CS::init_transcript(vp.id(), &vk, &v)?;
+        MLProofForR1CS::verify_with(vk, v, proof, &mut transcript, |vs, pm, _| {
+            MLProofForR1CS::verify_fifth_round(vs, pm, vp)
+        })
+    }
 
-        let pm = Self::try_pop(&mut first_sumcheck_messages)?;
-        fs_rng.feed_randomness(&pm)?;
-        let (vs, _) = MLProofForR1CS::verify_first_sumcheck_final_round(vs, pm, &mut fs_rng)?;
+    /// verify the proof using the setup trapdoor. Only available to the verifier who ran the setup.
+    /// Accepts exactly the proofs accepted by `verify`.
+    pub fn verify_designated(vk: IndexVK<E::Fr>, v: Vec<E::Fr>, proof: Proof<E>, dvp: &DesignatedVerifierParameter<E>) -> SResult<bool> {
+        let mut transcript = MLProofForR1CS::init_transcript(dvp.id(), &vk, &v)?;
+        MLProofForR1CS::verify_with(vk, v, proof, &mut transcript, |vs, pm, _| {
+            MLProofForR1CS::verify_fifth_round(vs, pm, dvp)
+        })
+    }
 
-        let pm = proof.prover_fourth_message;
-        fs_rng.feed_randomness(&pm)?;
-        let (vs, _) = MLProofForR1CS::verify_fourth_round(vs, pm, &mut fs_rng)?;
+    /// Verify the proof except for the final pairing check, which is returned as an accumulator. Accumulators
+    /// of many proofs can be merged with `PairingAccumulator::merge` and decided at once.
+    ///
+    /// return: error if any check other than the pairing check fails
+    pub fn verify_deferred(vk: IndexVK<E::Fr>, v: Vec<E::Fr>, proof: Proof<E>, vp: &VerifierParameter<E>) -> SResult<PairingAccumulator<E>> {
+        let mut transcript = MLProofForR1CS::init_transcript(vp.id(), &vk, &v)?;
+        let claim = MLProofForR1CS::verify_with(vk, v, proof, &mut transcript, |vs, pm, _| {
+            MLProofForR1CS::check_fifth_round(vs, pm)
+        })?;
+        PairingAccumulator::from_opening(vp, &claim)
+    }
 
-        let pm = proof.prover_fifth_message;
-        fs_rng.feed_randomness(&pm)?;
-        let (mut vs, _) = MLProofForR1CS::verify_fifth_round(vs, pm)?;
+    /// verify the proof using preprocessed verifier key
+    pub fn verify_preprocessed(vk: PreprocessedIndexVK<E>, v: Vec<E::Fr>, proof: PreprocessedProof<E>, vp: &VerifierParameter<E>) -> SResult<bool> {
+        let mut transcript = MLProofForR1CS::init_transcript(vp.id(), &vk, &v)?;
+        let matrix_evaluation_proof = proof.matrix_evaluation_proof;
+        MLProofForR1CS::verify_with(vk, v, proof.proof, &mut transcript, |vs, pm, transcript| {
+            MLProofForR1CS::verify_fifth_round_preprocessed(vs, pm, matrix_evaluation_proof, vp, transcript)
+        })
+    }
 
-        for _ in 0..(log_n - 1) {
-            let pm = Self::try_pop(&mut second_sumcheck_messages)?;
-            fs_rng.feed_randomness(&pm)?;
-            let (vs_new, _) =
-                MLProofForR1CS::verify_second_sumcheck_ongoing_round(vs, pm, &mut fs_rng)?;
-            vs = vs_new;
+    /// Verify many proofs of the same circuit. Each transcript is replayed and checked, while the openings of
+    /// all proofs are checked together with one multi-pairing. Evaluations of the matrices are shared between
+    /// proofs with the same challenge point.
+    ///
+    /// return: `None` if all proofs are accepted, or the index of the first rejected proof. Other errors, e.g. a
+    /// public input of the wrong length, are returned as they are.
+    pub fn batch_verify(vk: &IndexVK<E::Fr>, proofs: Vec<(Vec<E::Fr>, Proof<E>)>, vp: &VerifierParameter<E>) -> SResult<Option<usize>> {
+        let mut accumulators = Vec::with_capacity(proofs.len());
+        // (r_x, r_y) and M_i(r_x, r_y) for every matrix
+        let mut evaluations: Vec<((Vec<E::Fr>, Vec<E::Fr>), Vec<E::Fr>)> = Vec::new();
+        let mut batch_transcript = Transcript::<H>::new(b"r1cs-spartan-raw/batch-verify");
+        for (i, (v, proof)) in proofs.into_iter().enumerate() {
+            let mut transcript = MLProofForR1CS::init_transcript(vp.id(), vk, &v)?;
+            let claim = MLProofForR1CS::verify_with(vk, v, proof, &mut transcript, |vs, pm, _| {
+                MLProofForR1CS::check_final_claims(vs, pm, |_, r_x, r_y, coefficients| {
+                    let cached = evaluations
+                        .iter()
+                        .position(|((x, y), _)| x.as_slice() == r_x && y.as_slice() == r_y);
+                    let position = match cached {
+                        Some(position) => position,
+                        None => {
+                            let (eq_rx, eq_ry) = (eq_table(r_x), eq_table(r_y));
+                            let e = vk
+                                .matrices
+                                .iter()
+                                .map(|matrix| matrix.eval_with_eq(&eq_rx, &eq_ry))
+                                .collect::<SResult<Vec<_>>>()?;
+                            evaluations.push(((r_x.to_vec(), r_y.to_vec()), e));
+                            evaluations.len() - 1
+                        }
+                    };
+                    Ok(coefficients
+                        .iter()
+                        .zip(evaluations[position].1.iter())
+                        .fold(E::Fr::zero(), |acc, (r_i, e)| acc + &(*r_i * e)))
+                })
+            });
+            let claim = match claim {
+                Ok(claim) => claim,
+                Err(crate::Error::WrongWitness(_)) | Err(crate::Error::SumCheckError(_)) => return Ok(Some(i)),
+                Err(e) => return Err(e),
+            };
+            // an opening of the wrong shape rejects this proof only
+            match PairingAccumulator::from_opening(vp, &claim) {
+                Ok(accumulator) => {
+                    batch_transcript.append_message(b"opening claim", &claim)?;
+                    accumulators.push(accumulator);
+                }
+                Err(crate::Error::InvalidArgument(_)) | Err(crate::Error::SerializationError(_)) => return Ok(Some(i)),
+                Err(e) => return Err(e),
+            }
+        }
+        if accumulators.is_empty() {
+            return Ok(None);
         }
 
-        let pm = Self::try_pop(&mut second_sumcheck_messages)?;
-        fs_rng.feed_randomness(&pm)?;
-        let (vs, _) = MLProofForR1CS::verify_second_sumcheck_final_round(vs, pm, &mut fs_rng)?;
-
-        let pm = proof.prover_sixth_message;
-        fs_rng.feed_randomness(&pm)?;
-
-        let result = MLProofForR1CS::verify_sixth_round(vs, pm, vp)?;
-
-        Ok(result)
-    }
-
-    fn try_pop(sumcheck_messages: &mut LinkedList<ProverMsg<E::Fr>>) -> SResult<ProverMsg<E::Fr>> {
-        sumcheck_messages
-            .pop_front()
-            .ok_or(invalid_arg("malformed sumcheck message"))
+        let challenges: Vec<E::Fr> = batch_transcript.challenge_scalars(b"batch challenge", accumulators.len())?;
+        let mut batch = PairingAccumulator::new();
+        batch.merge_all(accumulators.iter().cloned().zip(challenges.into_iter()));
+        if batch.decide() {
+            return Ok(None);
+        }
+        // find the rejected opening
+        match accumulators.iter().position(|accumulator| !accumulator.decide()) {
+            Some(i) => Ok(Some(i)),
+            None => Err(crate::Error::WrongWitness(Some("batch of openings is rejected, but every opening is accepted".into()))),
+        }
     }
 }
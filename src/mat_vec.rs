@@ -0,0 +1,309 @@
+//! Argument that `y = M z` for a public sparse matrix `M` and a committed vector `z`.
+//!
+//! For a random `r_x`, `y(r_x) = sum over y M(r_x, y) z(y)`, which is proven by a sumcheck on `M(r_x, .) * z`.
+//! The sumcheck ends at a random `r_y`, where the verifier evaluates `M(r_x, r_y)` from the sparse entries and
+//! checks the opening of z. When y is committed as well, `y(r_x)` is opened instead of computed by the verifier.
+
+use ark_ec::PairingEngine;
+use ark_ff::{Field, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
+use linear_sumcheck::data_structures::ml_extension::{ArithmeticCombination, MLExtension};
+use linear_sumcheck::data_structures::MLExtensionArray;
+use digest::Digest;
+
+use crate::ahp::indexer::CircuitId;
+use crate::commitment::commit::Commitment;
+use crate::commitment::data_structures::PublicParameter;
+use crate::commitment::open::Proof;
+use crate::commitment::verify::OpeningVerifierKey;
+use crate::commitment::MLPolyCommit;
+use crate::data_structures::r1cs_reader::MatrixExtension;
+use crate::data_structures::transcript::{Transcript, TranscriptHash};
+use crate::error::{invalid_arg, SResult};
+use crate::sumcheck::{self, SumcheckProof};
+
+/// A public matrix with its digest. The digest is computed once, and only the digest is absorbed into the
+/// transcript.
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct MatVecIndex<F: Field> {
+    pub matrix: MatrixExtension<F>,
+    pub id: CircuitId,
+}
+
+impl<F: Field> MatVecIndex<F> {
+    /// Digest the matrix. The sumcheck runs over the columns, so the matrix should have at least two columns.
+    pub fn new(matrix: MatrixExtension<F>) -> SResult<Self> {
+        if matrix.num_variables < 2 {
+            return Err(invalid_arg("matrix should have at least two columns"));
+        }
+        let id = Self::compute_id(&matrix)?;
+        Ok(Self { matrix, id })
+    }
+
+    /// Compute the digest. Use it to check the identifier of an index from an untrusted source.
+    pub fn compute_id(matrix: &MatrixExtension<F>) -> SResult<CircuitId> {
+        let mut bytes = Vec::new();
+        b"r1cs-spartan-raw/mat-vec-index".to_vec().serialize(&mut bytes)?;
+        matrix.serialize(&mut bytes)?;
+        Ok(CircuitId(sha2::Sha256::digest(&bytes).to_vec()))
+    }
+}
+
+/// proof that `y(r_x) = sum over y M(r_x, y) z(y)`
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct MatVecProof<E: PairingEngine> {
+    pub sumcheck: SumcheckProof<E::Fr>,
+    pub z_ry: E::Fr,
+    pub proof_for_z: Proof<E>,
+}
+
+/// proof of the product when y is committed: the opening of y at r_x, and the proof of the product
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct CommittedMatVecProof<E: PairingEngine> {
+    pub y_rx: E::Fr,
+    pub proof_for_y: Proof<E>,
+    pub product: MatVecProof<E>,
+}
+
+fn log_size(n: usize) -> usize {
+    ark_std::log2(n) as usize
+}
+
+/// pad `vector` with zeros to `len` elements
+fn pad<F: Zero + Clone>(mut vector: Vec<F>, len: usize) -> SResult<Vec<F>> {
+    if vector.len() > len {
+        return Err(invalid_arg("vector is longer than the matrix allows"));
+    }
+    vector.resize(len, F::zero());
+    Ok(vector)
+}
+
+/// bind the digest of the matrix and the commitment to z
+fn append_statement<E: PairingEngine, H: TranscriptHash>(
+    index: &MatVecIndex<E::Fr>,
+    z_commitment: &Commitment<E>,
+    transcript: &mut Transcript<H>,
+) -> SResult<()> {
+    if z_commitment.nv != log_size(index.matrix.num_variables) {
+        return Err(invalid_arg("commitment to z has wrong number of variables"));
+    }
+    transcript.append_message(b"matrix", &index.id)?;
+    transcript.append_message(b"commitment to z", z_commitment)
+}
+
+/// Prove `y = M z`, where z is committed in `z_commitment` and y is public.
+///
+/// return: y, padded to the number of rows of M, and the proof
+pub fn prove<E: PairingEngine, H: TranscriptHash>(
+    pp: &PublicParameter<E>,
+    index: &MatVecIndex<E::Fr>,
+    z: Vec<E::Fr>,
+    z_commitment: &Commitment<E>,
+    transcript: &mut Transcript<H>,
+) -> SResult<(Vec<E::Fr>, MatVecProof<E>)> {
+    append_statement(index, z_commitment, transcript)?;
+    let matrix = &index.matrix;
+    let z = pad(z, matrix.num_variables)?;
+    let y = matrix.mul_vector(&z)?;
+    transcript.append_message(b"y", &y)?;
+    let r_x: Vec<E::Fr> = transcript.challenge_scalars(b"r_x", log_size(matrix.num_constraints))?;
+    let proof = prove_at(pp, index, z, &r_x, transcript)?;
+    Ok((y, proof))
+}
+
+/// Verify that `y = M z`, where z is committed in `z_commitment`.
+///
+/// `vp` can be the public `VerifierParameter` or a `DesignatedVerifierParameter`.
+pub fn verify<E: PairingEngine, K: OpeningVerifierKey<E>, H: TranscriptHash>(
+    vp: &K,
+    index: &MatVecIndex<E::Fr>,
+    y: Vec<E::Fr>,
+    z_commitment: &Commitment<E>,
+    proof: MatVecProof<E>,
+    transcript: &mut Transcript<H>,
+) -> SResult<bool> {
+    append_statement(index, z_commitment, transcript)?;
+    let matrix = &index.matrix;
+    let y = pad(y, matrix.num_constraints)?;
+    transcript.append_message(b"y", &y)?;
+    let r_x: Vec<E::Fr> = transcript.challenge_scalars(b"r_x", log_size(matrix.num_constraints))?;
+    let y_rx = MLExtensionArray::from_vec(y)?.eval_at(&r_x)?;
+    verify_at(vp, index, z_commitment, y_rx, &r_x, proof, transcript)
+}
+
+/// Prove `y = M z`, where z and y are committed in `z_commitment` and `y_commitment`.
+pub fn prove_committed<E: PairingEngine, H: TranscriptHash>(
+    pp: &PublicParameter<E>,
+    index: &MatVecIndex<E::Fr>,
+    z: Vec<E::Fr>,
+    z_commitment: &Commitment<E>,
+    y_commitment: &Commitment<E>,
+    transcript: &mut Transcript<H>,
+) -> SResult<CommittedMatVecProof<E>> {
+    append_statement(index, z_commitment, transcript)?;
+    let matrix = &index.matrix;
+    let log_m = log_size(matrix.num_constraints);
+    if y_commitment.nv != log_m {
+        return Err(invalid_arg("commitment to y has wrong number of variables"));
+    }
+    transcript.append_message(b"commitment to y", y_commitment)?;
+    let z = pad(z, matrix.num_variables)?;
+    let y = MLExtensionArray::from_vec(matrix.mul_vector(&z)?)?;
+    let r_x: Vec<E::Fr> = transcript.challenge_scalars(b"r_x", log_m)?;
+    let (y_rx, proof_for_y, _) = MLPolyCommit::open(pp, y, &r_x)?;
+    transcript.append_message(b"y(r_x)", &y_rx)?;
+    let product = prove_at(pp, index, z, &r_x, transcript)?;
+    Ok(CommittedMatVecProof {
+        y_rx,
+        proof_for_y,
+        product,
+    })
+}
+
+/// Verify that `y = M z`, where z and y are committed in `z_commitment` and `y_commitment`.
+pub fn verify_committed<E: PairingEngine, K: OpeningVerifierKey<E>, H: TranscriptHash>(
+    vp: &K,
+    index: &MatVecIndex<E::Fr>,
+    z_commitment: &Commitment<E>,
+    y_commitment: &Commitment<E>,
+    proof: CommittedMatVecProof<E>,
+    transcript: &mut Transcript<H>,
+) -> SResult<bool> {
+    append_statement(index, z_commitment, transcript)?;
+    let matrix = &index.matrix;
+    let log_m = log_size(matrix.num_constraints);
+    if y_commitment.nv != log_m {
+        return Err(invalid_arg("commitment to y has wrong number of variables"));
+    }
+    transcript.append_message(b"commitment to y", y_commitment)?;
+    let r_x: Vec<E::Fr> = transcript.challenge_scalars(b"r_x", log_m)?;
+    if !vp.verify_opening(y_commitment, &r_x, proof.y_rx, proof.proof_for_y)? {
+        return Err(crate::Error::WrongWitness(Some("cannot verify y(r_x)".into())));
+    }
+    transcript.append_message(b"y(r_x)", &proof.y_rx)?;
+    verify_at(vp, index, z_commitment, proof.y_rx, &r_x, proof.product, transcript)
+}
+
+/// prove `y(r_x) = sum over y M(r_x, y) z(y)` by a sumcheck, and open z at the final point
+fn prove_at<E: PairingEngine, H: TranscriptHash>(
+    pp: &PublicParameter<E>,
+    index: &MatVecIndex<E::Fr>,
+    z: Vec<E::Fr>,
+    r_x: &[E::Fr],
+    transcript: &mut Transcript<H>,
+) -> SResult<MatVecProof<E>> {
+    let timer = start_timer!(|| "Prove matrix-vector product");
+    let matrix = &index.matrix;
+    let z = MLExtensionArray::from_vec(z)?;
+    let mut poly = ArithmeticCombination::new(log_size(matrix.num_variables));
+    poly.add_product(vec![matrix.eval_on_x(r_x)?, z.clone()].into_iter())?;
+    let (sumcheck, r_y) = sumcheck::prove(poly, transcript)?;
+    let (z_ry, proof_for_z, _) = MLPolyCommit::open(pp, z, &r_y)?;
+    end_timer!(timer);
+    Ok(MatVecProof {
+        sumcheck,
+        z_ry,
+        proof_for_z,
+    })
+}
+
+fn verify_at<E: PairingEngine, K: OpeningVerifierKey<E>, H: TranscriptHash>(
+    vp: &K,
+    index: &MatVecIndex<E::Fr>,
+    z_commitment: &Commitment<E>,
+    y_rx: E::Fr,
+    r_x: &[E::Fr],
+    proof: MatVecProof<E>,
+    transcript: &mut Transcript<H>,
+) -> SResult<bool> {
+    let matrix = &index.matrix;
+    let (r_y, expected) = sumcheck::verify(proof.sumcheck, log_size(matrix.num_variables), 2, y_rx, transcript)?;
+    if matrix.eval_at(r_x, &r_y)? * &proof.z_ry != expected {
+        return Err(crate::Error::WrongWitness(Some(
+            "matrix-vector product has wrong subclaim".into(),
+        )));
+    }
+    if !vp.verify_opening(z_commitment, &r_y, proof.z_ry, proof.proof_for_z)? {
+        return Err(crate::Error::WrongWitness(Some("cannot verify z(r_y)".into())));
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commitment::MLPolyCommit;
+    use crate::data_structures::r1cs_reader::MatrixExtension;
+    use crate::data_structures::transcript::Transcript;
+    use crate::mat_vec::{self, MatVecIndex};
+    use crate::test_utils::TestCurve;
+    use ark_ec::PairingEngine;
+    use ark_ff::{test_rng, One, UniformRand};
+    use rand::RngCore;
+    use linear_sumcheck::data_structures::MLExtensionArray;
+
+    type E = TestCurve;
+    type Fr = <E as PairingEngine>::Fr;
+
+    #[test]
+    fn test_mat_vec() {
+        let mut rng = test_rng();
+        let (m, n) = (24, 60);
+        let matrix: Vec<Vec<(Fr, usize)>> = (0..m)
+            .map(|_| (0..5).map(|_| (Fr::rand(&mut rng), (rng.next_u64() % n as u64) as usize)).collect())
+            .collect();
+        let matrix = MatrixExtension::new(matrix, m, n).unwrap();
+        let index = MatVecIndex::new(matrix.clone()).unwrap();
+        let (pp, vp, _) = MLPolyCommit::<E>::keygen(6, &mut rng).unwrap();
+        let mut z: Vec<Fr> = (0..n).map(|_| Fr::rand(&mut rng)).collect();
+        z.resize(64, Fr::from(0u64));
+        let z_commitment = MLPolyCommit::commit(&pp, MLExtensionArray::from_slice(&z).unwrap()).unwrap();
+
+        // public y
+        let mut transcript: Transcript = Transcript::new(b"test");
+        let (y, proof) = mat_vec::prove(&pp, &index, z.clone(), &z_commitment, &mut transcript).unwrap();
+        assert_eq!(y, matrix.mul_vector(&z).unwrap());
+        let mut transcript: Transcript = Transcript::new(b"test");
+        assert!(mat_vec::verify(&vp, &index, y.clone(), &z_commitment, proof, &mut transcript).unwrap());
+
+        let mut wrong_y = y.clone();
+        wrong_y[3] += Fr::one();
+        let mut transcript: Transcript = Transcript::new(b"test");
+        let (_, proof) = mat_vec::prove(&pp, &index, z.clone(), &z_commitment, &mut transcript).unwrap();
+        let mut transcript: Transcript = Transcript::new(b"test");
+        assert!(mat_vec::verify(&vp, &index, wrong_y.clone(), &z_commitment, proof, &mut transcript).is_err());
+
+        // committed y
+        let commit_y = |y: Vec<Fr>| MLPolyCommit::commit(&pp, MLExtensionArray::from_vec(y).unwrap()).unwrap();
+        let y_commitment = commit_y(y);
+        let mut transcript: Transcript = Transcript::new(b"test");
+        let proof = mat_vec::prove_committed(&pp, &index, z.clone(), &z_commitment, &y_commitment, &mut transcript).unwrap();
+        let mut transcript: Transcript = Transcript::new(b"test");
+        assert!(mat_vec::verify_committed(&vp, &index, &z_commitment, &y_commitment, proof, &mut transcript).unwrap());
+
+        let wrong_commitment = commit_y(wrong_y);
+        let mut transcript: Transcript = Transcript::new(b"test");
+        let proof = mat_vec::prove_committed(&pp, &index, z, &z_commitment, &wrong_commitment, &mut transcript).unwrap();
+        let mut transcript: Transcript = Transcript::new(b"test");
+        assert!(mat_vec::verify_committed(&vp, &index, &z_commitment, &wrong_commitment, proof, &mut transcript).is_err());
+    }
+
+    #[test]
+    fn test_single_column() {
+        let mut rng = test_rng();
+        let matrix: Vec<Vec<(Fr, usize)>> = (0..4).map(|_| vec![(Fr::rand(&mut rng), 0)]).collect();
+        let matrix = MatrixExtension::new(matrix, 4, 1).unwrap();
+        assert_eq!(matrix.num_variables, 1);
+        assert!(MatVecIndex::new(matrix).is_err());
+
+        // padded with a zero column, the product can be proven
+        let matrix: Vec<Vec<(Fr, usize)>> = (0..4).map(|_| vec![(Fr::rand(&mut rng), 0)]).collect();
+        let index = MatVecIndex::new(MatrixExtension::new(matrix, 4, 2).unwrap()).unwrap();
+        let (pp, vp, _) = MLPolyCommit::<E>::keygen(1, &mut rng).unwrap();
+        let z = vec![Fr::rand(&mut rng), Fr::from(0u64)];
+        let z_commitment = MLPolyCommit::commit(&pp, MLExtensionArray::from_slice(&z).unwrap()).unwrap();
+        let mut transcript: Transcript = Transcript::new(b"test");
+        let (y, proof) = mat_vec::prove(&pp, &index, z, &z_commitment, &mut transcript).unwrap();
+        let mut transcript: Transcript = Transcript::new(b"test");
+        assert!(mat_vec::verify(&vp, &index, y, &z_commitment, proof, &mut transcript).unwrap());
+    }
+}
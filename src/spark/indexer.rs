@@ -0,0 +1,210 @@
+use ark_ec::PairingEngine;
+use ark_ff::Field;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
+use linear_sumcheck::data_structures::MLExtensionArray;
+
+use crate::commitment::commit::Commitment;
+use crate::commitment::data_structures::PublicParameter;
+use crate::commitment::MLPolyCommit;
+use crate::data_structures::r1cs_reader::MatrixExtension;
+use crate::error::{invalid_arg, SResult};
+use crate::spark::{to_field_mle, MLSpark};
+
+/// Sparse representation of one matrix. Entries are padded with zeros at (0, 0) so that
+/// there are `2^log_nnz` of them.
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct SparseMatrixPolynomials<F: Field> {
+    pub row: Vec<usize>,
+    pub col: Vec<usize>,
+    pub val: Vec<F>,
+    /// timestamp of each read of `row` in memory checking
+    pub read_ts_row: Vec<usize>,
+    /// timestamp of each read of `col` in memory checking
+    pub read_ts_col: Vec<usize>,
+    /// number of reads of each row
+    pub final_ts_row: Vec<usize>,
+    /// number of reads of each column
+    pub final_ts_col: Vec<usize>,
+}
+
+/// commitments to the sparse representation of one matrix
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct SparseMatrixCommitment<E: PairingEngine> {
+    pub row: Commitment<E>,
+    pub col: Commitment<E>,
+    pub val: Commitment<E>,
+    pub read_ts_row: Commitment<E>,
+    pub read_ts_col: Commitment<E>,
+    pub final_ts_row: Commitment<E>,
+    pub final_ts_col: Commitment<E>,
+}
+
+/// Verifier's key: commitments to the matrices
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct SparkVK<E: PairingEngine> {
+    /// log(number of rows)
+    pub log_m: usize,
+    /// log(number of columns)
+    pub log_n: usize,
+    /// log(number of entries after padding)
+    pub log_nnz: usize,
+    pub matrices: Vec<SparseMatrixCommitment<E>>,
+}
+
+/// Prover's key
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct SparkPK<E: PairingEngine> {
+    pub matrices: Vec<SparseMatrixPolynomials<E::Fr>>,
+    pub vk: SparkVK<E>,
+}
+
+impl<E: PairingEngine> Clone for SparseMatrixCommitment<E> {
+    fn clone(&self) -> Self {
+        Self {
+            row: self.row.clone(),
+            col: self.col.clone(),
+            val: self.val.clone(),
+            read_ts_row: self.read_ts_row.clone(),
+            read_ts_col: self.read_ts_col.clone(),
+            final_ts_row: self.final_ts_row.clone(),
+            final_ts_col: self.final_ts_col.clone(),
+        }
+    }
+}
+
+impl<E: PairingEngine> Clone for SparkVK<E> {
+    fn clone(&self) -> Self {
+        Self {
+            log_m: self.log_m,
+            log_n: self.log_n,
+            log_nnz: self.log_nnz,
+            matrices: self.matrices.clone(),
+        }
+    }
+}
+
+impl<E: PairingEngine> SparkPK<E> {
+    pub fn vk(&self) -> SparkVK<E> {
+        self.vk.clone()
+    }
+}
+
+/// timestamps of reading `addrs` in order from a memory of size `memory_size`
+///
+/// return: (timestamp of each read, number of reads of each address)
+fn memory_timestamps(addrs: &[usize], memory_size: usize) -> (Vec<usize>, Vec<usize>) {
+    let mut final_ts = vec![0; memory_size];
+    let read_ts = addrs
+        .iter()
+        .map(|&addr| {
+            let ts = final_ts[addr];
+            final_ts[addr] += 1;
+            ts
+        })
+        .collect();
+    (read_ts, final_ts)
+}
+
+impl<F: Field> SparseMatrixPolynomials<F> {
+    fn new(matrix: &MatrixExtension<F>, log_m: usize, log_n: usize, log_nnz: usize) -> SResult<Self> {
+        let nnz = 1 << log_nnz;
+        let mut row = Vec::with_capacity(nnz);
+        let mut col = Vec::with_capacity(nnz);
+        let mut val = Vec::with_capacity(nnz);
+        for (x, y, v) in matrix.non_zero_entries() {
+            if x >= (1 << log_m) || y >= (1 << log_n) {
+                return Err(invalid_arg("sparse index out of bound"));
+            }
+            row.push(x);
+            col.push(y);
+            val.push(v);
+        }
+        if row.len() > nnz {
+            return Err(invalid_arg("too many non-zero entries"));
+        }
+        row.resize(nnz, 0);
+        col.resize(nnz, 0);
+        val.resize(nnz, F::zero());
+
+        let (read_ts_row, final_ts_row) = memory_timestamps(&row, 1 << log_m);
+        let (read_ts_col, final_ts_col) = memory_timestamps(&col, 1 << log_n);
+        Ok(Self {
+            row,
+            col,
+            val,
+            read_ts_row,
+            read_ts_col,
+            final_ts_row,
+            final_ts_col,
+        })
+    }
+
+    /// number of entries after padding
+    pub fn nnz(&self) -> usize {
+        self.val.len()
+    }
+
+    pub fn val_mle(&self) -> SResult<MLExtensionArray<F>> {
+        Ok(MLExtensionArray::from_slice(&self.val)?)
+    }
+}
+
+impl<E: PairingEngine> MLSpark<E> {
+    /// number of variables the public parameter should support for matrices of `2^log_m` rows,
+    /// `2^log_n` columns and at most `max_nnz` non-zero entries
+    pub fn num_variables_for_setup(log_m: usize, log_n: usize, max_nnz: usize) -> usize {
+        let log_nnz = Self::log_nnz(max_nnz);
+        ark_std::cmp::max(ark_std::cmp::max(log_m, log_n), log_nnz) + 1
+    }
+
+    #[inline]
+    fn log_nnz(max_nnz: usize) -> usize {
+        ark_std::cmp::max(ark_std::log2(max_nnz) as usize, 1)
+    }
+
+    /// Commit to the sparse matrices with `2^log_m` rows and `2^log_n` columns.
+    pub fn index(
+        matrices: &[&MatrixExtension<E::Fr>],
+        log_m: usize,
+        log_n: usize,
+        pp: &PublicParameter<E>,
+    ) -> SResult<SparkPK<E>> {
+        if matrices.is_empty() || log_m == 0 || log_n == 0 {
+            return Err(invalid_arg("spark needs at least one matrix with at least two rows and columns"));
+        }
+        let max_nnz = matrices.iter().map(|m| m.num_non_zero()).max().unwrap();
+        let log_nnz = Self::log_nnz(max_nnz);
+        if pp.nv < Self::num_variables_for_setup(log_m, log_n, max_nnz) {
+            return Err(invalid_arg("public parameter does not support this many variables"));
+        }
+
+        let polynomials = matrices
+            .iter()
+            .map(|m| SparseMatrixPolynomials::new(m, log_m, log_n, log_nnz))
+            .collect::<SResult<Vec<_>>>()?;
+        let commitments = polynomials
+            .iter()
+            .map(|p| -> SResult<SparseMatrixCommitment<E>> {
+                Ok(SparseMatrixCommitment {
+                    row: MLPolyCommit::commit(pp, to_field_mle(&p.row)?)?,
+                    col: MLPolyCommit::commit(pp, to_field_mle(&p.col)?)?,
+                    val: MLPolyCommit::commit(pp, p.val_mle()?)?,
+                    read_ts_row: MLPolyCommit::commit(pp, to_field_mle(&p.read_ts_row)?)?,
+                    read_ts_col: MLPolyCommit::commit(pp, to_field_mle(&p.read_ts_col)?)?,
+                    final_ts_row: MLPolyCommit::commit(pp, to_field_mle(&p.final_ts_row)?)?,
+                    final_ts_col: MLPolyCommit::commit(pp, to_field_mle(&p.final_ts_col)?)?,
+                })
+            })
+            .collect::<SResult<Vec<_>>>()?;
+
+        Ok(SparkPK {
+            matrices: polynomials,
+            vk: SparkVK {
+                log_m,
+                log_n,
+                log_nnz,
+                matrices: commitments,
+            },
+        })
+    }
+}
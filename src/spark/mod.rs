@@ -0,0 +1,49 @@
+//! Spark: commitments to sparse matrices, and proofs of their multilinear extensions at a point.
+//!
+//! A matrix `M` with `nnz` entries is represented as `row(k)`, `col(k)`, `val(k)` so that
+//! `M(r_x, r_y) = sum_k val(k) * eq(r_x, row(k)) * eq(r_y, col(k))`. The prover commits to
+//! `e_rx(k) = eq(r_x, row(k))` and `e_ry(k) = eq(r_y, col(k))`, and offline memory checking shows that
+//! they are read from the memories `eq(r_x, .)` and `eq(r_y, .)`.
+
+use ark_ec::PairingEngine;
+use ark_ff::Field;
+use ark_std::marker::PhantomData;
+use linear_sumcheck::data_structures::MLExtensionArray;
+
+use crate::error::SResult;
+
+pub mod indexer;
+pub mod prover;
+pub mod verifier;
+
+#[cfg(test)]
+mod tests;
+
+pub struct MLSpark<E: PairingEngine>(#[doc(hidden)] PhantomData<E>);
+
+/// label of the evaluations of the final timestamps in the transcript, absorbed by both the prover and the verifier
+pub(crate) const FINAL_TIMESTAMP_EVALUATIONS_LABEL: &[u8] = b"final timestamp evaluations";
+
+/// multilinear extension of a table of addresses or timestamps
+pub(crate) fn to_field_mle<F: Field>(values: &[usize]) -> SResult<MLExtensionArray<F>> {
+    Ok(MLExtensionArray::from_vec(
+        values.iter().map(|x| F::from(*x as u64)).collect(),
+    )?)
+}
+
+/// fingerprint of (address, value, timestamp) used by memory checking
+#[inline]
+pub(crate) fn memory_hash<F: Field>(addr: F, val: F, ts: F, gamma: F, tau: F) -> F {
+    (addr * &gamma + &val) * &gamma + &ts - &tau
+}
+
+/// evaluate the multilinear extension of `x -> x` at `point`
+pub(crate) fn identity_eval<F: Field>(point: &[F]) -> F {
+    let mut result = F::zero();
+    let mut coefficient = F::one();
+    for x in point {
+        result += coefficient * x;
+        coefficient.double_in_place();
+    }
+    result
+}
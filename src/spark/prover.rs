@@ -0,0 +1,250 @@
+use ark_ec::PairingEngine;
+use ark_ff::{One, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
+use linear_sumcheck::data_structures::ml_extension::{ArithmeticCombination, MLExtension};
+use linear_sumcheck::data_structures::MLExtensionArray;
+
+use crate::commitment::commit::Commitment;
+use crate::commitment::data_structures::PublicParameter;
+use crate::commitment::open::Proof;
+use crate::commitment::MLPolyCommit;
+use crate::data_structures::eq::eq_table;
+use crate::data_structures::transcript::{Transcript, TranscriptHash};
+use crate::error::{invalid_arg, SResult};
+use crate::grand_product::{self, GrandProductProof};
+use crate::spark::indexer::SparkPK;
+use crate::spark::{memory_hash, to_field_mle, MLSpark, FINAL_TIMESTAMP_EVALUATIONS_LABEL};
+use crate::sumcheck::{self, SumcheckProof};
+
+/// proof that the committed matrices evaluate to `evaluations` at (r_x, r_y)
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct SparkProof<E: PairingEngine> {
+    /// M_i(r_x, r_y) for each matrix
+    pub evaluations: Vec<E::Fr>,
+    /// commitments to e_rx_i and e_ry_i, as [e_rx_0, e_ry_0, e_rx_1, e_ry_1, ...]
+    pub e_commitments: Vec<Commitment<E>>,
+    pub sumcheck: SumcheckProof<E::Fr>,
+    /// val_i, e_rx_i, e_ry_i at the sumcheck point
+    pub sumcheck_evaluations: Vec<E::Fr>,
+    pub proof_for_sumcheck_evaluations: Proof<E>,
+    /// products of read sets and write sets, as [rs_row_0, ws_row_0, rs_col_0, ws_col_0, rs_row_1, ...]
+    pub read_write_products: GrandProductProof<E>,
+    /// row_i, e_rx_i, read_ts_row_i, col_i, e_ry_i, read_ts_col_i at the grand product point
+    pub read_write_evaluations: Vec<E::Fr>,
+    pub proof_for_read_write_evaluations: Proof<E>,
+    /// products of init set and audit sets of row memory, as [init, audit_0, audit_1, ...]
+    pub row_audit_products: GrandProductProof<E>,
+    /// final_ts_row_i at the grand product point
+    pub row_audit_evaluations: Vec<E::Fr>,
+    pub proof_for_row_audit_evaluations: Proof<E>,
+    /// products of init set and audit sets of column memory
+    pub col_audit_products: GrandProductProof<E>,
+    /// final_ts_col_i at the grand product point
+    pub col_audit_evaluations: Vec<E::Fr>,
+    pub proof_for_col_audit_evaluations: Proof<E>,
+}
+
+impl<E: PairingEngine> MLSpark<E> {
+    /// Prove the evaluations of the committed matrices at (r_x, r_y).
+    pub fn prove<H: TranscriptHash>(
+        pp: &PublicParameter<E>,
+        pk: &SparkPK<E>,
+        r_x: &[E::Fr],
+        r_y: &[E::Fr],
+        transcript: &mut Transcript<H>,
+    ) -> SResult<SparkProof<E>> {
+        let vk = &pk.vk;
+        if r_x.len() != vk.log_m || r_y.len() != vk.log_n {
+            return Err(invalid_arg("evaluation point has wrong dimension"));
+        }
+        let eq_rx = eq_table(r_x);
+        let eq_ry = eq_table(r_y);
+
+        let timer = start_timer!(|| "commit e_rx, e_ry");
+        let mut evaluations: Vec<E::Fr> = Vec::with_capacity(pk.matrices.len());
+        let mut e_rx = Vec::with_capacity(pk.matrices.len());
+        let mut e_ry = Vec::with_capacity(pk.matrices.len());
+        let mut e_commitments = Vec::with_capacity(pk.matrices.len() << 1);
+        for m in pk.matrices.iter() {
+            let erx: Vec<_> = m.row.iter().map(|&x| eq_rx[x]).collect();
+            let ery: Vec<_> = m.col.iter().map(|&y| eq_ry[y]).collect();
+            evaluations.push(
+                m.val
+                    .iter()
+                    .zip(erx.iter().zip(ery.iter()))
+                    .map(|(v, (a, b))| *v * a * b)
+                    .sum(),
+            );
+            let erx = MLExtensionArray::from_vec(erx)?;
+            let ery = MLExtensionArray::from_vec(ery)?;
+            e_commitments.push(MLPolyCommit::commit(pp, erx.clone())?);
+            e_commitments.push(MLPolyCommit::commit(pp, ery.clone())?);
+            e_rx.push(erx);
+            e_ry.push(ery);
+        }
+        end_timer!(timer);
+        transcript.append_scalars(b"matrix evaluations", &evaluations)?;
+        transcript.append_message(b"e commitments", &e_commitments)?;
+
+        // sum over k: sum_i lambda^i val_i(k) e_rx_i(k) e_ry_i(k) = sum_i lambda^i M_i(r_x, r_y)
+        let timer = start_timer!(|| "sumcheck");
+        let lambda: E::Fr = transcript.challenge_scalar(b"lambda")?;
+        let vals = pk
+            .matrices
+            .iter()
+            .map(|m| m.val_mle())
+            .collect::<SResult<Vec<_>>>()?;
+        let mut poly = ArithmeticCombination::new(vk.log_nnz);
+        let mut coefficient = E::Fr::one();
+        for i in 0..pk.matrices.len() {
+            poly.add_product(
+                vec![vals[i].multiply(coefficient)?, e_rx[i].clone(), e_ry[i].clone()].into_iter(),
+            )?;
+            coefficient *= lambda;
+        }
+        let (sumcheck, r_k) = sumcheck::prove(poly, transcript)?;
+        let mut sumcheck_polys = Vec::with_capacity(pk.matrices.len() * 3);
+        for ((val, erx), ery) in vals.into_iter().zip(e_rx.iter()).zip(e_ry.iter()) {
+            sumcheck_polys.push(val);
+            sumcheck_polys.push(erx.clone());
+            sumcheck_polys.push(ery.clone());
+        }
+        let sumcheck_evaluations = Self::evaluate_all(&sumcheck_polys, &r_k)?;
+        transcript.append_scalars(b"sumcheck evaluations", &sumcheck_evaluations)?;
+        let mu: E::Fr = transcript.challenge_scalar(b"mu")?;
+        let proof_for_sumcheck_evaluations =
+            MLPolyCommit::batch_open(pp, sumcheck_polys, &r_k, mu)?;
+        end_timer!(timer);
+
+        // offline memory checking
+        let timer = start_timer!(|| "memory checking");
+        let gamma: E::Fr = transcript.challenge_scalar(b"gamma")?;
+        let tau: E::Fr = transcript.challenge_scalar(b"tau")?;
+        let hash = |addr: usize, val: E::Fr, ts: usize| {
+            memory_hash(
+                E::Fr::from(addr as u64),
+                val,
+                E::Fr::from(ts as u64),
+                gamma,
+                tau,
+            )
+        };
+        let mut read_write_leaves = Vec::with_capacity(pk.matrices.len() << 2);
+        for m in pk.matrices.iter() {
+            let rs_row: Vec<_> = m
+                .row
+                .iter()
+                .zip(m.read_ts_row.iter())
+                .map(|(&x, &ts)| hash(x, eq_rx[x], ts))
+                .collect();
+            let ws_row: Vec<_> = rs_row.iter().map(|h| *h + E::Fr::one()).collect();
+            let rs_col: Vec<_> = m
+                .col
+                .iter()
+                .zip(m.read_ts_col.iter())
+                .map(|(&y, &ts)| hash(y, eq_ry[y], ts))
+                .collect();
+            let ws_col: Vec<_> = rs_col.iter().map(|h| *h + E::Fr::one()).collect();
+            read_write_leaves.push(rs_row);
+            read_write_leaves.push(ws_row);
+            read_write_leaves.push(rs_col);
+            read_write_leaves.push(ws_col);
+        }
+        let (read_write_products, r_rw) = grand_product::prove(pp, read_write_leaves, transcript)?;
+        let mut read_write_polys: Vec<MLExtensionArray<E::Fr>> = Vec::with_capacity(pk.matrices.len() * 6);
+        for ((m, erx), ery) in pk.matrices.iter().zip(e_rx.into_iter()).zip(e_ry.into_iter()) {
+            read_write_polys.push(to_field_mle(&m.row)?);
+            read_write_polys.push(erx);
+            read_write_polys.push(to_field_mle(&m.read_ts_row)?);
+            read_write_polys.push(to_field_mle(&m.col)?);
+            read_write_polys.push(ery);
+            read_write_polys.push(to_field_mle(&m.read_ts_col)?);
+        }
+        let read_write_evaluations = Self::evaluate_all(&read_write_polys, &r_rw)?;
+        transcript.append_scalars(b"read write evaluations", &read_write_evaluations)?;
+        let mu: E::Fr = transcript.challenge_scalar(b"mu")?;
+        let proof_for_read_write_evaluations =
+            MLPolyCommit::batch_open(pp, read_write_polys, &r_rw, mu)?;
+
+        let (row_audit_products, row_audit_evaluations, proof_for_row_audit_evaluations) =
+            Self::prove_audit(
+                pp,
+                &eq_rx,
+                pk.matrices.iter().map(|m| &m.final_ts_row[..]).collect(),
+                gamma,
+                tau,
+                transcript,
+            )?;
+        let (col_audit_products, col_audit_evaluations, proof_for_col_audit_evaluations) =
+            Self::prove_audit(
+                pp,
+                &eq_ry,
+                pk.matrices.iter().map(|m| &m.final_ts_col[..]).collect(),
+                gamma,
+                tau,
+                transcript,
+            )?;
+        end_timer!(timer);
+
+        Ok(SparkProof {
+            evaluations,
+            e_commitments,
+            sumcheck,
+            sumcheck_evaluations,
+            proof_for_sumcheck_evaluations,
+            read_write_products,
+            read_write_evaluations,
+            proof_for_read_write_evaluations,
+            row_audit_products,
+            row_audit_evaluations,
+            proof_for_row_audit_evaluations,
+            col_audit_products,
+            col_audit_evaluations,
+            proof_for_col_audit_evaluations,
+        })
+    }
+
+    /// grand products of the init set and audit sets of memory `eq`, and the final timestamps at the grand product point
+    fn prove_audit<H: TranscriptHash>(
+        pp: &PublicParameter<E>,
+        eq: &[E::Fr],
+        final_ts: Vec<&[usize]>,
+        gamma: E::Fr,
+        tau: E::Fr,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<(GrandProductProof<E>, Vec<E::Fr>, Proof<E>)> {
+        let init: Vec<_> = eq
+            .iter()
+            .enumerate()
+            .map(|(addr, val)| memory_hash(E::Fr::from(addr as u64), *val, E::Fr::zero(), gamma, tau))
+            .collect();
+        let mut leaves: Vec<Vec<E::Fr>> = Vec::with_capacity(final_ts.len() + 1);
+        for ts in final_ts.iter() {
+            leaves.push(
+                init.iter()
+                    .zip(ts.iter())
+                    .map(|(h, &t)| *h + E::Fr::from(t as u64))
+                    .collect(),
+            );
+        }
+        leaves.insert(0, init);
+        let (products, r) = grand_product::prove(pp, leaves, transcript)?;
+
+        let polys: Vec<MLExtensionArray<E::Fr>> = final_ts
+            .iter()
+            .map(|ts| to_field_mle(ts))
+            .collect::<SResult<Vec<_>>>()?;
+        let evaluations = Self::evaluate_all(&polys, &r)?;
+        transcript.append_scalars(FINAL_TIMESTAMP_EVALUATIONS_LABEL, &evaluations)?;
+        let mu: E::Fr = transcript.challenge_scalar(b"mu")?;
+        let proof = MLPolyCommit::batch_open(pp, polys, &r, mu)?;
+        Ok((products, evaluations, proof))
+    }
+
+    fn evaluate_all(polys: &[MLExtensionArray<E::Fr>], point: &[E::Fr]) -> SResult<Vec<E::Fr>> {
+        Ok(polys
+            .iter()
+            .map(|p| p.eval_at(point))
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+}
@@ -0,0 +1,35 @@
+use crate::data_structures::r1cs_reader::MatrixExtension;
+use crate::spark::MLSpark;
+use crate::test_utils::{random_matrix, TestCurve, TestCurveFr};
+use crate::commitment::MLPolyCommit;
+use ark_ff::{test_rng, One, UniformRand};
+use crate::data_structures::transcript::Transcript;
+use linear_sumcheck::data_structures::Blake2s512Rng;
+
+#[test]
+fn test_spark_evaluation() {
+    let mut rng = test_rng();
+    let log_n = 6;
+    let matrices: Vec<_> = [100, 37, 250]
+        .iter()
+        .map(|&nnz| MatrixExtension::new(random_matrix(log_n, nnz, &mut rng), 1 << log_n, 1 << log_n).unwrap())
+        .collect();
+    let max_nnz = matrices.iter().map(|m| m.num_non_zero()).max().unwrap();
+    let nv = MLSpark::<TestCurve>::num_variables_for_setup(log_n, log_n, max_nnz);
+    let (pp, vp, _) = MLPolyCommit::<TestCurve>::keygen(nv, &mut rng).unwrap();
+    let pk = MLSpark::index(&matrices.iter().collect::<Vec<_>>(), log_n, log_n, &pp).unwrap();
+    let vk = pk.vk();
+
+    let r_x: Vec<_> = (0..log_n).map(|_| TestCurveFr::rand(&mut rng)).collect();
+    let r_y: Vec<_> = (0..log_n).map(|_| TestCurveFr::rand(&mut rng)).collect();
+    let proof = MLSpark::prove(&pp, &pk, &r_x, &r_y, &mut Transcript::<Blake2s512Rng>::new(b"spark test")).unwrap();
+    for (m, eval) in matrices.iter().zip(proof.evaluations.iter()) {
+        assert_eq!(m.eval_at(&r_x, &r_y).unwrap(), *eval);
+    }
+    assert!(MLSpark::verify(&vp, &vk, &r_x, &r_y, proof, &mut Transcript::<Blake2s512Rng>::new(b"spark test")).unwrap());
+
+    // wrong evaluation should be rejected
+    let mut proof = MLSpark::prove(&pp, &pk, &r_x, &r_y, &mut Transcript::<Blake2s512Rng>::new(b"spark test")).unwrap();
+    proof.evaluations[1] += TestCurveFr::one();
+    assert!(MLSpark::verify(&vp, &vk, &r_x, &r_y, proof, &mut Transcript::<Blake2s512Rng>::new(b"spark test")).is_err());
+}
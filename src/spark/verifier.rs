@@ -0,0 +1,217 @@
+use ark_ec::PairingEngine;
+use ark_ff::{One, Zero};
+
+use crate::commitment::commit::Commitment;
+use crate::commitment::open::Proof;
+use crate::commitment::verify::OpeningVerifierKey;
+use crate::commitment::MLPolyCommit;
+use crate::data_structures::eq::eq_eval;
+use crate::data_structures::transcript::{Transcript, TranscriptHash};
+use crate::error::{invalid_arg, SResult};
+use crate::grand_product::{self, GrandProductProof};
+use crate::spark::indexer::SparkVK;
+use crate::spark::prover::SparkProof;
+use crate::spark::{identity_eval, memory_hash, MLSpark, FINAL_TIMESTAMP_EVALUATIONS_LABEL};
+use crate::sumcheck;
+
+impl<E: PairingEngine> MLSpark<E> {
+    /// Verify that the committed matrices evaluate to `proof.evaluations` at (r_x, r_y).
+    pub fn verify<K: OpeningVerifierKey<E>, H: TranscriptHash>(
+        vp: &K,
+        vk: &SparkVK<E>,
+        r_x: &[E::Fr],
+        r_y: &[E::Fr],
+        proof: SparkProof<E>,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<bool> {
+        let n = vk.matrices.len();
+        if r_x.len() != vk.log_m || r_y.len() != vk.log_n {
+            return Err(invalid_arg("evaluation point has wrong dimension"));
+        }
+        if proof.evaluations.len() != n
+            || proof.e_commitments.len() != n << 1
+            || proof.sumcheck_evaluations.len() != n * 3
+            || proof.read_write_evaluations.len() != n * 6
+            || proof.row_audit_evaluations.len() != n
+            || proof.col_audit_evaluations.len() != n
+            || proof.e_commitments.iter().any(|c| c.nv != vk.log_nnz)
+        {
+            return Err(invalid_arg("malformed spark proof"));
+        }
+        transcript.append_scalars(b"matrix evaluations", &proof.evaluations)?;
+        transcript.append_message(b"e commitments", &proof.e_commitments)?;
+
+        // verify sumcheck
+        let lambda: E::Fr = transcript.challenge_scalar(b"lambda")?;
+        let mut claimed_sum = E::Fr::zero();
+        let mut coefficient = E::Fr::one();
+        for eval in proof.evaluations.iter() {
+            claimed_sum += coefficient * eval;
+            coefficient *= lambda;
+        }
+        let (r_k, expected) = sumcheck::verify(proof.sumcheck, vk.log_nnz, 3, claimed_sum, transcript)?;
+        let mut actual = E::Fr::zero();
+        let mut coefficient = E::Fr::one();
+        for ev in proof.sumcheck_evaluations.chunks(3) {
+            actual += coefficient * ev[0] * ev[1] * ev[2];
+            coefficient *= lambda;
+        }
+        if actual != expected {
+            return Err(crate::Error::WrongWitness(Some(
+                "spark sumcheck has wrong subclaim".into(),
+            )));
+        }
+        transcript.append_scalars(b"sumcheck evaluations", &proof.sumcheck_evaluations)?;
+        let mu: E::Fr = transcript.challenge_scalar(b"mu")?;
+        let mut commitments = Vec::with_capacity(n * 3);
+        for (i, m) in vk.matrices.iter().enumerate() {
+            commitments.push(&m.val);
+            commitments.push(&proof.e_commitments[i << 1]);
+            commitments.push(&proof.e_commitments[(i << 1) + 1]);
+        }
+        if !MLPolyCommit::batch_verify(
+            vp,
+            &commitments,
+            &r_k,
+            &proof.sumcheck_evaluations,
+            mu,
+            proof.proof_for_sumcheck_evaluations,
+        )? {
+            return Err(crate::Error::WrongWitness(Some(
+                "cannot verify val, e_rx, e_ry".into(),
+            )));
+        }
+
+        // offline memory checking
+        let gamma: E::Fr = transcript.challenge_scalar(b"gamma")?;
+        let tau: E::Fr = transcript.challenge_scalar(b"tau")?;
+        let (read_write_products, r_rw, read_write_leaves) =
+            grand_product::verify(vp, proof.read_write_products, vk.log_nnz, transcript)?;
+        if read_write_products.len() != n << 2 {
+            return Err(invalid_arg("malformed spark proof"));
+        }
+        for (ev, leaves) in proof
+            .read_write_evaluations
+            .chunks(6)
+            .zip(read_write_leaves.chunks(4))
+        {
+            let rs_row = memory_hash(ev[0], ev[1], ev[2], gamma, tau);
+            let rs_col = memory_hash(ev[3], ev[4], ev[5], gamma, tau);
+            if leaves[0] != rs_row
+                || leaves[1] != rs_row + E::Fr::one()
+                || leaves[2] != rs_col
+                || leaves[3] != rs_col + E::Fr::one()
+            {
+                return Err(crate::Error::WrongWitness(Some(
+                    "read set or write set is inconsistent with commitments".into(),
+                )));
+            }
+        }
+        transcript.append_scalars(b"read write evaluations", &proof.read_write_evaluations)?;
+        let mu: E::Fr = transcript.challenge_scalar(b"mu")?;
+        let mut commitments = Vec::with_capacity(n * 6);
+        for (i, m) in vk.matrices.iter().enumerate() {
+            commitments.push(&m.row);
+            commitments.push(&proof.e_commitments[i << 1]);
+            commitments.push(&m.read_ts_row);
+            commitments.push(&m.col);
+            commitments.push(&proof.e_commitments[(i << 1) + 1]);
+            commitments.push(&m.read_ts_col);
+        }
+        if !MLPolyCommit::batch_verify(
+            vp,
+            &commitments,
+            &r_rw,
+            &proof.read_write_evaluations,
+            mu,
+            proof.proof_for_read_write_evaluations,
+        )? {
+            return Err(crate::Error::WrongWitness(Some(
+                "cannot verify addresses and timestamps".into(),
+            )));
+        }
+
+        let row_audit_products = Self::verify_audit(
+            vp,
+            r_x,
+            proof.row_audit_products,
+            proof.row_audit_evaluations,
+            proof.proof_for_row_audit_evaluations,
+            vk.matrices.iter().map(|m| &m.final_ts_row).collect(),
+            gamma,
+            tau,
+            transcript,
+        )?;
+        let col_audit_products = Self::verify_audit(
+            vp,
+            r_y,
+            proof.col_audit_products,
+            proof.col_audit_evaluations,
+            proof.proof_for_col_audit_evaluations,
+            vk.matrices.iter().map(|m| &m.final_ts_col).collect(),
+            gamma,
+            tau,
+            transcript,
+        )?;
+
+        // init * write = read * audit, for each matrix and each memory
+        for i in 0..n {
+            let rw = &read_write_products[(i << 2)..((i + 1) << 2)];
+            if row_audit_products[0] * rw[1] != rw[0] * row_audit_products[i + 1]
+                || col_audit_products[0] * rw[3] != rw[2] * col_audit_products[i + 1]
+            {
+                return Err(crate::Error::WrongWitness(Some(
+                    "memory checking failed".into(),
+                )));
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Verify grand products of the init set and audit sets of memory `eq(eq_point, .)`.
+    ///
+    /// return: products as [init, audit_0, audit_1, ...]
+    fn verify_audit<K: OpeningVerifierKey<E>, H: TranscriptHash>(
+        vp: &K,
+        eq_point: &[E::Fr],
+        products_proof: GrandProductProof<E>,
+        final_ts_evaluations: Vec<E::Fr>,
+        proof: Proof<E>,
+        final_ts_commitments: Vec<&Commitment<E>>,
+        gamma: E::Fr,
+        tau: E::Fr,
+        transcript: &mut Transcript<H>,
+    ) -> SResult<Vec<E::Fr>> {
+        let (products, r, leaves) =
+            grand_product::verify(vp, products_proof, eq_point.len(), transcript)?;
+        if products.len() != final_ts_commitments.len() + 1 {
+            return Err(invalid_arg("malformed spark proof"));
+        }
+        let init = memory_hash(
+            identity_eval(&r),
+            eq_eval(eq_point, &r)?,
+            E::Fr::zero(),
+            gamma,
+            tau,
+        );
+        if leaves[0] != init
+            || leaves[1..]
+                .iter()
+                .zip(final_ts_evaluations.iter())
+                .any(|(audit, ts)| *audit != init + ts)
+        {
+            return Err(crate::Error::WrongWitness(Some(
+                "audit set is inconsistent with commitments".into(),
+            )));
+        }
+        transcript.append_scalars(FINAL_TIMESTAMP_EVALUATIONS_LABEL, &final_ts_evaluations)?;
+        let mu: E::Fr = transcript.challenge_scalar(b"mu")?;
+        if !MLPolyCommit::batch_verify(vp, &final_ts_commitments, &r, &final_ts_evaluations, mu, proof)? {
+            return Err(crate::Error::WrongWitness(Some(
+                "cannot verify final timestamps".into(),
+            )));
+        }
+        Ok(products)
+    }
+}
@@ -0,0 +1,129 @@
+//! non-interactive multilinear sumcheck used by sub-protocols
+
+use ark_ff::Field;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
+use linear_sumcheck::data_structures::ml_extension::ArithmeticCombination;
+use linear_sumcheck::ml_sumcheck::ahp::indexer::IndexInfo as MLIndexInfo;
+use linear_sumcheck::ml_sumcheck::ahp::prover::ProverMsg as MLProverMsg;
+use linear_sumcheck::ml_sumcheck::ahp::AHPForMLSumcheck;
+
+use crate::data_structures::transcript::{Transcript, TranscriptHash};
+use crate::error::{invalid_arg, SResult};
+
+/// messages of a non-interactive sumcheck
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct SumcheckProof<F: Field> {
+    pub index_info: MLIndexInfo,
+    pub messages: Vec<MLProverMsg<F>>,
+}
+
+/// Prove the sum of `poly` over the boolean hypercube, using `transcript` to generate verifier's randomness.
+///
+/// return: the proof and the random point at which `poly` should be evaluated
+pub(crate) fn prove<F: Field, H: TranscriptHash>(
+    poly: ArithmeticCombination<F>,
+    transcript: &mut Transcript<H>,
+) -> SResult<(SumcheckProof<F>, Vec<F>)> {
+    let index = AHPForMLSumcheck::convert_to_index(poly)?;
+    let index_info = index.info();
+    let nv = index_info.num_variables;
+    if nv == 0 {
+        return Err(invalid_arg("sumcheck needs at least one variable"));
+    }
+    transcript.append_message(b"sumcheck index", &index_info)?;
+
+    let mut prover_state = AHPForMLSumcheck::prover_init(&index);
+    let mut v_msg = None;
+    let mut messages = Vec::with_capacity(nv);
+    for _ in 0..nv {
+        let (p_msg, new_prover_state) = AHPForMLSumcheck::prove_round(prover_state, &v_msg)?;
+        prover_state = new_prover_state;
+        transcript.append_message(b"sumcheck message", &p_msg)?;
+        messages.push(p_msg);
+        v_msg = Some(AHPForMLSumcheck::sample_round(
+            transcript.challenge_rng(b"sumcheck challenge")?,
+        ));
+    }
+    let mut point = prover_state.randomness;
+    point.push(v_msg.unwrap().randomness);
+
+    Ok((
+        SumcheckProof {
+            index_info,
+            messages,
+        },
+        point,
+    ))
+}
+
+/// Verify a sumcheck proof of a polynomial with `num_variables` variables and sum `claimed_sum`.
+/// `expected_degree` is the degree of the polynomial in each variable, i.e. the largest number of multiplicands
+/// in one of its products. The prover cannot choose it, since it bounds the soundness error.
+///
+/// return: the random point and the expected evaluation of the polynomial at that point
+pub(crate) fn verify<F: Field, H: TranscriptHash>(
+    proof: SumcheckProof<F>,
+    num_variables: usize,
+    expected_degree: usize,
+    claimed_sum: F,
+    transcript: &mut Transcript<H>,
+) -> SResult<(Vec<F>, F)> {
+    if num_variables == 0
+        || proof.index_info.num_variables != num_variables
+        || proof.index_info.max_multiplicands != expected_degree
+        || proof.messages.len() != num_variables
+    {
+        return Err(invalid_arg("invalid sumcheck proposal"));
+    }
+    transcript.append_message(b"sumcheck index", &proof.index_info)?;
+
+    let mut verifier_state = AHPForMLSumcheck::verifier_init(&proof.index_info);
+    for p_msg in proof.messages {
+        transcript.append_message(b"sumcheck message", &p_msg)?;
+        let (_, new_verifier_state) = AHPForMLSumcheck::verify_round(
+            p_msg,
+            verifier_state,
+            transcript.challenge_rng(b"sumcheck challenge")?,
+        )?;
+        verifier_state = new_verifier_state;
+    }
+    let subclaim = AHPForMLSumcheck::check_and_generate_subclaim(verifier_state, claimed_sum)?;
+    Ok((subclaim.point, subclaim.expected_evaluation))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_structures::transcript::Transcript;
+    use crate::sumcheck;
+    use crate::test_utils::TestCurveFr;
+    use ark_ff::{test_rng, UniformRand, Zero};
+    use linear_sumcheck::data_structures::ml_extension::ArithmeticCombination;
+    use linear_sumcheck::data_structures::MLExtensionArray;
+
+    #[test]
+    fn test_degree() {
+        let mut rng = test_rng();
+        let nv = 4;
+        let f: Vec<TestCurveFr> = (0..(1 << nv)).map(|_| TestCurveFr::rand(&mut rng)).collect();
+        let g: Vec<TestCurveFr> = (0..(1 << nv)).map(|_| TestCurveFr::rand(&mut rng)).collect();
+        let sum = f.iter().zip(g.iter()).fold(TestCurveFr::zero(), |acc, (f, g)| acc + *f * g);
+        let mut poly = ArithmeticCombination::new(nv);
+        poly.add_product(vec![MLExtensionArray::from_vec(f).unwrap(), MLExtensionArray::from_vec(g).unwrap()].into_iter())
+            .unwrap();
+        let mut transcript: Transcript = Transcript::new(b"test");
+        let (proof, point) = sumcheck::prove(poly, &mut transcript).unwrap();
+
+        let mut transcript: Transcript = Transcript::new(b"test");
+        let (verified_point, _) = sumcheck::verify(proof, nv, 2, sum, &mut transcript).unwrap();
+        assert_eq!(point, verified_point);
+
+        // the degree of the round polynomials is fixed by the verifier
+        let mut poly = ArithmeticCombination::new(nv);
+        poly.add_product(vec![MLExtensionArray::from_vec(vec![TestCurveFr::zero(); 1 << nv]).unwrap()].into_iter())
+            .unwrap();
+        let mut transcript: Transcript = Transcript::new(b"test");
+        let (proof, _) = sumcheck::prove(poly, &mut transcript).unwrap();
+        let mut transcript: Transcript = Transcript::new(b"test");
+        assert!(sumcheck::verify(proof, nv, 2, TestCurveFr::zero(), &mut transcript).is_err());
+    }
+}
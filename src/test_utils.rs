@@ -1,6 +1,7 @@
 //! testing utility
 
 use ark_ff::{Field, UniformRand};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use ark_relations::r1cs::{
     ConstraintSynthesizer, ConstraintSystem, ConstraintSystemRef, Matrix, Variable,
 };
@@ -15,6 +16,19 @@ use ark_ec::PairingEngine;
 pub type TestCurve = Bls12_381;
 pub type TestCurveFr = <TestCurve as PairingEngine>::Fr;
 
+/// Serialize `value` and deserialize it back, checking that the encoding is stable. Also gives a fresh copy of
+/// proofs, which are not `Clone`.
+pub fn serialization_round_trip<T: CanonicalSerialize + CanonicalDeserialize>(value: &T) -> T {
+    let mut data = Vec::new();
+    value.serialize(&mut data).unwrap();
+    assert_eq!(data.len(), value.serialized_size());
+    let result = T::deserialize(&data[..]).unwrap();
+    let mut again = Vec::new();
+    result.serialize(&mut again).unwrap();
+    assert_eq!(data, again);
+    result
+}
+
 pub fn random_matrix<R: RngCore>(
     log_size: usize,
     num_non_zero: usize,
@@ -100,3 +114,40 @@ pub(crate) fn make_matrices_square<F: Field>(
         }
     }
 }
+
+/// Matrices of a circuit with many satisfying assignments, and `num_instances` random instances of it.
+/// Public input is `(1, out, s, ...)` and the witness is a chain `w[i] = (w[i - 1] + s) * w[i - 1]` from a
+/// random `w[0]`, with `out = w[last]`.
+pub fn chain_circuit_with_instances<F: Field, R: RngCore>(
+    num_public_variables: usize,
+    num_private_variables: usize,
+    num_instances: usize,
+    rng: &mut R,
+) -> ((Matrix<F>, Matrix<F>, Matrix<F>), Vec<Vec<F>>, Vec<Vec<F>>) {
+    assert!(num_public_variables >= 3 && num_private_variables >= 1);
+    let w_index = |i: usize| num_public_variables + i;
+    let (mut a, mut b, mut c) = (Vec::new(), Vec::new(), Vec::new());
+    for i in 1..num_private_variables {
+        a.push(vec![(F::one(), w_index(i - 1)), (F::one(), 2)]);
+        b.push(vec![(F::one(), w_index(i - 1))]);
+        c.push(vec![(F::one(), w_index(i))]);
+    }
+    a.push(vec![(F::one(), w_index(num_private_variables - 1))]);
+    b.push(vec![(F::one(), 0)]);
+    c.push(vec![(F::one(), 1)]);
+
+    let mut vs = Vec::with_capacity(num_instances);
+    let mut ws = Vec::with_capacity(num_instances);
+    for _ in 0..num_instances {
+        let mut v: Vec<F> = (0..num_public_variables).map(|_| F::rand(rng)).collect();
+        v[0] = F::one();
+        let mut w = vec![F::rand(rng)];
+        for i in 1..num_private_variables {
+            w.push((w[i - 1] + v[2]) * w[i - 1]);
+        }
+        v[1] = w[num_private_variables - 1];
+        vs.push(v);
+        ws.push(w);
+    }
+    ((a, b, c), vs, ws)
+}
@@ -0,0 +1,436 @@
+//! Virtual polynomials: sums of products of committed and public multilinear polynomials.
+//!
+//! A virtual polynomial `sum_j c_j prod_k f_{j,k}(x)` is described once and shared by the prover and the verifier.
+//! The prover runs the sumcheck of its sum over the boolean hypercube, and opens all committed polynomials at the
+//! final point with one batched opening. The verifier evaluates the public polynomials itself, so they should
+//! either be small tables, or have succinct extensions such as `eq(t, x)`. The public polynomials are bound to the
+//! transcript: the point of each `eq(t, x)`, and a digest of each table.
+
+use ark_ec::PairingEngine;
+use ark_ff::{Field, One, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
+use linear_sumcheck::data_structures::ml_extension::{ArithmeticCombination, MLExtension};
+use linear_sumcheck::data_structures::MLExtensionArray;
+
+use crate::commitment::commit::Commitment;
+use crate::commitment::data_structures::PublicParameter;
+use crate::commitment::open::Proof;
+use crate::commitment::verify::{OpeningClaim, OpeningVerifierKey};
+use crate::commitment::MLPolyCommit;
+use crate::data_structures::eq::{eq_eval, eq_table};
+use crate::data_structures::transcript::{Transcript, TranscriptHash};
+use crate::error::{invalid_arg, SResult};
+use crate::sumcheck::{self, SumcheckProof};
+use sha2::Digest;
+
+/// multilinear polynomial known to the verifier
+pub enum PublicPolynomial<F: Field> {
+    /// polynomial given by its evaluations over the boolean hypercube
+    Table(MLExtensionArray<F>),
+    /// eq(t, x) for the given t, which the verifier evaluates in O(|t|)
+    Eq(Vec<F>),
+}
+
+impl<F: Field> PublicPolynomial<F> {
+    fn num_variables(&self) -> SResult<usize> {
+        match self {
+            PublicPolynomial::Table(table) => Ok(table.num_variables()?),
+            PublicPolynomial::Eq(t) => Ok(t.len()),
+        }
+    }
+
+    fn table(&self) -> SResult<MLExtensionArray<F>> {
+        match self {
+            PublicPolynomial::Table(table) => Ok(table.clone()),
+            PublicPolynomial::Eq(t) => Ok(MLExtensionArray::from_vec(eq_table(t))?),
+        }
+    }
+
+    fn evaluate(&self, point: &[F]) -> SResult<F> {
+        match self {
+            PublicPolynomial::Table(table) => Ok(table.eval_at(point)?),
+            PublicPolynomial::Eq(t) => eq_eval(t, point),
+        }
+    }
+
+    /// bind the point of `eq(t, x)`, or a digest of the table
+    fn append_to_transcript<H: TranscriptHash>(&self, transcript: &mut Transcript<H>) -> SResult<()> {
+        match self {
+            PublicPolynomial::Table(table) => {
+                let mut bytes = Vec::new();
+                b"r1cs-spartan-raw/public-table".to_vec().serialize(&mut bytes)?;
+                table.clone().into_table()?.serialize(&mut bytes)?;
+                transcript.append_message(b"public table digest", &sha2::Sha256::digest(&bytes).to_vec())
+            }
+            PublicPolynomial::Eq(t) => transcript.append_scalars(b"public eq point", t),
+        }
+    }
+}
+
+/// factor of a product in a virtual polynomial
+///
+/// In the transcript, each multiplicand is bound by its tag, `COMMITTED_TAG` or `PUBLIC_TAG`, and its index.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Multiplicand {
+    /// index into the committed polynomials given to the prover, and their commitments given to the verifier
+    Committed(usize),
+    /// index into the public polynomials of the virtual polynomial
+    Public(usize),
+}
+
+/// `sum_j c_j prod_k f_{j,k}(x)`, where each `f_{j,k}` is a committed or a public polynomial
+pub struct VirtualPolynomial<F: Field> {
+    pub num_variables: usize,
+    pub public: Vec<PublicPolynomial<F>>,
+    pub products: Vec<(F, Vec<Multiplicand>)>,
+}
+
+impl Multiplicand {
+    /// tag of a committed multiplicand in the transcript
+    pub const COMMITTED_TAG: u8 = 0;
+    /// tag of a public multiplicand in the transcript
+    pub const PUBLIC_TAG: u8 = 1;
+
+    /// the tag and the index bound to the transcript
+    fn encode(&self) -> (u8, usize) {
+        match self {
+            Multiplicand::Committed(k) => (Self::COMMITTED_TAG, *k),
+            Multiplicand::Public(k) => (Self::PUBLIC_TAG, *k),
+        }
+    }
+}
+
+impl<F: Field> VirtualPolynomial<F> {
+    pub fn new(num_variables: usize) -> Self {
+        Self {
+            num_variables,
+            public: Vec::new(),
+            products: Vec::new(),
+        }
+    }
+
+    /// add a public polynomial, and return the multiplicand referring to it
+    pub fn add_public(&mut self, polynomial: PublicPolynomial<F>) -> SResult<Multiplicand> {
+        if polynomial.num_variables()? != self.num_variables {
+            return Err(invalid_arg("public polynomial has wrong number of variables"));
+        }
+        self.public.push(polynomial);
+        Ok(Multiplicand::Public(self.public.len() - 1))
+    }
+
+    /// add the product `coefficient * prod multiplicands`
+    pub fn add_product(&mut self, coefficient: F, multiplicands: Vec<Multiplicand>) -> SResult<()> {
+        if multiplicands.is_empty() {
+            return Err(invalid_arg("product should have at least one multiplicand"));
+        }
+        if multiplicands
+            .iter()
+            .any(|m| matches!(m, Multiplicand::Public(k) if *k >= self.public.len()))
+        {
+            return Err(invalid_arg("product refers to unknown public polynomial"));
+        }
+        self.products.push((coefficient, multiplicands));
+        Ok(())
+    }
+
+    /// degree in each variable: the largest number of multiplicands in a product
+    pub fn degree(&self) -> usize {
+        self.products.iter().map(|(_, multiplicands)| multiplicands.len()).max().unwrap_or(0)
+    }
+
+    /// number of committed polynomials the products refer to
+    fn num_committed(&self) -> usize {
+        self.products
+            .iter()
+            .flat_map(|(_, multiplicands)| multiplicands.iter())
+            .filter_map(|m| match m {
+                Multiplicand::Committed(k) => Some(k + 1),
+                Multiplicand::Public(_) => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// the virtual polynomial as an arithmetic combination, given the committed polynomials
+    fn to_combination(&self, committed: &[MLExtensionArray<F>]) -> SResult<ArithmeticCombination<F>> {
+        let public = self
+            .public
+            .iter()
+            .map(|p| p.table())
+            .collect::<SResult<Vec<_>>>()?;
+        let mut poly = ArithmeticCombination::new(self.num_variables);
+        for (coefficient, multiplicands) in self.products.iter() {
+            let mut tables: Vec<_> = multiplicands
+                .iter()
+                .map(|m| match m {
+                    Multiplicand::Committed(k) => committed[*k].clone(),
+                    Multiplicand::Public(k) => public[*k].clone(),
+                })
+                .collect();
+            if !coefficient.is_one() {
+                tables[0] = tables[0].multiply(*coefficient)?;
+            }
+            poly.add_product(tables.into_iter())?;
+        }
+        Ok(poly)
+    }
+
+    /// evaluate the virtual polynomial at `point`, given the evaluations of the committed polynomials
+    fn evaluate(&self, committed: &[F], point: &[F]) -> SResult<F> {
+        let public = self
+            .public
+            .iter()
+            .map(|p| p.evaluate(point))
+            .collect::<SResult<Vec<_>>>()?;
+        Ok(self.products.iter().fold(F::zero(), |acc, (coefficient, multiplicands)| {
+            let product = multiplicands.iter().fold(*coefficient, |acc, m| match m {
+                Multiplicand::Committed(k) => acc * &committed[*k],
+                Multiplicand::Public(k) => acc * &public[*k],
+            });
+            acc + &product
+        }))
+    }
+}
+
+/// proof of the sum of a virtual polynomial: the sumcheck, and the evaluations of the committed polynomials at the
+/// final point with one batched opening
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct VirtualPolynomialProof<E: PairingEngine> {
+    pub sumcheck: SumcheckProof<E::Fr>,
+    pub evaluations: Vec<E::Fr>,
+    pub proof_for_evaluations: Proof<E>,
+}
+
+/// bind the commitments, the public polynomials, the products and the claimed sum
+fn append_statement<E: PairingEngine, H: TranscriptHash>(
+    poly: &VirtualPolynomial<E::Fr>,
+    commitments: &[Commitment<E>],
+    claimed_sum: E::Fr,
+    transcript: &mut Transcript<H>,
+) -> SResult<()> {
+    if poly.products.is_empty() {
+        return Err(invalid_arg("virtual polynomial has no product"));
+    }
+    if commitments.is_empty() || poly.num_committed() > commitments.len() {
+        return Err(invalid_arg("should have a commitment for each committed polynomial"));
+    }
+    if commitments.iter().any(|c| c.nv != poly.num_variables) {
+        return Err(invalid_arg("committed polynomial has wrong number of variables"));
+    }
+    transcript.append_message(b"virtual polynomial commitments", &commitments.to_vec())?;
+    transcript.append_message(b"number of public polynomials", &poly.public.len())?;
+    for public in poly.public.iter() {
+        public.append_to_transcript(transcript)?;
+    }
+    let coefficients: Vec<_> = poly.products.iter().map(|(c, _)| *c).collect();
+    // tags and indices of the multiplicands of each product, as two lists of the same shape
+    let (tags, indices): (Vec<Vec<u8>>, Vec<Vec<usize>>) = poly
+        .products
+        .iter()
+        .map(|(_, multiplicands)| multiplicands.iter().map(Multiplicand::encode).unzip::<_, _, Vec<_>, Vec<_>>())
+        .unzip();
+    transcript.append_message(b"virtual polynomial coefficients", &coefficients)?;
+    transcript.append_message(b"virtual polynomial multiplicand tags", &tags)?;
+    transcript.append_message(b"virtual polynomial multiplicand indices", &indices)?;
+    transcript.append_message(b"claimed sum", &claimed_sum)
+}
+
+/// Prove that the sum of `poly` over the boolean hypercube is `claimed_sum`, where `committed[k]` is committed in
+/// `commitments[k]`.
+///
+/// return: the proof, and the point at which the committed polynomials are opened
+pub fn prove<E: PairingEngine, H: TranscriptHash>(
+    pp: &PublicParameter<E>,
+    poly: &VirtualPolynomial<E::Fr>,
+    committed: Vec<MLExtensionArray<E::Fr>>,
+    commitments: &[Commitment<E>],
+    claimed_sum: E::Fr,
+    transcript: &mut Transcript<H>,
+) -> SResult<(VirtualPolynomialProof<E>, Vec<E::Fr>)> {
+    if committed.len() != commitments.len() {
+        return Err(invalid_arg("should have one commitment for each committed polynomial"));
+    }
+    if committed.len() < poly.num_committed() {
+        return Err(invalid_arg("product refers to unknown committed polynomial"));
+    }
+    append_statement(poly, commitments, claimed_sum, transcript)?;
+
+    let timer = start_timer!(|| "Prove virtual polynomial");
+    let (sumcheck, point) = sumcheck::prove(poly.to_combination(&committed)?, transcript)?;
+    let evaluations = committed
+        .iter()
+        .map(|f| f.eval_at(&point))
+        .collect::<Result<Vec<_>, _>>()?;
+    transcript.append_scalars(b"evaluations", &evaluations)?;
+    let mu: E::Fr = transcript.challenge_scalar(b"opening challenge")?;
+    let proof_for_evaluations = MLPolyCommit::batch_open(pp, committed, &point, mu)?;
+    end_timer!(timer);
+
+    Ok((
+        VirtualPolynomialProof {
+            sumcheck,
+            evaluations,
+            proof_for_evaluations,
+        },
+        point,
+    ))
+}
+
+/// Verify that the sum of `poly` over the boolean hypercube is `claimed_sum`.
+///
+/// `vp` can be the public `VerifierParameter` or a `DesignatedVerifierParameter`.
+///
+/// return: the point and the evaluations of the committed polynomials at that point, which are already checked
+pub fn verify<E: PairingEngine, K: OpeningVerifierKey<E>, H: TranscriptHash>(
+    vp: &K,
+    poly: &VirtualPolynomial<E::Fr>,
+    commitments: &[Commitment<E>],
+    claimed_sum: E::Fr,
+    proof: VirtualPolynomialProof<E>,
+    transcript: &mut Transcript<H>,
+) -> SResult<(Vec<E::Fr>, Vec<E::Fr>)> {
+    let (point, evaluations, claim) = verify_deferred(poly, commitments, claimed_sum, proof, transcript)?;
+    if !vp.verify_opening(&claim.commitment, &claim.point, claim.eval, claim.proof)? {
+        return Err(crate::Error::WrongWitness(Some(
+            "cannot verify evaluations of committed polynomials".into(),
+        )));
+    }
+    Ok((point, evaluations))
+}
+
+/// Verify that the sum of `poly` over the boolean hypercube is `claimed_sum`, except for the batched opening of the
+/// committed polynomials.
+///
+/// return: the point, the evaluations of the committed polynomials at that point, and their batched opening claim,
+/// which should be checked by the caller, e.g. with `MLPolyCommit::verify_many`
+pub fn verify_deferred<E: PairingEngine, H: TranscriptHash>(
+    poly: &VirtualPolynomial<E::Fr>,
+    commitments: &[Commitment<E>],
+    claimed_sum: E::Fr,
+    proof: VirtualPolynomialProof<E>,
+    transcript: &mut Transcript<H>,
+) -> SResult<(Vec<E::Fr>, Vec<E::Fr>, OpeningClaim<E>)> {
+    append_statement(poly, commitments, claimed_sum, transcript)?;
+    if proof.evaluations.len() != commitments.len() {
+        return Err(invalid_arg("should have one evaluation for each committed polynomial"));
+    }
+
+    let (point, expected) = sumcheck::verify(proof.sumcheck, poly.num_variables, poly.degree(), claimed_sum, transcript)?;
+    if poly.evaluate(&proof.evaluations, &point)? != expected {
+        return Err(crate::Error::WrongWitness(Some(
+            "virtual polynomial has wrong subclaim".into(),
+        )));
+    }
+    transcript.append_scalars(b"evaluations", &proof.evaluations)?;
+    let mu: E::Fr = transcript.challenge_scalar(b"opening challenge")?;
+    let commitments: Vec<_> = commitments.iter().collect();
+    let claim = MLPolyCommit::batch_claim(&commitments, &point, &proof.evaluations, mu, proof.proof_for_evaluations)?;
+    Ok((point, proof.evaluations, claim))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commitment::MLPolyCommit;
+    use crate::data_structures::eq::eq_table;
+    use crate::data_structures::transcript::Transcript;
+    use crate::test_utils::TestCurve;
+    use crate::virtual_polynomial::{self, Multiplicand, PublicPolynomial, VirtualPolynomial};
+    use ark_ec::PairingEngine;
+    use ark_ff::{test_rng, One, UniformRand, Zero};
+    use linear_sumcheck::data_structures::ml_extension::MLExtension;
+    use linear_sumcheck::data_structures::MLExtensionArray;
+    use rand::RngCore;
+
+    type E = TestCurve;
+    type Fr = <E as PairingEngine>::Fr;
+
+    fn random_table<R: RngCore>(nv: usize, rng: &mut R) -> Vec<Fr> {
+        (0..(1 << nv)).map(|_| Fr::rand(rng)).collect()
+    }
+
+    #[test]
+    fn test_virtual_polynomial() {
+        let mut rng = test_rng();
+        let nv = 5;
+        let (pp, vp, _) = MLPolyCommit::<E>::keygen(nv, &mut rng).unwrap();
+        let (f, g, h, p) = (
+            random_table(nv, &mut rng),
+            random_table(nv, &mut rng),
+            random_table(nv, &mut rng),
+            random_table(nv, &mut rng),
+        );
+        let t: Vec<Fr> = (0..nv).map(|_| Fr::rand(&mut rng)).collect();
+
+        // f * g * eq(t, .) + 3 * h * p - h, where p is public
+        let three = Fr::from(3u64);
+        let mut poly = VirtualPolynomial::new(nv);
+        let eq = poly.add_public(PublicPolynomial::Eq(t.clone())).unwrap();
+        let public = poly.add_public(PublicPolynomial::Table(MLExtensionArray::from_slice(&p).unwrap())).unwrap();
+        let (f_m, g_m, h_m) = (Multiplicand::Committed(0), Multiplicand::Committed(1), Multiplicand::Committed(2));
+        poly.add_product(Fr::one(), vec![f_m, g_m, eq]).unwrap();
+        poly.add_product(three, vec![h_m, public]).unwrap();
+        poly.add_product(-Fr::one(), vec![h_m]).unwrap();
+        assert!(poly.add_product(Fr::one(), vec![Multiplicand::Public(2)]).is_err());
+
+        let eq_t = eq_table(&t);
+        let sum = (0..(1 << nv)).fold(Fr::zero(), |acc, x| {
+            acc + f[x] * g[x] * eq_t[x] + three * h[x] * p[x] - h[x]
+        });
+        let committed: Vec<_> = [f, g, h].iter().map(|t| MLExtensionArray::from_slice(t).unwrap()).collect();
+        let commitments: Vec<_> = committed.iter().map(|c| MLPolyCommit::commit(&pp, c.clone()).unwrap()).collect();
+
+        let mut transcript: Transcript = Transcript::new(b"test");
+        let (proof, point) = virtual_polynomial::prove(&pp, &poly, committed.clone(), &commitments, sum, &mut transcript).unwrap();
+        let mut transcript: Transcript = Transcript::new(b"test");
+        let (verified_point, evaluations) = virtual_polynomial::verify(&vp, &poly, &commitments, sum, proof, &mut transcript).unwrap();
+        assert_eq!(point, verified_point);
+        assert_eq!(evaluations[1], committed[1].eval_at(&point).unwrap());
+
+        // wrong claimed sum
+        let mut transcript: Transcript = Transcript::new(b"test");
+        let (proof, _) = virtual_polynomial::prove(&pp, &poly, committed.clone(), &commitments, sum + Fr::one(), &mut transcript).unwrap();
+        let mut transcript: Transcript = Transcript::new(b"test");
+        assert!(virtual_polynomial::verify(&vp, &poly, &commitments, sum + Fr::one(), proof, &mut transcript).is_err());
+
+        // the public polynomials are part of the statement
+        let challenge = |t: &[Fr], p: &[Fr]| {
+            let mut other = VirtualPolynomial::new(nv);
+            other.add_public(PublicPolynomial::Eq(t.to_vec())).unwrap();
+            other.add_public(PublicPolynomial::Table(MLExtensionArray::from_slice(p).unwrap())).unwrap();
+            other.products = poly.products.clone();
+            let mut transcript: Transcript = Transcript::new(b"test");
+            virtual_polynomial::append_statement(&other, &commitments, sum, &mut transcript).unwrap();
+            transcript.challenge_scalar::<Fr>(b"challenge").unwrap()
+        };
+        let (mut other_t, mut other_p) = (t.clone(), p.clone());
+        other_t[0] += Fr::one();
+        other_p[3] += Fr::one();
+        assert_eq!(challenge(&t, &p), challenge(&t, &p));
+        assert_ne!(challenge(&t, &p), challenge(&other_t, &p));
+        assert_ne!(challenge(&t, &p), challenge(&t, &other_p));
+
+        // committed and public multiplicands with the same index are distinguished
+        let products = |m: Multiplicand| {
+            let mut other = VirtualPolynomial::new(nv);
+            other.add_public(PublicPolynomial::Eq(t.clone())).unwrap();
+            other.add_product(Fr::one(), vec![m]).unwrap();
+            let mut transcript: Transcript = Transcript::new(b"test");
+            virtual_polynomial::append_statement(&other, &commitments, sum, &mut transcript).unwrap();
+            transcript.challenge_scalar::<Fr>(b"challenge").unwrap()
+        };
+        assert_ne!(products(Multiplicand::Committed(0)), products(Multiplicand::Public(0)));
+
+        // every committed multiplicand should be given to the prover
+        let mut transcript: Transcript = Transcript::new(b"test");
+        assert!(virtual_polynomial::prove(&pp, &poly, committed[..2].to_vec(), &commitments[..2], sum, &mut transcript).is_err());
+
+        // committed polynomials should match the commitments
+        let mut other = committed;
+        other.swap(0, 1);
+        other[2] = MLExtensionArray::from_vec(random_table(nv, &mut rng)).unwrap();
+        let mut transcript: Transcript = Transcript::new(b"test");
+        let (proof, _) = virtual_polynomial::prove(&pp, &poly, other, &commitments, sum, &mut transcript).unwrap();
+        let mut transcript: Transcript = Transcript::new(b"test");
+        assert!(virtual_polynomial::verify(&vp, &poly, &commitments, sum, proof, &mut transcript).is_err());
+    }
+}
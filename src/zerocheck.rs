@@ -0,0 +1,292 @@
+//! Zerocheck: proof that a combination of products of committed multilinear polynomials is zero on the
+//! whole boolean hypercube.
+//!
+//! The combination `g(x) = sum_j c_j prod_k f_{j,k}(x)` is masked by `eq(tau, x)` for a random `tau`. The sum of
+//! `eq(tau, x) g(x)` over the hypercube is the multilinear extension of g evaluated at tau, which is zero for all
+//! tau only if g is zero on the hypercube. The masked combination is a virtual polynomial with claimed sum zero,
+//! whose sumcheck ends at a random point `r`, where all polynomials are opened with one batched opening. The opening
+//! is either checked with a verifier key, or returned as a claim, so that the caller checks it together with
+//! other openings.
+
+use ark_ec::PairingEngine;
+use ark_ff::{Field, One, Zero};
+use linear_sumcheck::data_structures::ml_extension::{ArithmeticCombination, MLExtension};
+use linear_sumcheck::data_structures::MLExtensionArray;
+
+use crate::commitment::commit::Commitment;
+use crate::commitment::data_structures::PublicParameter;
+use crate::commitment::verify::{OpeningClaim, OpeningVerifierKey};
+use crate::data_structures::eq::{eq_eval, eq_table};
+use crate::data_structures::transcript::{Transcript, TranscriptHash};
+use crate::error::{invalid_arg, SResult};
+use crate::virtual_polynomial::{
+    self, Multiplicand, PublicPolynomial, VirtualPolynomial, VirtualPolynomialProof,
+};
+
+/// Products `(c_j, [k_1, k_2, ...])` of the combination `sum_j c_j f_{k_1} f_{k_2} ...`, given by the indices
+/// of the polynomials.
+pub type Products<F> = [(F, Vec<usize>)];
+
+/// proof of a zerocheck: the sumcheck of the masked combination, and the evaluations of the polynomials at the
+/// final point with one batched opening
+pub type ZerocheckProof<E> = VirtualPolynomialProof<E>;
+
+/// products of `f_0 * f_1 - f_2`, i.e. the Hadamard product `f_0 ∘ f_1 = f_2`
+pub fn hadamard<F: Field>() -> Vec<(F, Vec<usize>)> {
+    vec![(F::one(), vec![0, 1]), (-F::one(), vec![2])]
+}
+
+fn check_products<F: Field>(num_polynomials: usize, products: &Products<F>) -> SResult<()> {
+    if products.is_empty()
+        || products
+            .iter()
+            .any(|(_, p)| p.iter().any(|k| *k >= num_polynomials))
+    {
+        return Err(invalid_arg(
+            "products should refer to the given polynomials",
+        ));
+    }
+    Ok(())
+}
+
+/// `eq(tau, x) * sum_j c_j prod_k f_{j,k}(x)` as an arithmetic combination
+pub(crate) fn masked_combination<F: Field>(
+    tau: &[F],
+    polynomials: &[MLExtensionArray<F>],
+    products: &Products<F>,
+) -> SResult<ArithmeticCombination<F>> {
+    check_products(polynomials.len(), products)?;
+    let eq = MLExtensionArray::from_vec(eq_table(tau))?;
+    let mut poly = ArithmeticCombination::new(tau.len());
+    for (coefficient, product) in products.iter() {
+        let mut multiplicands = Vec::with_capacity(product.len() + 1);
+        for k in product.iter() {
+            multiplicands.push(polynomials[*k].clone());
+        }
+        if coefficient.is_one() {
+            multiplicands.push(eq.clone());
+        } else {
+            multiplicands.push(eq.multiply(*coefficient)?);
+        }
+        poly.add_product(multiplicands.into_iter())?;
+    }
+    Ok(poly)
+}
+
+/// `eq(tau, r) * sum_j c_j prod_k f_{j,k}(r)`, given the evaluations `f_k(r)`
+pub(crate) fn masked_evaluation<F: Field>(
+    tau: &[F],
+    r: &[F],
+    evaluations: &[F],
+    products: &Products<F>,
+) -> SResult<F> {
+    check_products(evaluations.len(), products)?;
+    let combination = products
+        .iter()
+        .fold(F::zero(), |acc, (coefficient, product)| {
+            acc + &product
+                .iter()
+                .fold(*coefficient, |acc, k| acc * &evaluations[*k])
+        });
+    Ok(eq_eval(tau, r)? * &combination)
+}
+
+fn append_statement<E: PairingEngine, H: TranscriptHash>(
+    commitments: &[Commitment<E>],
+    products: &Products<E::Fr>,
+    transcript: &mut Transcript<H>,
+) -> SResult<usize> {
+    let nv = commitments
+        .first()
+        .map(|c| c.nv)
+        .ok_or(invalid_arg("no polynomial for zerocheck"))?;
+    if commitments.iter().any(|c| c.nv != nv) {
+        return Err(invalid_arg(
+            "polynomials of zerocheck should have the same number of variables",
+        ));
+    }
+    check_products(commitments.len(), products)?;
+    transcript.append_message(b"zerocheck commitments", &commitments.to_vec())?;
+    let coefficients: Vec<_> = products.iter().map(|(c, _)| *c).collect();
+    let indices: Vec<_> = products.iter().map(|(_, p)| p.clone()).collect();
+    transcript.append_message(b"zerocheck coefficients", &coefficients)?;
+    transcript.append_message(b"zerocheck products", &indices)?;
+    Ok(nv)
+}
+
+/// `eq(tau, x) * sum_j c_j prod_k f_{j,k}(x)` as a virtual polynomial over the committed polynomials
+fn masked_virtual_polynomial<F: Field>(
+    tau: Vec<F>,
+    products: &Products<F>,
+) -> SResult<VirtualPolynomial<F>> {
+    let mut poly = VirtualPolynomial::new(tau.len());
+    let eq = poly.add_public(PublicPolynomial::Eq(tau))?;
+    for (coefficient, product) in products.iter() {
+        let mut multiplicands: Vec<_> = product
+            .iter()
+            .map(|k| Multiplicand::Committed(*k))
+            .collect();
+        multiplicands.push(eq);
+        poly.add_product(*coefficient, multiplicands)?;
+    }
+    Ok(poly)
+}
+
+/// Prove that `sum_j c_j prod_k f_{j,k}` is zero on the boolean hypercube, where `polynomials[k]` is committed
+/// in `commitments[k]`.
+///
+/// return: the proof and the point at which the polynomials are opened
+pub fn prove<E: PairingEngine, H: TranscriptHash>(
+    pp: &PublicParameter<E>,
+    polynomials: Vec<MLExtensionArray<E::Fr>>,
+    commitments: &[Commitment<E>],
+    products: &Products<E::Fr>,
+    transcript: &mut Transcript<H>,
+) -> SResult<(ZerocheckProof<E>, Vec<E::Fr>)> {
+    if polynomials.len() != commitments.len() {
+        return Err(invalid_arg(
+            "should have one commitment for each polynomial",
+        ));
+    }
+    let nv = append_statement(commitments, products, transcript)?;
+    for polynomial in polynomials.iter() {
+        if polynomial.num_variables()? != nv {
+            return Err(invalid_arg("polynomial does not match its commitment"));
+        }
+    }
+    let tau: Vec<E::Fr> = transcript.challenge_scalars(b"tau", nv)?;
+
+    let timer = start_timer!(|| "Prove zerocheck");
+    let poly = masked_virtual_polynomial(tau, products)?;
+    let result = virtual_polynomial::prove(
+        pp,
+        &poly,
+        polynomials,
+        commitments,
+        E::Fr::zero(),
+        transcript,
+    )?;
+    end_timer!(timer);
+    Ok(result)
+}
+
+/// absorb the statement, squeeze tau, and build the masked combination checked by the verifier
+fn verifier_polynomial<E: PairingEngine, H: TranscriptHash>(
+    commitments: &[Commitment<E>],
+    products: &Products<E::Fr>,
+    transcript: &mut Transcript<H>,
+) -> SResult<VirtualPolynomial<E::Fr>> {
+    let nv = append_statement(commitments, products, transcript)?;
+    let tau: Vec<E::Fr> = transcript.challenge_scalars(b"tau", nv)?;
+    masked_virtual_polynomial(tau, products)
+}
+
+/// Verify the zerocheck.
+///
+/// `vp` can be the public `VerifierParameter` or a `DesignatedVerifierParameter`.
+///
+/// return: the point and the evaluations of the polynomials at that point, which are already checked
+pub fn verify<E: PairingEngine, K: OpeningVerifierKey<E>, H: TranscriptHash>(
+    vp: &K,
+    commitments: &[Commitment<E>],
+    products: &Products<E::Fr>,
+    proof: ZerocheckProof<E>,
+    transcript: &mut Transcript<H>,
+) -> SResult<(Vec<E::Fr>, Vec<E::Fr>)> {
+    let poly = verifier_polynomial(commitments, products, transcript)?;
+    virtual_polynomial::verify(vp, &poly, commitments, E::Fr::zero(), proof, transcript)
+}
+
+/// Verify the zerocheck, except for the openings of the polynomials.
+///
+/// return: the point, the evaluations of the polynomials at that point, and their batched opening claim, which
+/// should be checked by the caller, e.g. with `MLPolyCommit::verify_many`
+pub fn verify_deferred<E: PairingEngine, H: TranscriptHash>(
+    commitments: &[Commitment<E>],
+    products: &Products<E::Fr>,
+    proof: ZerocheckProof<E>,
+    transcript: &mut Transcript<H>,
+) -> SResult<(Vec<E::Fr>, Vec<E::Fr>, OpeningClaim<E>)> {
+    let poly = verifier_polynomial(commitments, products, transcript)?;
+    virtual_polynomial::verify_deferred(&poly, commitments, E::Fr::zero(), proof, transcript)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commitment::data_structures::DesignatedVerifierParameter;
+    use crate::commitment::MLPolyCommit;
+    use crate::data_structures::transcript::Transcript;
+    use crate::test_utils::{serialization_round_trip, TestCurve};
+    use crate::zerocheck;
+    use ark_ec::PairingEngine;
+    use ark_ff::{test_rng, One, UniformRand};
+    use linear_sumcheck::data_structures::MLExtensionArray;
+
+    type E = TestCurve;
+    type Fr = <E as PairingEngine>::Fr;
+
+    #[test]
+    fn test_zerocheck() {
+        let mut rng = test_rng();
+        let nv = 5;
+        let (pp, vp, t) = MLPolyCommit::<E>::keygen(nv, &mut rng).unwrap();
+        let dvp = DesignatedVerifierParameter::new(&vp, t);
+        let f: Vec<Fr> = (0..(1 << nv)).map(|_| Fr::rand(&mut rng)).collect();
+        let g: Vec<Fr> = (0..(1 << nv)).map(|_| Fr::rand(&mut rng)).collect();
+        let h: Vec<Fr> = f.iter().zip(g.iter()).map(|(f, g)| *f * g).collect();
+        let check = |tables: Vec<Vec<Fr>>, products: &[(Fr, Vec<usize>)]| {
+            let polynomials: Vec<_> = tables
+                .into_iter()
+                .map(|t| MLExtensionArray::from_vec(t).unwrap())
+                .collect();
+            let commitments: Vec<_> = polynomials
+                .iter()
+                .map(|p| MLPolyCommit::commit(&pp, p.clone()).unwrap())
+                .collect();
+            let mut transcript: Transcript = Transcript::new(b"test");
+            let (proof, _) =
+                zerocheck::prove(&pp, polynomials, &commitments, products, &mut transcript)
+                    .unwrap();
+            let copy = || serialization_round_trip(&proof);
+
+            // the public and the designated verifier agree with the deferred opening
+            let mut transcript: Transcript = Transcript::new(b"test");
+            let public = zerocheck::verify(&vp, &commitments, products, copy(), &mut transcript);
+            let mut transcript: Transcript = Transcript::new(b"test");
+            let designated =
+                zerocheck::verify(&dvp, &commitments, products, copy(), &mut transcript);
+            assert_eq!(public.is_ok(), designated.is_ok());
+            let mut transcript: Transcript = Transcript::new(b"test");
+            let (point, evaluations, claim) =
+                zerocheck::verify_deferred(&commitments, products, copy(), &mut transcript)?;
+            let challenge = transcript.challenge_scalar(b"opening challenge")?;
+            let result = MLPolyCommit::verify_many(&vp, &[claim], challenge)?;
+            assert_eq!(public.is_ok(), result);
+            if let Ok(checked) = public {
+                assert_eq!(checked, (point, evaluations));
+            }
+            Ok::<bool, crate::Error>(result)
+        };
+
+        // f ∘ g = h
+        assert!(check(
+            vec![f.clone(), g.clone(), h.clone()],
+            &zerocheck::hadamard()
+        )
+        .unwrap());
+        let mut wrong = h.clone();
+        wrong[9] += Fr::one();
+        assert!(check(vec![f.clone(), g.clone(), wrong], &zerocheck::hadamard()).is_err());
+
+        // f * g * f - 2 * h * f + f * h = 0
+        let two = Fr::one() + Fr::one();
+        let products = vec![
+            (Fr::one(), vec![0, 1, 0]),
+            (-two, vec![2, 0]),
+            (Fr::one(), vec![0, 2]),
+        ];
+        assert!(check(vec![f.clone(), g.clone(), h.clone()], &products).unwrap());
+        let products = vec![(Fr::one(), vec![0, 1, 0]), (-two, vec![2, 0])];
+        assert!(check(vec![f, g, h], &products).is_err());
+    }
+}